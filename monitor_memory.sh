@@ -4,7 +4,7 @@
 cargo build --release
 
 # Run your Rust program in the background
-./target/release/main build --dir examples/full/Notebook &
+./target/release/oxidian build examples/basic/notebook -o /tmp/oxidian_memory &
 
 # Get the process ID of the Rust program
 PID=$!
@@ -5,10 +5,15 @@ extern crate pretty_env_logger;
 extern crate log;
 
 use clap::{Parser, Subcommand};
-use oxidian::exporting::{config, exporter};
+use oxidian::exporting::{
+    check::ReportFormat,
+    config, exporter,
+    query::{ResultFormat, SearchQuery},
+};
 
 use oxidian::core::errors;
 use oxidian::exporting::load_static::INDEX_TEMPLATE;
+use oxidian::server::{http::DevServer, reload::Reloader};
 
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -46,6 +51,19 @@ enum Commands {
         /// Path to the config file. Uses `[dir]/config.toml` by default.
         #[arg(short, long)]
         cfg: Option<PathBuf>,
+
+        /// The number of threads to build on. Overrides `performance.threads` of the config.
+        #[arg(short = 'j', long)]
+        threads: Option<usize>,
+
+        /// Exit with a non-zero status if there are warnings, like broken links. Errors, like
+        /// notes that can't be read, always do.
+        #[arg(long)]
+        strict: bool,
+
+        /// Publish the notes marked as drafts (`draft: true`), e.g. to preview them locally.
+        #[arg(long)]
+        drafts: bool,
     },
     #[command(arg_required_else_help = true)]
     Watch {
@@ -63,6 +81,45 @@ enum Commands {
         /// Do a full rebuild upon detected file changes.
         #[clap(short, long)]
         full: bool,
+
+        /// The number of threads to build on. Overrides `performance.threads` of the config.
+        #[arg(short = 'j', long)]
+        threads: Option<usize>,
+
+        /// Publish the notes marked as drafts (`draft: true`), e.g. to preview them locally.
+        #[arg(long)]
+        drafts: bool,
+    },
+
+    /// Builds the webpage, serves it locally and reloads open pages on file changes.
+    #[command(arg_required_else_help = true)]
+    Serve {
+        /// The directory containing the notes
+        dir: PathBuf,
+
+        /// The output directory
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+
+        /// Path to the config file. Uses `[dir]/config.toml` by default.
+        #[arg(short, long)]
+        cfg: Option<PathBuf>,
+
+        /// The port to serve the webpage on.
+        #[arg(short, long, default_value_t = 8000)]
+        port: u16,
+
+        /// Do a full rebuild upon detected file changes.
+        #[clap(short, long)]
+        full: bool,
+
+        /// The number of threads to build on. Overrides `performance.threads` of the config.
+        #[arg(short = 'j', long)]
+        threads: Option<usize>,
+
+        /// Publish the notes marked as drafts (`draft: true`), e.g. to preview them locally.
+        #[arg(long)]
+        drafts: bool,
     },
 
     #[command(arg_required_else_help = true)]
@@ -83,6 +140,50 @@ enum Commands {
         #[arg(short, long)]
         cfg: Option<PathBuf>,
     },
+    /// Check the notes for broken links and other problems.
+    ///
+    /// Exits with a non-zero status if errors were found.
+    #[command(arg_required_else_help = true)]
+    Check {
+        /// The directory containing the notes
+        dir: PathBuf,
+
+        /// Path to the config file. Uses `[dir]/config.toml` by default.
+        #[arg(short, long)]
+        cfg: Option<PathBuf>,
+
+        /// The format of the report.
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+    /// Search the notes, like the search bar of the exported notes.
+    ///
+    /// Use `tag:name` in the query to only find notes with that tag.
+    #[command(arg_required_else_help = true)]
+    Search {
+        /// The directory containing the notes
+        dir: PathBuf,
+
+        /// The words to search for, and the tags to filter on.
+        #[arg(required = true)]
+        query: Vec<String>,
+
+        /// Path to the config file. Uses `[dir]/config.toml` by default.
+        #[arg(short, long)]
+        cfg: Option<PathBuf>,
+
+        /// Only search the titles of the notes.
+        #[arg(short, long)]
+        titles: bool,
+
+        /// The maximum number of results.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// The format of the results.
+        #[arg(long, value_enum, default_value_t = ResultFormat::Text)]
+        format: ResultFormat,
+    },
     #[command(arg_required_else_help = true)]
     /// Set up a new, minimal project
     New {
@@ -91,23 +192,26 @@ enum Commands {
     },
 }
 
-//fn serve(port: u32) {
-//    println!("Serving on port {}", port);
-//}
-
 fn main() {
     pretty_env_logger::init();
 
     let args = Cli::parse();
 
     match args.command {
-        Commands::Build { dir, out, cfg } => {
+        Commands::Build {
+            dir,
+            out,
+            cfg,
+            threads,
+            strict,
+            drafts,
+        } => {
             trace!("Running build command.");
             //let index = index.unwrap_or(PathBuf::from(INDEX_FILE));
             //debug!("index file: {:?}", index);
             let out = out.unwrap_or_else(|| default_output_file(&dir));
             debug!("output directory: {:?}", out);
-            build_vault(dir, out, cfg);
+            build_vault(dir, out, cfg, threads, strict, drafts);
         }
 
         Commands::Watch {
@@ -115,12 +219,28 @@ fn main() {
             out,
             cfg,
             full,
+            threads,
+            drafts,
         } => {
             trace!("Running watch command.");
             let out = out.unwrap_or_else(|| default_output_file(&dir));
             //let full = full.unwrap_or(false);
             debug!("output directory: {:?}", out);
-            watch(dir, out, cfg, full);
+            watch(dir, out, cfg, full, threads, drafts);
+        }
+        Commands::Serve {
+            dir,
+            out,
+            cfg,
+            port,
+            full,
+            threads,
+            drafts,
+        } => {
+            trace!("Running serve command.");
+            let out = out.unwrap_or_else(|| default_output_file(&dir));
+            debug!("output directory: {:?}", out);
+            serve(dir, out, cfg, port, full, threads, drafts);
         }
         Commands::Where {
             dir,
@@ -140,6 +260,22 @@ fn main() {
             let output = builder.slugify_path(&file, Some("html")).unwrap();
             println!("{}", output.to_string_lossy());
         }
+        Commands::Check { dir, cfg, format } => {
+            trace!("Running check command.");
+            check_vault(dir, cfg, format);
+        }
+        Commands::Search {
+            dir,
+            query,
+            cfg,
+            titles,
+            limit,
+            format,
+        } => {
+            trace!("Running search command.");
+            let query = SearchQuery::parse(&query.join(" "), titles);
+            search_vault(dir, cfg, &query, limit, format);
+        }
         Commands::New { dir } => {
             trace!("Running new command.");
             create_new_project(&dir);
@@ -185,10 +321,19 @@ fn default_output_file(dir: &Path) -> PathBuf {
     out
 }
 
-fn build_vault(input_dir: PathBuf, output_dir: PathBuf, config_file: Option<PathBuf>) {
+fn build_vault(
+    input_dir: PathBuf,
+    output_dir: PathBuf,
+    config_file: Option<PathBuf>,
+    threads: Option<usize>,
+    strict: bool,
+    drafts: bool,
+) {
     // Prepare
     // --------------------
-    let cfg = load_config(&input_dir, config_file);
+    let mut cfg = load_config(&input_dir, config_file);
+    set_threads(&mut cfg, threads);
+    set_drafts(&mut cfg, drafts);
     let mut builder = setup_exporter(&input_dir, &output_dir, &cfg);
 
     // Do the export
@@ -197,6 +342,42 @@ fn build_vault(input_dir: PathBuf, output_dir: PathBuf, config_file: Option<Path
     // Print outputs
     // ----------------------
     info!("{}", builder.stats);
+    let diagnostics = builder.diagnostics();
+    let summary = diagnostics.summary();
+    if !summary.issues.is_empty() {
+        eprint!("{}", summary.to_text());
+    }
+    if diagnostics.failed(strict) {
+        process::exit(1);
+    }
+}
+
+fn check_vault(input_dir: PathBuf, config_file: Option<PathBuf>, format: ReportFormat) {
+    let cfg = load_config(&input_dir, config_file);
+    // Nothing is written, but the exporter needs an output directory.
+    let output_dir = default_output_file(&input_dir);
+    let mut builder = setup_exporter(&input_dir, &output_dir, &cfg);
+
+    let report = builder.check();
+    print!("{}", report.format(format));
+    if report.has_errors() {
+        process::exit(1);
+    }
+}
+
+fn search_vault(
+    input_dir: PathBuf,
+    config_file: Option<PathBuf>,
+    query: &SearchQuery,
+    limit: usize,
+    format: ResultFormat,
+) {
+    let cfg = load_config(&input_dir, config_file);
+    // Nothing is written, but the exporter needs an output directory.
+    let output_dir = default_output_file(&input_dir);
+    let mut builder = setup_exporter(&input_dir, &output_dir, &cfg);
+
+    print!("{}", builder.search(query, limit).format(format));
 }
 
 fn load_config(input_dir: &Path, config_file: Option<PathBuf>) -> config::ExportConfig {
@@ -205,6 +386,20 @@ fn load_config(input_dir: &Path, config_file: Option<PathBuf>) -> config::Export
     config::ExportConfig::from_file(config_file).unwrap_or_default()
 }
 
+/// Let the thread count given on the command line override the one of the config.
+fn set_threads(cfg: &mut config::ExportConfig, threads: Option<usize>) {
+    if let Some(threads) = threads {
+        cfg.performance.threads = threads;
+    }
+}
+
+/// Include the drafts if asked for on the command line, even if the config doesn't.
+fn set_drafts(cfg: &mut config::ExportConfig, drafts: bool) {
+    if drafts {
+        cfg.include_drafts = true;
+    }
+}
+
 fn setup_exporter<'a>(
     input_dir: &'a Path,
     output_dir: &'a Path,
@@ -220,15 +415,63 @@ fn setup_exporter<'a>(
     exporter::Exporter::new(input_dir, output_dir, &cfg)
 }
 
-fn watch(input_dir: PathBuf, output_dir: PathBuf, config_file: Option<PathBuf>, full: bool) {
-    use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
-    let (tx, rx) = std::sync::mpsc::channel();
+fn watch(
+    input_dir: PathBuf,
+    output_dir: PathBuf,
+    config_file: Option<PathBuf>,
+    full: bool,
+    threads: Option<usize>,
+    drafts: bool,
+) {
+    // Prepare
+    // --------------------
+    let mut cfg = load_config(&input_dir, config_file);
+    set_threads(&mut cfg, threads);
+    set_drafts(&mut cfg, drafts);
+    let mut builder = setup_exporter(&input_dir, &output_dir, &cfg);
 
+    watch_for_changes(&mut builder, &input_dir, full, || {});
+}
+
+fn serve(
+    input_dir: PathBuf,
+    output_dir: PathBuf,
+    config_file: Option<PathBuf>,
+    port: u16,
+    full: bool,
+    threads: Option<usize>,
+    drafts: bool,
+) {
     // Prepare
     // --------------------
-    let cfg = load_config(&input_dir, config_file);
+    let mut cfg = load_config(&input_dir, config_file);
+    set_threads(&mut cfg, threads);
+    set_drafts(&mut cfg, drafts);
     let mut builder = setup_exporter(&input_dir, &output_dir, &cfg);
 
+    let reloader = Reloader::new();
+    let server = DevServer::new(&output_dir, cfg.root_path.as_deref(), reloader.clone());
+    let url = server.url(port);
+    if let Err(e) = server.start(port) {
+        log::error!("Error: {}", e);
+        process::exit(1);
+    }
+    println!("Serving the notes at {}", url);
+
+    watch_for_changes(&mut builder, &input_dir, full, || reloader.notify());
+}
+
+/// Run an initial build and recompile the notes whenever files in `input_dir` change.
+/// `on_rebuild` is called after every recompilation.
+fn watch_for_changes<F: Fn()>(
+    builder: &mut exporter::Exporter,
+    input_dir: &Path,
+    full: bool,
+    on_rebuild: F,
+) {
+    use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+    let (tx, rx) = std::sync::mpsc::channel();
+
     //todo store cache files to allow a true incremental build.
     info!("Running initial build.");
     let mut backlinks = builder.export();
@@ -245,13 +488,18 @@ fn watch(input_dir: PathBuf, output_dir: PathBuf, config_file: Option<PathBuf>,
 
     // Add a path to be watched. All files and directories at that path and
     // below will be monitored for changes.
-    watcher.watch(&input_dir, RecursiveMode::Recursive).unwrap();
-
-    for res in rx {
-        match res {
-            Ok(event) => builder.handle_event(event, &mut backlinks, full),
-            Err(error) => log::error!("Error: {error:?}"),
+    watcher.watch(input_dir, RecursiveMode::Recursive).unwrap();
+
+    while let Ok(res) = rx.recv() {
+        // A single save often triggers several events. Handle all of them before
+        // signalling the rebuild.
+        for res in std::iter::once(res).chain(rx.try_iter()) {
+            match res {
+                Ok(event) => builder.handle_event(event, &mut backlinks, full),
+                Err(error) => log::error!("Error: {error:?}"),
+            }
         }
+        on_rebuild();
     }
 }
 
@@ -1,12 +1,14 @@
 use std::collections::BTreeMap;
 use std::path::Path;
-use log::info;
+use log::{debug, info};
 
 use chrono::Datelike;
 
 use crate::components::link::Link;
 use super::note::Note;
+use crate::core::errors::TemplateError;
 use crate::core::html::{self, HtmlTag};
+use crate::core::template::{PageContext, PageTemplate, ARCHIVE_LAYOUT};
 use crate::utils::utils;
 use crate::utils::constants::MONTHS;
 
@@ -37,13 +39,14 @@ fn make_collapsible(header: &str, collapsed: &str, count: Option<usize>, level:
 }
 
 
+///The entry of a note in the archive. Notes without a creation date are listed without one.
 fn render_note_entry(note: &Note, input_dir: &Path, tag_dir: &Path) -> String {
-    let date_str = note.get_creation_date()
-        .unwrap()
-        .format(r"%d/%m/%y");
-    let date_html = HtmlTag::span()
-        .with_class("date-annot")
-        .wrap(date_str);
+    let date_html = match note.get_creation_date() {
+        Some(date) => HtmlTag::span()
+            .with_class("date-annot")
+            .wrap(date.format(r"%d/%m/%y")),
+        None => String::new(),
+    };
 
     let note_link = Link::from_note(note)
                         .set_relative(input_dir)
@@ -82,17 +85,22 @@ fn build_month<'a>( name: &str, notes: &mut Vec<&'a Note<'a>>, input_dir: &Path,
 
 
 pub fn generate_archive_page_html<'a>(
-    notes: &Vec<Note<'a>>, input_dir: &Path, tag_dir: &Path, template: &str) -> String {
+    notes: &Vec<Note<'a>>, input_dir: &Path, tag_dir: &Path, template: &PageTemplate)
+    -> Result<String, TemplateError> {
 
     let title = "Archive".to_string();
     let mut html_body = html::HtmlTag::header(1).wrap(&title);
 
     let mut calendar: Calendar = BTreeMap::new();
+    let mut undated: Vec<&Note> = vec![];
 
     info!("Building calendar based on notes");
     for note in notes {
-        let date_created = note.get_creation_date()
-            .expect(&format!("Couldn't get creation date of note {}", note.title)); 
+        let Some(date_created) = note.get_creation_date() else {
+            debug!("Note {} has no creation date, so it is listed as undated.", note.title);
+            undated.push(note);
+            continue;
+        };
 
         calendar.entry(date_created.year())  // Get the months at the given year
             .or_insert_with(BTreeMap::new)   // If no entries at this year, make a new hashmap
@@ -125,9 +133,56 @@ pub fn generate_archive_page_html<'a>(
         html_body.push_str(&collapsible_year);
     }
 
-    template
-        .replace("{{date}}", "")
-        .replace("{{title}}", &title)
-        .replace("{{backlinks}}", "")
-        .replace("{{content}}", &html_body)
+    if !undated.is_empty() {
+        undated.sort_by(|a, b| a.title.cmp(&b.title));
+        let links = undated.iter()
+                    .map(|note| HtmlTag::li().wrap(render_note_entry(note, input_dir, tag_dir)))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+        let ul = HtmlTag::div().with_class("article-list")
+                    .wrap(HtmlTag::ul().wrap(&links));
+        html_body.push('\n');
+        html_body.push_str(&make_collapsible("Undated", &ul, Some(undated.len()), 1));
+    }
+
+    template.render_layout(ARCHIVE_LAYOUT, &PageContext {
+        title,
+        content: html_body,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate_archive_page_html;
+    use crate::components::note::Note;
+    use crate::core::template::PageTemplate;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    #[test]
+    fn notes_without_date_are_listed_as_undated() {
+        let dir = tempdir().unwrap();
+        let input = dir.path();
+        let content = "---\ndate_created: 2024-03-05\n---\nText";
+        let note = |name: &str, content: &str| {
+            Note::from_content(input.join(name), content.into(), input, false, &vec![])
+        };
+        let mut dated = note("dated.md", content);
+        // The file does not exist, so there is no date to fall back on.
+        let mut undated = note("undated.md", "Text");
+        dated.cache_creation_time(false);
+        undated.cache_creation_time(false);
+        assert!(undated.get_creation_date().is_none());
+
+        let template = PageTemplate::new("{{content}}").unwrap();
+        let notes = vec![dated, undated];
+        let html = generate_archive_page_html(&notes, input, Path::new("tags"), &template).unwrap();
+        let year = html.find("2024").unwrap();
+        let undated_section = html.find("Undated").unwrap();
+        assert!(year < undated_section);
+        assert!(html[..undated_section].contains("05/03/24"));
+        assert!(html[undated_section..].contains("undated"));
+        assert!(!html[undated_section..].contains("date-annot"));
+    }
 }
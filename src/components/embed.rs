@@ -0,0 +1,153 @@
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+
+use super::frontmatter::extract_yaml_frontmatter;
+use super::link::Link;
+use super::note::Note;
+use crate::core::html::HtmlTag;
+use crate::exporting::math::MathRenderer;
+use crate::obsidian::{comments, embeds};
+use crate::utils::constants::MAX_EMBED_DEPTH;
+use crate::utils::filesys::{Aliases, Unpublished};
+use crate::utils::utils::{self, read_file_to_str};
+
+///Everything needed to load and render notes that are embedded in other notes (`![[note]]`).
+///
+///Keeps track of the chain of notes that is currently being rendered, so a note that
+///(indirectly) embeds itself is detected instead of recursing forever.
+pub struct EmbedContext<'c> {
+    base_dir: &'c Path,
+    search_links: bool,
+    ignore: &'c Vec<PathBuf>,
+    aliases: &'c Aliases,
+    unpublished: &'c Unpublished,
+    math: Option<&'c MathRenderer>,
+    stack: Vec<PathBuf>,
+}
+
+impl<'c> EmbedContext<'c> {
+    pub fn new(
+        base_dir: &'c Path,
+        search_links: bool,
+        ignore: &'c Vec<PathBuf>,
+        aliases: &'c Aliases,
+        unpublished: &'c Unpublished,
+    ) -> Self {
+        EmbedContext {
+            base_dir,
+            search_links,
+            ignore,
+            aliases,
+            unpublished,
+            math: None,
+            stack: vec![],
+        }
+    }
+
+    /// Render the math of the notes with `math`, instead of leaving it to the browser.
+    pub fn with_math(mut self, math: Option<&'c MathRenderer>) -> Self {
+        self.math = math;
+        self
+    }
+
+    pub fn math(&self) -> Option<&'c MathRenderer> {
+        self.math
+    }
+
+    fn normalize(path: &Path) -> PathBuf {
+        path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    /// Mark the note at `path` as being rendered.
+    pub fn enter(&mut self, path: &Path) {
+        self.stack.push(Self::normalize(path));
+    }
+
+    /// Mark the last entered note as rendered.
+    pub fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    fn target_path(&self, link: &Link) -> PathBuf {
+        let target = link.target.strip_prefix("/").unwrap_or(&link.target);
+        self.base_dir.join(target)
+    }
+
+    /// Load the (part of the) note that `link` refers to.
+    fn load(&self, path: PathBuf, subtarget: Option<&str>) -> Option<Note<'static>> {
+        let content = match read_file_to_str(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Could not read embedded note {:?}. Got error {}", path, e);
+                return None;
+            }
+        };
+
+        let content = match subtarget {
+            Some(subtarget) => {
+                let body = match extract_yaml_frontmatter(&content) {
+                    Some(fm) => utils::remove_first_n_lines(&content, fm.lines().count() + 2),
+                    None => content,
+                };
+                match embeds::extract_subtarget(&comments::strip_comments(&body), subtarget) {
+                    Some(section) => section,
+                    None => {
+                        warn!("Could not find `{}` in embedded note {:?}", subtarget, path);
+                        return None;
+                    }
+                }
+            }
+            None => content,
+        };
+        let mut note = Note::from_content(
+            path,
+            content,
+            self.base_dir,
+            self.search_links,
+            self.ignore,
+        );
+        note.resolve_aliases(self.aliases);
+        note.hide_unpublished(self.unpublished);
+        Some(note)
+    }
+
+    fn notice(link: &Link, reason: &str) -> String {
+        HtmlTag::div()
+            .with_class("embed")
+            .with_class("embed-unavailable")
+            .wrap(format!("{} ({})", link.to_html(), reason))
+    }
+
+    /// Render the note that is embedded by `link` to html.
+    ///
+    /// If the note cannot be embedded, a link to it is rendered instead.
+    pub fn render(&mut self, link: &Link) -> String {
+        if link.broken {
+            return Self::notice(link, "not found");
+        }
+        let path = self.target_path(link);
+        if self.stack.contains(&Self::normalize(&path)) {
+            warn!("Note {:?} embeds itself. Not embedding it again.", path);
+            return Self::notice(link, "recursive embed");
+        }
+        if self.stack.len() > MAX_EMBED_DEPTH {
+            warn!("Maximum embedding depth reached at {:?}.", path);
+            return Self::notice(link, "too deeply nested");
+        }
+
+        debug!("Embedding {:?}", path);
+        let note = match self.load(path, link.subtarget.as_deref()) {
+            Some(note) => note,
+            None => {
+                return Self::notice(link, "not found");
+            }
+        };
+        let body = note.render_body(self);
+        HtmlTag::div().with_class("embed").wrap(
+            HtmlTag::div()
+                .with_class("embed-title")
+                .wrap(link.to_html())
+                + &body,
+        )
+    }
+}
@@ -1,5 +1,7 @@
 use yaml_rust::{YamlLoader, Yaml, ScanError};
 
+use crate::exporting::wrap_pulldown_cmark::text_to_html;
+
 
 pub fn extract_yaml_frontmatter(content: & str) -> Option<String> {
     let mut lines = content.lines(); 
@@ -20,5 +22,117 @@ pub fn extract_yaml_frontmatter(content: & str) -> Option<String> {
 
 
 pub fn parse_frontmatter(string_rep: &str) -> Result<Yaml, ScanError> {
-    YamlLoader::load_from_str(string_rep).and_then(|out| Ok(out[0].clone()))
+    // Empty frontmatter has no documents.
+    YamlLoader::load_from_str(string_rep)
+        .map(|out| out.into_iter().next().unwrap_or(Yaml::Null))
+}
+
+/// The message of a parsing error, without its position in the frontmatter.
+pub fn error_message(error: &ScanError) -> String {
+    let message = error.to_string();
+    let marker = error.marker();
+    let position = format!(" at line {} column {}", marker.line(), marker.col() + 1);
+    message.strip_suffix(&position).unwrap_or(&message).to_string()
+}
+
+fn scalar_to_string(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(string) | Yaml::Real(string) => Some(string.clone()),
+        Yaml::Integer(int) => Some(int.to_string()),
+        Yaml::Boolean(boolean) => Some(boolean.to_string()),
+        _ => None,
+    }
+}
+
+///Read a frontmatter value that is either a list or a comma-separated string, like `tags` or
+///`aliases`.
+pub fn string_list(value: &Yaml) -> Vec<String> {
+    let items: Vec<String> = match value {
+        Yaml::Array(items) => items.iter().filter_map(scalar_to_string).collect(),
+        Yaml::String(string) => string.split(',').map(str::to_string).collect(),
+        other => scalar_to_string(other).into_iter().collect(),
+    };
+    items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+///Convert parsed yaml to json, e.g. to pass the frontmatter to templates.
+pub fn yaml_to_json(yaml: &Yaml) -> serde_json::Value {
+    use serde_json::Value;
+    match yaml {
+        Yaml::Real(real) => real
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or_else(|| Value::String(real.clone()), Value::Number),
+        Yaml::Integer(int) => Value::from(*int),
+        Yaml::String(string) => Value::String(string.clone()),
+        Yaml::Boolean(boolean) => Value::Bool(*boolean),
+        Yaml::Array(items) => Value::Array(items.iter().map(yaml_to_json).collect()),
+        Yaml::Hash(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let key = match yaml_to_json(key) {
+                        Value::String(string) => string,
+                        other => other.to_string(),
+                    };
+                    (key, yaml_to_json(value))
+                })
+                .collect(),
+        ),
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Value::Null,
+    }
+}
+
+///`value` with the html special characters in its strings escaped, so templates can insert it
+///as it is. The keys are kept, since templates look the values up by them.
+pub fn escape_strings(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        Value::String(string) => Value::String(text_to_html(&string)),
+        Value::Array(items) => Value::Array(items.into_iter().map(escape_strings).collect()),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, escape_strings(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{error_message, escape_strings, parse_frontmatter, string_list, yaml_to_json};
+
+    #[test]
+    fn list_or_comma_separated() {
+        let frontmatter =
+            parse_frontmatter("tags: [a, b/c]\naliases: First, Second \nyear: 2024").unwrap();
+        assert_eq!(string_list(&frontmatter["tags"]), vec!["a", "b/c"]);
+        assert_eq!(string_list(&frontmatter["aliases"]), vec!["First", "Second"]);
+        assert_eq!(string_list(&frontmatter["year"]), vec!["2024"]);
+        assert!(string_list(&frontmatter["missing"]).is_empty());
+    }
+
+    #[test]
+    fn empty_and_invalid() {
+        assert!(parse_frontmatter("").unwrap()["title"].is_badvalue());
+        let error = parse_frontmatter("title: a\n  tags: [b\n").unwrap_err();
+        assert_eq!(error.marker().line(), 2);
+        assert_eq!(error_message(&error), "mapping values are not allowed in this context");
+    }
+
+    #[test]
+    fn strings_are_escaped_for_templates() {
+        let frontmatter =
+            parse_frontmatter("author: <script>x</script>\nmeta:\n  tags: [a&b]\n  year: 2024")
+                .unwrap();
+        let escaped = escape_strings(yaml_to_json(&frontmatter));
+        assert_eq!(escaped["author"], "&lt;script&gt;x&lt;/script&gt;");
+        assert_eq!(escaped["meta"]["tags"][0], "a&amp;b");
+        assert_eq!(escaped["meta"]["year"], 2024);
+    }
 }
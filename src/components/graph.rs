@@ -0,0 +1,394 @@
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::link::{Link, LinkType};
+use super::note::Note;
+use crate::core::errors::TemplateError;
+use crate::core::html::HtmlTag;
+use crate::core::template::{PageContext, PageTemplate, GRAPH_LAYOUT};
+use crate::utils::filesys::{relative_to, slugify_path};
+use crate::utils::utils::{format_tag_path, prepend_slash};
+
+///Prefix of the ids of tag nodes, so they can't clash with the paths of notes.
+const TAG_PREFIX: &str = "tag:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Note,
+    Tag,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphNode {
+    ///Path of the note relative to the notes directory, or `tag:<tag>` for tags.
+    pub id: String,
+    pub kind: NodeKind,
+    pub title: String,
+    ///Url of the page of the note or tag, relative to the root of the website.
+    pub url: String,
+    pub tags: Vec<String>,
+    ///Creation date in the form `YYYY-MM-DD`.
+    pub date: Option<String>,
+    ///Whether this is the note that the (local) graph is shown on.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+}
+
+///The graph of links between the notes, as shown on the graph page.
+///
+///The nodes are the notes and, optionally, the tags. A note is connected to the notes it links
+///to or embeds, and to its tags.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+fn note_id(path: &Path, input_dir: &Path) -> String {
+    prepend_slash(relative_to(path, input_dir))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn note_url(id: &str) -> String {
+    slugify_path(Path::new(id), Some("html"))
+        .unwrap_or_else(|_| PathBuf::from(id))
+        .to_string_lossy()
+        .to_string()
+}
+
+impl Graph {
+    ///Construct the graph of the given notes. Links to notes that are not among `notes` are left
+    ///out.
+    ///
+    ///The creation dates of the notes are only included if they were cached.
+    pub fn from_notes(notes: &[Note], input_dir: &Path, include_tags: bool) -> Self {
+        let mut nodes: BTreeMap<String, GraphNode> = BTreeMap::new();
+        for note in notes {
+            let id = note_id(&note.path, input_dir);
+            let mut tags: Vec<String> = note.tags.iter().map(|tag| tag.tag_path.clone()).collect();
+            tags.sort();
+            tags.dedup();
+            nodes.insert(
+                id.clone(),
+                GraphNode {
+                    url: note_url(&id),
+                    id,
+                    kind: NodeKind::Note,
+                    title: note.title.clone(),
+                    tags,
+                    date: note
+                        .get_creation_date()
+                        .map(|date| date.format("%Y-%m-%d").to_string()),
+                    current: false,
+                },
+            );
+        }
+
+        let mut edges: BTreeSet<GraphEdge> = BTreeSet::new();
+        let mut tag_nodes: BTreeMap<String, GraphNode> = BTreeMap::new();
+        for note in notes {
+            let source = note_id(&note.path, input_dir);
+            for link in note.links.iter().filter(|link| {
+                !link.broken
+                    && !link.unpublished
+                    && matches!(link.link_type(), LinkType::Note | LinkType::Embed)
+            }) {
+                let target = link
+                    .target
+                    .with_extension("md")
+                    .to_string_lossy()
+                    .to_string();
+                if target != source && nodes.contains_key(&target) {
+                    edges.insert(GraphEdge {
+                        source: source.clone(),
+                        target,
+                    });
+                }
+            }
+
+            if !include_tags {
+                continue;
+            }
+            for tag in &note.tags {
+                let id = format!("{}{}", TAG_PREFIX, tag.tag_path);
+                tag_nodes.entry(id.clone()).or_insert_with(|| GraphNode {
+                    id: id.clone(),
+                    kind: NodeKind::Tag,
+                    title: format!("#{}", tag.tag_path),
+                    url: format_tag_path(tag).trim_start_matches('/').to_string(),
+                    tags: vec![],
+                    date: None,
+                    current: false,
+                });
+                edges.insert(GraphEdge {
+                    source: source.clone(),
+                    target: id,
+                });
+            }
+        }
+        nodes.extend(tag_nodes);
+
+        Graph {
+            nodes: nodes.into_values().collect(),
+            edges: edges.into_iter().collect(),
+        }
+    }
+
+    ///Html of a local graph, shown on the page of a note, which draws it with the script at
+    ///`script_url`.
+    pub fn to_local_html(&self, script_url: &str) -> String {
+        // The json is placed in a script tag, so it may not close it.
+        let json = serde_json::to_string(self)
+            .expect("Serialization of graph failed.")
+            .replace("</", "<\\/");
+        HtmlTag::div().with_class("local-graph").wrap(format!(
+            "<script type=\"application/json\">{}</script>",
+            json
+        )) + &format!(
+            "\n<script src=\"{}\" type=\"text/javascript\"></script>",
+            script_url
+        )
+    }
+}
+
+///The links between the notes in both directions, as found from the backlinks of all notes.
+///
+///Used to find the neighbourhood of a note for its local graph.
+#[derive(Debug, Default)]
+pub struct LinkMap {
+    titles: HashMap<String, String>,
+    outgoing: HashMap<String, BTreeSet<String>>,
+    incoming: HashMap<String, BTreeSet<String>>,
+}
+
+impl LinkMap {
+    ///Construct the links from the backlinks of all notes, which map the path of a note to links
+    ///to the notes that refer to it.
+    pub fn from_backlinks(backlinks: &HashMap<PathBuf, HashSet<Link>>) -> Self {
+        let mut map = LinkMap::default();
+        for (target, referring) in backlinks {
+            let target = target.to_string_lossy().to_string();
+            for link in referring {
+                let source = link.target.to_string_lossy().to_string();
+                if source == target {
+                    continue;
+                }
+                map.titles.insert(source.clone(), link.link_text());
+                map.outgoing
+                    .entry(source.clone())
+                    .or_default()
+                    .insert(target.clone());
+                map.incoming
+                    .entry(target.clone())
+                    .or_default()
+                    .insert(source);
+            }
+        }
+        map
+    }
+
+    ///The notes within `depth` links of the note `id`, following links in both directions.
+    ///Includes the note itself.
+    pub fn neighbourhood(&self, id: &str, depth: usize) -> BTreeSet<String> {
+        let mut found = BTreeSet::from([id.to_string()]);
+        let mut frontier = vec![id.to_string()];
+        for _ in 0..depth {
+            let mut next = vec![];
+            for node in &frontier {
+                let neighbours = self
+                    .outgoing
+                    .get(node)
+                    .into_iter()
+                    .chain(self.incoming.get(node))
+                    .flatten();
+                for neighbour in neighbours {
+                    if found.insert(neighbour.clone()) {
+                        next.push(neighbour.clone());
+                    }
+                }
+            }
+            frontier = next;
+        }
+        found
+    }
+
+    ///The graph of the notes within `depth` links of `note`.
+    pub fn local_graph(&self, note: &Note, input_dir: &Path, depth: usize) -> Graph {
+        let id = note_id(&note.path, input_dir);
+        let members = self.neighbourhood(&id, depth);
+        let nodes = members
+            .iter()
+            .map(|member| {
+                let current = *member == id;
+                let title = match (current, self.titles.get(member)) {
+                    (true, _) => note.title.clone(),
+                    (false, Some(title)) => title.clone(),
+                    (false, None) => Path::new(member)
+                        .file_stem()
+                        .map_or_else(|| member.clone(), |s| s.to_string_lossy().to_string()),
+                };
+                GraphNode {
+                    id: member.clone(),
+                    kind: NodeKind::Note,
+                    title,
+                    url: note_url(member),
+                    tags: vec![],
+                    date: None,
+                    current,
+                }
+            })
+            .collect();
+        let edges = members
+            .iter()
+            .flat_map(|source| {
+                self.outgoing
+                    .get(source)
+                    .into_iter()
+                    .flatten()
+                    .filter(|target| members.contains(*target))
+                    .map(|target| GraphEdge {
+                        source: source.clone(),
+                        target: target.clone(),
+                    })
+            })
+            .collect();
+        Graph { nodes, edges }
+    }
+}
+
+///Render the graph page, which shows the graph stored as json at `data_url` with the script at
+///`script_url`.
+pub fn generate_graph_page_html(
+    data_url: &str,
+    script_url: &str,
+    template: &PageTemplate,
+) -> Result<String, TemplateError> {
+    let title = "Graph".to_string();
+    let content = HtmlTag::header(1).wrap(&title)
+        + &format!(
+            "<div id=\"graph\" class=\"graph\" data-graph-src=\"{}\"></div>\n",
+            data_url
+        )
+        + &format!(
+            "<script src=\"{}\" type=\"text/javascript\"></script>",
+            script_url
+        );
+    template.render_layout(
+        GRAPH_LAYOUT,
+        &PageContext {
+            title,
+            content,
+            ..Default::default()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Graph, GraphEdge, LinkMap, NodeKind};
+    use crate::components::link::Link;
+    use crate::components::note::Note;
+    use std::collections::{HashMap, HashSet};
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn edge(source: &str, target: &str) -> GraphEdge {
+        GraphEdge {
+            source: source.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn graph_of_notes() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().canonicalize().unwrap();
+        std::fs::write(
+            input.join("a.md"),
+            "[[b]], [[b#Heading]], [[a]] and [[c]] #topic",
+        )
+        .unwrap();
+        std::fs::write(input.join("b.md"), "# Heading\n#topic/sub").unwrap();
+        let notes: Vec<Note> = ["a.md", "b.md"]
+            .iter()
+            .map(|name| Note::new(input.join(name), &input, false, &vec![]).unwrap())
+            .collect();
+
+        let graph = Graph::from_notes(&notes, &input, false);
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.nodes[0].url, "a.html");
+        assert_eq!(graph.nodes[0].tags, vec!["topic"]);
+        assert_eq!(graph.edges, vec![edge("a.md", "b.md")]);
+
+        let graph = Graph::from_notes(&notes, &input, true);
+        let tags: Vec<&str> = graph
+            .nodes
+            .iter()
+            .filter(|node| node.kind == NodeKind::Tag)
+            .map(|node| node.id.as_str())
+            .collect();
+        assert_eq!(tags, vec!["tag:topic", "tag:topic/sub"]);
+        assert_eq!(
+            graph.edges,
+            vec![
+                edge("a.md", "b.md"),
+                edge("a.md", "tag:topic"),
+                edge("b.md", "tag:topic/sub")
+            ]
+        );
+    }
+
+    #[test]
+    fn local_graph() {
+        // a -> b -> c -> d, and e -> b
+        let mut backlinks: HashMap<PathBuf, HashSet<Link>> = HashMap::new();
+        for (source, target) in [("a", "b"), ("b", "c"), ("c", "d"), ("e", "b")] {
+            backlinks
+                .entry(PathBuf::from(format!("{}.md", target)))
+                .or_default()
+                .insert(Link::new(source.to_uppercase(), format!("{}.md", source)));
+        }
+        let links = LinkMap::from_backlinks(&backlinks);
+
+        let ids =
+            |depth| -> Vec<String> { links.neighbourhood("b.md", depth).into_iter().collect() };
+        assert_eq!(ids(0), vec!["b.md"]);
+        assert_eq!(ids(1), vec!["a.md", "b.md", "c.md", "e.md"]);
+        assert_eq!(ids(2), vec!["a.md", "b.md", "c.md", "d.md", "e.md"]);
+
+        let dir = tempdir().unwrap();
+        let input = dir.path().canonicalize().unwrap();
+        std::fs::write(input.join("b.md"), "[[c]]").unwrap();
+        let note = Note::new(input.join("b.md"), &input, false, &vec![]).unwrap();
+        let graph = links.local_graph(&note, &input, 1);
+        let titles: Vec<(&str, bool)> = graph
+            .nodes
+            .iter()
+            .map(|node| (node.title.as_str(), node.current))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![("A", false), ("b", true), ("C", false), ("E", false)]
+        );
+        assert_eq!(
+            graph.edges,
+            vec![
+                edge("a.md", "b.md"),
+                edge("b.md", "c.md"),
+                edge("e.md", "b.md")
+            ]
+        );
+        let html = graph.to_local_html("assets/js/graph.js");
+        assert!(html.contains("\"current\":true"));
+        assert!(html.contains("<script src=\"assets/js/graph.js\""));
+    }
+}
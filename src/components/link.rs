@@ -44,6 +44,8 @@ pub struct Link {
     pub source_string: String,
     pub is_attachment: bool,
     pub broken: bool,
+    ///Whether the link points to a note that is not published, so it is shown as plain text.
+    pub unpublished: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -51,6 +53,8 @@ pub enum LinkType {
     External,
     Note,
     Internal,
+    /// A note that is embedded in another note: `![[note]]`.
+    Embed,
     Attachment(FileType),
 }
 
@@ -95,8 +99,16 @@ fn attachment_type_from_file(file: &Path) -> FileType {
     }
 }
 
+fn is_markdown_file(file: &Path) -> bool {
+    file.extension()
+        .is_some_and(|ext| ext.to_string_lossy().to_lowercase() == "md")
+}
+
 impl Link {
     pub fn link_type(&self) -> LinkType {
+        if self.is_attachment && is_markdown_file(&self.target) {
+            return LinkType::Embed;
+        }
         if self.is_attachment {
             let attach_type = attachment_type_from_file(&self.target);
             return LinkType::Attachment(attach_type);
@@ -149,6 +161,7 @@ impl Link {
             source_string: "".to_string(),
             is_attachment: false,
             broken: false,
+            unpublished: false,
         }
     }
 
@@ -163,6 +176,7 @@ impl Link {
             source_string,
             is_attachment: false,
             broken: false,
+            unpublished: false,
         }
     }
 
@@ -174,6 +188,7 @@ impl Link {
             source_string: "".to_string(),
             is_attachment: false,
             broken: false,
+            unpublished: false,
         }
     }
 
@@ -188,7 +203,7 @@ impl Link {
                         String::from(".")
                     }
                 }
-                LinkType::Note => {
+                LinkType::Note | LinkType::Embed => {
                     if let Some(filename) = self.target.with_extension("").file_name() {
                         filename.to_string_lossy().to_string()
                     } else {
@@ -224,18 +239,20 @@ impl Link {
             source_string: md_link.into(),
             is_attachment,
             broken: false,
+            unpublished: false,
         }
     }
 
     fn needs_md_ext(path: &str, is_attachment: bool) -> bool {
-        if is_attachment {
-            return false;
-        }
         if path.len() == 0 {
             return false;
         } // Internal link
 
         let target_path = Path::new(path);
+        // Embedded notes are given without extension, other attachments always have one.
+        if is_attachment {
+            return target_path.extension().is_none();
+        }
         // Add markdown extension for notes.
         if let Some(ext) = target_path.extension() {
             if !NOTE_EXT.iter().any(|note_ext| OsStr::from(note_ext) == ext) {
@@ -297,6 +314,7 @@ impl Link {
             is_attachment,
             source_string: source_str,
             broken: false,
+            unpublished: false,
         })
     }
 }
@@ -322,6 +340,7 @@ impl<'a> From<Note<'a>> for Link {
             source_string: "".to_string(),
             is_attachment: false,
             broken: false,
+            unpublished: false,
         }
     }
 }
@@ -341,6 +360,7 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
@@ -356,6 +376,7 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
@@ -372,6 +393,7 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
@@ -387,6 +409,7 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
@@ -402,6 +425,7 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
@@ -417,6 +441,7 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
@@ -432,6 +457,7 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
@@ -447,6 +473,7 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
@@ -461,9 +488,26 @@ mod tests {
             is_attachment: false,
             source_string: format!("[[{}]]", test_string).to_string(),
             broken: false,
+            unpublished: false,
         };
         let got_link = Link::from_obsidian_link(test_string, false).unwrap();
         assert_eq!(expected_link, got_link);
     }
+
+    #[test]
+    fn test_from_obsidian_embed() {
+        let got_link = Link::from_obsidian_link("other note#Heading", true).unwrap();
+        assert_eq!(got_link.target, PathBuf::from("other note.md"));
+        assert_eq!(got_link.subtarget, Some("Heading".to_string()));
+        assert_eq!(got_link.source_string, "![[other note#Heading]]");
+        assert_eq!(got_link.link_type(), LinkType::Embed);
+    }
+
+    #[test]
+    fn test_from_obsidian_attachment_is_not_embed() {
+        let got_link = Link::from_obsidian_link("figure.png|300", true).unwrap();
+        assert_eq!(got_link.target, PathBuf::from("figure.png"));
+        assert_ne!(got_link.link_type(), LinkType::Embed);
+    }
     // More tests...
 }
@@ -1,7 +1,10 @@
 pub mod archive; 
+pub mod embed;
 pub mod frontmatter;
+pub mod graph;
 pub mod link;
 pub mod note;
+pub mod properties;
 pub mod tag_tree;
 //pub mod notebook;
 mod errors;
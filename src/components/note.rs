@@ -1,24 +1,29 @@
 use chrono::{Datelike, NaiveDate};
-use log::{debug, info};
+use log::{debug, info, warn};
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::{self, Error, Write};
 use std::path::{Path, PathBuf};
 
 //use super::formatting::link_to_md;
-use super::frontmatter::{extract_yaml_frontmatter, parse_frontmatter};
+use super::embed::EmbedContext;
+use super::frontmatter::{
+    escape_strings, extract_yaml_frontmatter, parse_frontmatter, string_list, yaml_to_json,
+};
+use super::properties;
 use crate::components::link::{Link, LinkType};
 use crate::core::html;
-use crate::core::sanitization::Sanitization;
-use crate::obsidian::raw_html;
-use crate::obsidian::{
-    admonitions, headers::HeaderParser, highlights::replace_obs_highlights, labels, links, tags,
+use crate::exporting::wrap_pulldown_cmark::{
+    self, text_to_html, text_with_math_to_html, Element, MarkdownParser, ObsidianMarkdown,
 };
+use crate::core::template::{LinkContext, NoteContext, PageContext, PageTemplate, DEFAULT_LAYOUT};
+use crate::obsidian::{comments, highlights, links, tags};
 use crate::utils::{
-    filesys, formatting, placeholders,
-    utils::{self, markdown_to_html, read_file_to_str},
+    filesys, formatting,
+    utils::{self, read_file_to_str},
 };
-use yaml_rust::Yaml;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use yaml_rust::{ScanError, Yaml};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -28,10 +33,11 @@ pub struct Note<'a> {
     pub frontmatter: Option<Yaml>,
     pub tags: Vec<tags::Tag>,
     pub content: String,
-    placeholders: Vec<Sanitization>,
     pub title: String,
     pub backlinks: HashSet<&'a Link>,
     creation_date: Option<NaiveDate>,
+    local_graph: String,
+    frontmatter_error: Option<ScanError>,
 }
 
 impl<'a> AsRef<Note<'a>> for Note<'a> {
@@ -72,38 +78,6 @@ impl<'a> Note<'a> {
         prefix + base_title
     }
 
-    fn process_links(&self, mut content: String) -> String {
-        for link in &self.links {
-            let link_html = formatting::link_to_html(link);
-            debug!("Link {:?} rendered as {}.", link.alias, link_html);
-            content = content.replace(&link.source_string, &link_html);
-        }
-        content
-    }
-
-    fn process_tags(&self, mut content: String) -> String {
-        for tag in &self.tags {
-            content = content.replace(&tag.source, &formatting::tag_to_md(&tag));
-        }
-        content
-    }
-
-    fn process_headers(content: String) -> String {
-        let mut parser = HeaderParser::new();
-
-        // Add a bit of buffer capacity for the newlines that we'll add.
-        let mut updated = String::with_capacity(content.len() + 10);
-        for line in content.lines() {
-            if let Some(updated_line) = parser.process_line(line) {
-                updated.push_str(&updated_line);
-            } else {
-                updated.push_str(line);
-            }
-            updated.push('\n');
-        }
-        updated
-    }
-
     // Get a raw version of the notes, not meant for postprocessing, just for extraction of
     // information.
     pub fn new_raw(
@@ -125,6 +99,7 @@ impl<'a> Note<'a> {
             }
             None => None,
         };
+        let content = comments::strip_comments(&content).into_owned();
 
         let links = Self::find_obsidian_links(&path, ref_path, &content, find_files, ignore);
         let title = Self::get_title(&path, frontmatter.as_ref());
@@ -135,10 +110,11 @@ impl<'a> Note<'a> {
             content,
             title,
             frontmatter,
-            placeholders: vec![],
             tags: vec![],
             backlinks: HashSet::new(),
             creation_date: None,
+            local_graph: String::new(),
+            frontmatter_error: None,
         })
     }
 
@@ -148,62 +124,88 @@ impl<'a> Note<'a> {
         search_links: bool,
         ignore: &Vec<PathBuf>,
     ) -> Result<Self, std::io::Error> {
-        let mut content = read_file_to_str(&path)?;
-        //let mut content = Self::sanitize(&read_file_to_str(&path)?);
+        let content = read_file_to_str(&path)?;
+        Ok(Self::from_content(path, content, base_dir, search_links, ignore))
+    }
 
+    /// Construct a note from the given markdown `content`, as if it were read from `path`.
+    pub fn from_content(
+        path: PathBuf,
+        mut content: String,
+        base_dir: &Path,
+        search_links: bool,
+        ignore: &Vec<PathBuf>,
+    ) -> Self {
+        let mut frontmatter_error = None;
         let frontmatter = match extract_yaml_frontmatter(&content) {
             Some(fm_content) => {
                 let fm_count = fm_content.lines().count() + 2; // +2 for the surrounding "---"
                                                                // lines
                                                                //debug!("Found {} lines of frontmatter in {:?}", fm_count, path);
                 content = utils::remove_first_n_lines(&content, fm_count);
-                parse_frontmatter(&fm_content).ok()
+                parse_frontmatter(&fm_content)
+                    .map_err(|e| frontmatter_error = Some(e))
+                    .ok()
             }
             None => None,
         };
+        // Comments are private, so they are left out of everything: links, tags, search and html.
+        let content = comments::strip_comments(&content).into_owned();
+
+        // Extract the links and tags
+        let (mut links, mut tags) = Self::find_links_and_tags(&content);
+        Self::resolve_links(&mut links, &path, base_dir, search_links, ignore);
+
+        // Links in the frontmatter count as links of the note as well.
+        if let Some(fm) = &frontmatter {
+            let mut property_links = properties::find_links(fm);
+            Self::resolve_links(&mut property_links, &path, base_dir, search_links, ignore);
+            links.append(&mut property_links);
+        }
 
-        // Remove code blocks, and math.
-        let (mut content, mut placeholders) = Self::remove_protected_elems(content);
-        content = Self::replace_raw_html_blocks_by_placeholders(content, &mut placeholders);
-        // Extract the links
-        let mut links = Self::find_obsidian_links(&path, base_dir, &content, search_links, ignore);
-        // Replace links by placeholders, since they may also contain protected symbols with
-        // special meaning, like `^` and `#`.
-        let mut markdown_links =
-            Self::find_markdown_links(&path, &base_dir, &content, search_links, ignore);
-        links.append(&mut markdown_links);
-
-        //let mut raw_links = Self::find_raw_links(&content);
-        //links.append(&mut raw_links);
-        content = Self::replace_links_by_placeholders(content, &mut placeholders, &links);
-
-        let mut raw_links = Self::find_raw_links(&content);
-        content = Self::replace_links_by_placeholders(content, &mut placeholders, &raw_links);
-        links.append(&mut raw_links);
-
-        // Get the labels of block-refs
-        let blockref_labels = Self::find_blockref_labels(&content);
-        content =
-            Self::replace_blockrefs_by_placeholders(content, &mut placeholders, &blockref_labels);
-        //content = Self::replace_links_by_placeholders(content, &mut placeholders, &markdown_links);
-        let tags = Self::find_tags(&content);
-        // Replace admonitions by placeholders, so they are not recognized as quotes by the
-        // markdown processor
-        content = Self::replace_admonitions_by_placeholders(content, &mut placeholders);
+        if let Some(fm) = &frontmatter {
+            Self::add_frontmatter_tags(&mut tags, fm);
+        }
         let title = Self::get_title(&path, frontmatter.as_ref());
 
         //let creation_date = Self::compute_creation_date(&frontmatter, &path).unwrap();
-        Ok(Note {
+        Note {
             path,
             links,
             content,
             title,
             frontmatter,
-            placeholders,
             tags,
             backlinks: HashSet::new(),
             creation_date: None,
-        })
+            local_graph: String::new(),
+            frontmatter_error,
+        }
+    }
+
+    /// The error of parsing the frontmatter, if it is not valid yaml. The note is then
+    /// loaded without frontmatter.
+    pub fn frontmatter_error(&self) -> Option<&ScanError> {
+        self.frontmatter_error.as_ref()
+    }
+
+    /// Drop the content of the note. What is needed to link to the note and to list it is
+    /// kept: its links, tags, title, frontmatter and creation date.
+    pub fn drop_content(&mut self) {
+        self.content = String::new();
+    }
+
+    /// Load the note again from its file, e.g. after its content was dropped. The creation
+    /// date is kept, if it was cached.
+    pub fn reload<'b>(
+        &self,
+        base_dir: &Path,
+        search_links: bool,
+        ignore: &Vec<PathBuf>,
+    ) -> Result<Note<'b>, std::io::Error> {
+        let mut note = Note::new(self.path.clone(), base_dir, search_links, ignore)?;
+        note.creation_date = self.creation_date;
+        Ok(note)
     }
 
     pub fn cache_creation_time(&mut self, use_git: bool) {
@@ -274,83 +276,61 @@ impl<'a> Note<'a> {
     //    Ok(creation_date)
     //}
 
-    fn replace_links_by_placeholders(
-        content: String,
-        placeholders: &mut Vec<Sanitization>,
-        links: &Vec<Link>,
-    ) -> String {
-        let mut content = content;
-        for link in links {
-            let link_ph = Sanitization::from(link.source_string.to_string());
-            content = content.replace(&link_ph.original, &link_ph.get_placeholder());
-            placeholders.push(link_ph);
+    /// Add the tags listed in the `tags` key of the frontmatter, if they were not found yet.
+    fn add_frontmatter_tags(tags: &mut Vec<tags::Tag>, frontmatter: &Yaml) {
+        for name in string_list(&frontmatter["tags"]) {
+            let tag_path = name.trim_start_matches('#').to_lowercase();
+            if !tags.iter().any(|tag| tag.tag_path == tag_path) {
+                tags.push(tags::Tag {
+                    tag_path,
+                    source: String::new(),
+                });
+            }
         }
-        content
     }
 
-    fn replace_blockrefs_by_placeholders(
-        content: String,
-        placeholders: &mut Vec<Sanitization>,
-        labels: &Vec<labels::BlockLabel>,
-    ) -> String {
-        let mut content = content;
-        for label in labels {
-            let link_ph = Sanitization::new(
-                label.source.to_string(),
-                html::HtmlTag::span().with_id(&label.label).wrap(""),
-                false,
-            );
-            content = content.replace(&link_ph.original, &link_ph.get_placeholder());
-            placeholders.push(link_ph);
-        }
-        content
+    /// Aliases of the note, listed in the `aliases` key of the frontmatter.
+    pub fn aliases(&self) -> Vec<String> {
+        self.frontmatter
+            .as_ref()
+            .map_or_else(Vec::new, |fm| string_list(&fm["aliases"]))
     }
 
-    fn replace_raw_html_blocks_by_placeholders(
-        content: String,
-        placeholders: &mut Vec<Sanitization>,
-    ) -> String {
-        let mut raw_html = raw_html::RawHTMLParser::new();
-        let mut output = String::with_capacity(content.len());
-        for line in content.lines() {
-            match raw_html.process_line(line) {
-                raw_html::ParseOutput::Placeholder {
-                    replacement,
-                    placeholder,
-                } => {
-                    output.push_str(&replacement);
-                    if let Some(ph) = placeholder {
-                        placeholders.push(ph);
-                    }
-                }
-                raw_html::ParseOutput::None => output.push_str(line),
-            }
-            output.push('\n');
+    /// Whether the note is published: not if its frontmatter has `publish: false`, nor if it
+    /// has `draft: true`, unless drafts are included.
+    pub fn is_published(&self, include_drafts: bool) -> bool {
+        let Some(fm) = &self.frontmatter else {
+            return true;
+        };
+        fm["publish"].as_bool() != Some(false)
+            && (include_drafts || fm["draft"].as_bool() != Some(true))
+    }
+
+    /// Mark the links of this note to notes that are not published, so they are shown as
+    /// plain text.
+    pub fn hide_unpublished(&mut self, unpublished: &filesys::Unpublished) {
+        for link in self.links.iter_mut().filter(|link| {
+            !link.broken && matches!(link.link_type(), LinkType::Note | LinkType::Embed)
+        }) {
+            link.unpublished = unpublished.contains(&link.target.with_extension("md"));
         }
-        output
-    }
-    fn replace_admonitions_by_placeholders(
-        content: String,
-        placeholders: &mut Vec<Sanitization>,
-    ) -> String {
-        let mut admonitions = admonitions::AdmonitionParser::new();
-        let mut output = String::with_capacity(content.len());
-        for line in content.lines() {
-            match admonitions.process_line(line) {
-                admonitions::ParseOutput::Placeholder {
-                    replacement,
-                    placeholder,
-                } => {
-                    output.push_str(&replacement);
-                    if let Some(ph) = placeholder {
-                        placeholders.push(ph);
-                    }
+    }
+
+    /// Point the broken links of this note to the notes that have the link target as an alias.
+    pub fn resolve_aliases(&mut self, aliases: &filesys::Aliases) {
+        for link in self.links.iter_mut().filter(|link| {
+            link.broken && matches!(link.link_type(), LinkType::Note | LinkType::Embed)
+        }) {
+            if let Some(target) = filesys::resolve_alias(&link.target, aliases) {
+                debug!("Resolved alias {:?} to {:?}", link.target, target);
+                if link.alias.is_none() {
+                    // Show the alias, rather than the name of the note.
+                    link.alias = Some(link.target.with_extension("").to_string_lossy().to_string());
                 }
-                admonitions::ParseOutput::None => output.push_str(line),
+                link.set_target(target);
+                link.set_broken(false);
             }
-            output.push('\n');
         }
-        output
     }
 
     fn resolve_links(
@@ -360,10 +340,12 @@ impl<'a> Note<'a> {
         search_links: bool,
         ignore: &Vec<PathBuf>,
     ) {
-        for link in links
-            .iter_mut()
-            .filter(|l| l.link_type() == LinkType::Note || l.link_type() == LinkType::Internal)
-        {
+        for link in links.iter_mut().filter(|l| {
+            matches!(
+                l.link_type(),
+                LinkType::Note | LinkType::Internal | LinkType::Embed
+            )
+        }) {
             match filesys::resolve_path(&link.target, ref_path, root_path, search_links, ignore) {
                 filesys::ResolvedPath::Unchanged => {}
                 filesys::ResolvedPath::Broken => {
@@ -389,44 +371,54 @@ impl<'a> Note<'a> {
         links
     }
 
-    fn find_raw_links(content: &str) -> Vec<Link> {
-        links::find_raw_links(content)
-    }
-
-    fn find_markdown_links(
-        ref_path: &Path,
-        root_path: &Path,
-        content: &str,
-        search_links: bool,
-        ignore: &Vec<PathBuf>,
-    ) -> Vec<Link> {
-        let mut links = links::find_markdown_links(content);
-        Self::resolve_links(&mut links, ref_path, root_path, search_links, ignore);
-        links
-    }
-
-    /// Find labels used for blockrefs: `^<alias>`. These get translated into empty spans with
-    /// corresponding id `<alias>`.
-    ///
-    /// ## Example
-    /// `^124` -> `<span id="124"></span>`
-    fn find_blockref_labels(content: &str) -> Vec<labels::BlockLabel> {
-        labels::find_labels(content)
+    /// Find the links and tags in the content of the note, in the order they occur.
+    fn find_links_and_tags(content: &str) -> (Vec<Link>, Vec<tags::Tag>) {
+        let mut links = vec![];
+        let mut tags = vec![];
+        for element in ObsidianMarkdown::new(content).parser() {
+            match element {
+                Element::Link { link, .. } => links.push(link),
+                Element::Tag(tag) => tags.push(tag),
+                _ => {}
+            }
+        }
+        // Sort by length (longest first), like the tags of the frontmatter.
+        tags::sort_by_length(&mut tags);
+        (links, tags)
     }
 
-    fn find_tags(content: &str) -> Vec<tags::Tag> {
-        // TODO: Extract tags from the frontmatter
-        tags::find_tags(content)
+    /// The content of the note as markdown, with links replaced by their text, and without
+    /// the `[!type]` of callouts and the `^label`s of blocks. Used to extract the text of the
+    /// note, e.g. for search.
+    pub fn plain_markdown(&self) -> String {
+        let markdown = ObsidianMarkdown::new(&self.content);
+        let text = markdown.text();
+        let mut content = String::with_capacity(text.len());
+        let mut links = self.links.iter();
+        let mut last = 0;
+        for element in markdown.parser() {
+            let (source, replacement) = match element {
+                Element::Link { link, source, .. } => {
+                    (source, links.next().unwrap_or(&link).link_text())
+                }
+                Element::Label { source, .. } | Element::CalloutStart { source, .. } => {
+                    (source, String::new())
+                }
+                _ => continue,
+            };
+            if source.start >= last {
+                content.push_str(&text[last..source.start]);
+                content.push_str(&replacement);
+                last = source.end;
+            }
+        }
+        content.push_str(&text[last..]);
+        content
     }
 
-    ///Remove protected pieces of content, like math and code.
-    ///
-    ///These should be interpreted literally and should be ignored when scanning for
-    ///tags, links etc. Therefore, these elements are detected first and replaced with a
-    ///hash serving as a placeholder.
-    fn remove_protected_elems(content: String) -> (String, Vec<Sanitization>) {
-        // Remove math elements and code
-        placeholders::disambiguate_protected(&content)
+    /// Set the html of the graph of the notes around this note.
+    pub fn set_local_graph(&mut self, html: String) {
+        self.local_graph = html;
     }
 
     /// Add a backlink to `self`s set of backlinks.
@@ -437,54 +429,182 @@ impl<'a> Note<'a> {
     }
 
     ///Export the current note to a html file at the specified path.
-    pub fn to_html<U: AsRef<str>>(&self, path: &Path, template_content: U) -> Result<(), Error> {
-        self.to_html_inner(path, template_content.as_ref())?;
+    ///
+    ///Notes embedded in this note are loaded through `embeds`.
+    pub fn to_html(
+        &self,
+        path: &Path,
+        template: &PageTemplate,
+        embeds: &mut EmbedContext,
+    ) -> Result<(), Error> {
+        self.to_html_inner(path, template, embeds)?;
         Ok(())
     }
 
-    fn process_highlights(content: &str) -> String {
-        replace_obs_highlights(content)
+    /// The text and url of the notes that link to this note, sorted by text and then by url,
+    /// so the pages don't change between builds. The text is not escaped yet. A path that
+    /// can't be slugified is used as it is, like for the page itself.
+    fn sorted_backlinks(&self) -> Vec<LinkContext> {
+        let mut backlinks: Vec<LinkContext> = self
+            .backlinks
+            .iter()
+            .map(|link| LinkContext {
+                title: link.link_text(),
+                url: filesys::slugify_path(&link.target, Some("html"))
+                    .unwrap_or_else(|_| link.target.with_extension("html"))
+                    .to_string_lossy()
+                    .to_string(),
+            })
+            .collect();
+        backlinks.sort_by(|a, b| a.title.cmp(&b.title).then_with(|| a.url.cmp(&b.url)));
+        backlinks
     }
 
-    /// Main method to convert a given note to a html file, based on the given template.
-    fn to_html_inner(&self, path: &Path, template_content: &str) -> Result<(), Error> {
-        if let Some(parent_dir) = path.parent() {
-            filesys::create_dir_if_not_exists(&parent_dir).unwrap();
+    /// Values of this note that are available in templates. Its text is escaped, since
+    /// templates insert values as they are.
+    fn template_context(&self) -> NoteContext {
+        let mut backlinks = self.sorted_backlinks();
+        for backlink in &mut backlinks {
+            backlink.title = text_to_html(&backlink.title);
         }
-        let file = File::create(path)?;
-        let mut writer = io::BufWriter::new(file);
 
-        let mut content = self.content.to_owned();
+        let mut tags: Vec<LinkContext> = vec![];
+        for tag in &self.tags {
+            let tag = LinkContext {
+                title: text_to_html(&tag.tag_path),
+                url: utils::format_tag_path(tag),
+            };
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
 
-        // Needs to be done before replacing the highlights placeholders back
-        // to avoid false matches.
-        content = Self::process_highlights(&content);
+        NoteContext {
+            title: text_to_html(&self.title),
+            date: self
+                .creation_date
+                .map(|date| date.format("%Y-%m-%d").to_string()),
+            tags,
+            backlinks,
+            frontmatter: self
+                .frontmatter
+                .as_ref()
+                .map_or(serde_json::Value::Null, |fm| escape_strings(yaml_to_json(fm))),
+        }
+    }
+
+    /// The html of a link of the note. `text` is the html of the text of markdown links.
+    /// Embedded notes are rendered through `embeds`, if given. Links to notes that are not
+    /// published are only their text.
+    fn link_html(link: &Link, text: Option<String>, embeds: Option<&mut EmbedContext>) -> String {
+        let math = embeds.as_ref().and_then(|embeds| embeds.math());
+        let text = text.unwrap_or_else(|| text_with_math_to_html(&link.link_text(), math));
+        if link.unpublished {
+            return text;
+        }
+        if let (LinkType::Embed, Some(embeds)) = (link.link_type(), embeds) {
+            return embeds.render(link);
+        }
+        if link.is_attachment {
+            return formatting::link_to_html(link);
+        }
+        let mut shown = link.clone();
+        shown.alias = Some(text);
+        debug!("Link {:?} rendered as {:?}.", link.alias, shown.alias);
+        formatting::link_to_html(&shown)
+    }
 
-        for placeholder in self.placeholders.iter().filter(|p| p.before_markdown) {
-            content = content.replace(&placeholder.get_placeholder(), &placeholder.replacement);
+    /// The markdown events of the note, with the Obsidian syntax rendered to html.
+    fn html_events<'m, 'c: 'm>(
+        &self,
+        markdown: &'m ObsidianMarkdown,
+        mut embeds: Option<&mut EmbedContext<'c>>,
+    ) -> Vec<Event<'m>> {
+        // The links are found in the same order when the note is loaded.
+        let mut links = self.links.iter();
+        let mut events = vec![];
+        let math = embeds.as_ref().and_then(|embeds| embeds.math());
+        let mut elements = markdown.parser().with_math(math).peekable();
+        while let Some(element) = elements.next() {
+            let event = match element {
+                Element::Markdown(event) => event,
+                Element::Link { link, text, .. } => {
+                    let link = links.next().unwrap_or(&link);
+                    let embedded = embeds.is_some()
+                        && !link.unpublished
+                        && link.link_type() == LinkType::Embed;
+                    let html = Self::link_html(link, text, embeds.as_deref_mut());
+                    // An embedded note alone in a paragraph replaces it, since its <div> can't
+                    // be in a <p>.
+                    let alone = events.last() == Some(&Event::Start(Tag::Paragraph))
+                        && matches!(
+                            elements.peek(),
+                            Some(Element::Markdown(Event::End(TagEnd::Paragraph)))
+                        );
+                    if embedded && alone {
+                        events.pop();
+                        elements.next();
+                        Event::Html(html.into())
+                    } else {
+                        Event::InlineHtml(html.into())
+                    }
+                }
+                Element::Tag(tag) => Event::InlineHtml(formatting::tag_to_md(&tag).into()),
+                Element::HighlightStart => Event::InlineHtml(highlights::open_highlight().into()),
+                Element::HighlightEnd => Event::InlineHtml(highlights::close_highlight().into()),
+                Element::Label { label, .. } => {
+                    Event::InlineHtml(html::HtmlTag::span().with_id(&label.label).wrap("").into())
+                }
+                Element::Math(math) => Event::InlineMath(math),
+                Element::CalloutStart { callout, .. } => Event::Html(callout.start_html().into()),
+                Element::CalloutEnd(callout) => Event::Html(callout.end_html().into()),
+                Element::RawHtml(raw) => Event::Html(raw.into()),
+            };
+            events.push(event);
         }
+        events
+    }
 
-        content = self.process_links(content);
-        content = self.process_tags(content);
-        content = Self::process_headers(content);
+    /// The ids of the headings in the html of the note, in order. `None` for headings
+    /// without an id.
+    pub fn heading_ids(&self) -> Vec<Option<String>> {
+        let markdown = ObsidianMarkdown::new(&self.content);
+        wrap_pulldown_cmark::heading_ids(self.html_events(&markdown, None))
+    }
 
-        let mut html_content = markdown_to_html(&content);
+    /// Convert the content of the note to html, without wrapping it in a template.
+    pub fn render_body(&self, embeds: &mut EmbedContext) -> String {
+        embeds.enter(&self.path);
+        let markdown = ObsidianMarkdown::new(&self.content);
+        let events = self.html_events(&markdown, Some(&mut *embeds));
+        let mut html_content = String::with_capacity(markdown.text().len() * 3 / 2);
+        pulldown_cmark::html::push_html(
+            &mut html_content,
+            MarkdownParser::new(events.into_iter()).with_math(embeds.math()),
+        );
+        embeds.leave();
+        html_content
+    }
 
-        for placeholder in self.placeholders.iter().filter(|p| !p.before_markdown) {
-            html_content =
-                html_content.replace(&placeholder.get_placeholder(), &placeholder.replacement);
+    /// Main method to convert a given note to a html file, based on the given template.
+    fn to_html_inner(
+        &self,
+        path: &Path,
+        template: &PageTemplate,
+        embeds: &mut EmbedContext,
+    ) -> Result<(), Error> {
+        if let Some(parent_dir) = path.parent() {
+            filesys::create_dir_if_not_exists(parent_dir)?;
         }
+        let file = File::create(path)?;
+        let mut writer = io::BufWriter::new(file);
+
+        let html_content = self.render_body(embeds);
 
         let backlinks: Vec<String> = self
-            .backlinks
+            .sorted_backlinks()
             .iter()
-            .map(|link| {
-                html::link(
-                    &filesys::slugify_path(&link.target, Some("html")).unwrap(),
-                    &link.alias.clone().unwrap(),
-                    "",
-                )
-            })
+            .map(|link| html::link(&link.url, &text_to_html(&link.title), ""))
             .collect();
 
         debug!("Note {} has {} backlinks", self.title, backlinks.len());
@@ -509,17 +629,28 @@ impl<'a> Note<'a> {
             },
         );
 
-        write!(
-            writer,
-            "{}",
-            template_content
-                .replace(r"{{date}}", &date_string)
-                .replace(r"{{content}}", &html_content)
-                .replace(r"{{title}}", &self.title)
-                .replace(r"{{backlinks}}", &backlink_replacement)
-        )
-        .expect("Couldn't write note contents.");
-
-        Ok(())
+        let page = PageContext {
+            title: text_to_html(&self.title),
+            content: html_content,
+            date: date_string,
+            backlinks: backlink_replacement,
+            properties: self
+                .frontmatter
+                .as_ref()
+                .map_or_else(String::new, |fm| properties::render_properties(fm, &self.links)),
+            local_graph: self.local_graph.clone(),
+            note: Some(self.template_context()),
+        };
+        let layout = self
+            .frontmatter
+            .as_ref()
+            .and_then(|fm| fm["layout"].as_str())
+            .unwrap_or(DEFAULT_LAYOUT);
+        if !template.has_layout(layout) {
+            warn!("Note {:?} asks for unknown layout `{}`. Using the default.", self.path, layout);
+        }
+        let html = template.render_layout(layout, &page).map_err(Error::other)?;
+        write!(writer, "{}", html)?;
+        writer.flush()
     }
 }
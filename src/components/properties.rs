@@ -0,0 +1,143 @@
+use yaml_rust::Yaml;
+
+use super::frontmatter::yaml_to_json;
+use super::link::Link;
+use crate::core::html::HtmlTag;
+use crate::exporting::wrap_pulldown_cmark::text_to_html;
+use crate::obsidian::links;
+use crate::utils::formatting::link_to_html;
+
+fn collect_strings<'y>(yaml: &'y Yaml, strings: &mut Vec<&'y str>) {
+    match yaml {
+        Yaml::String(string) => strings.push(string),
+        Yaml::Array(items) => items.iter().for_each(|item| collect_strings(item, strings)),
+        Yaml::Hash(map) => map
+            .values()
+            .for_each(|value| collect_strings(value, strings)),
+        _ => {}
+    }
+}
+
+/// Find the links in the (possibly nested) string values of the frontmatter, e.g.
+/// `related: "[[Other note]]"`.
+///
+/// Embeds are ignored, since they can't be shown in a property. The links are not resolved.
+pub fn find_links(frontmatter: &Yaml) -> Vec<Link> {
+    let mut strings = vec![];
+    collect_strings(frontmatter, &mut strings);
+    strings
+        .into_iter()
+        .flat_map(links::find_obsidian_links)
+        .filter(|link| !link.source_string.starts_with('!'))
+        .collect()
+}
+
+/// The string as html, with its links rendered and the rest of the text escaped.
+fn render_string(string: &str, links: &[Link]) -> String {
+    let mut html = String::with_capacity(string.len());
+    let mut rest = string;
+    while let Some((start, link)) = links
+        .iter()
+        .filter(|link| !link.source_string.is_empty())
+        .filter_map(|link| rest.find(&link.source_string).map(|start| (start, link)))
+        .min_by_key(|(start, _)| *start)
+    {
+        html.push_str(&text_to_html(&rest[..start]));
+        html.push_str(&link_to_html(link));
+        rest = &rest[start + link.source_string.len()..];
+    }
+    html.push_str(&text_to_html(rest));
+    html
+}
+
+fn render_value(value: &Yaml, links: &[Link]) -> String {
+    match value {
+        Yaml::String(string) => render_string(string, links),
+        Yaml::Real(real) => text_to_html(real),
+        Yaml::Integer(int) => int.to_string(),
+        Yaml::Boolean(boolean) => boolean.to_string(),
+        Yaml::Array(items) => HtmlTag::ul().with_class("property-list").wrap(
+            items
+                .iter()
+                .map(|item| HtmlTag::li().wrap(render_value(item, links)))
+                .collect::<String>(),
+        ),
+        Yaml::Hash(_) => render_properties(value, links),
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => String::new(),
+    }
+}
+
+/// Render the frontmatter as a table of properties, like the Properties view of Obsidian.
+///
+/// The links in the values are rendered as html using `links`, the (resolved) links of the
+/// note. Returns an empty string if there are no properties.
+pub fn render_properties(frontmatter: &Yaml, links: &[Link]) -> String {
+    let map = match frontmatter {
+        Yaml::Hash(map) if !map.is_empty() => map,
+        _ => return String::new(),
+    };
+    let rows: String = map
+        .iter()
+        .map(|(key, value)| {
+            let name = match key.as_str() {
+                Some(name) => text_to_html(name),
+                None => text_to_html(&yaml_to_json(key).to_string()),
+            };
+            HtmlTag::tr()
+                .wrap(HtmlTag::th().wrap(name) + &HtmlTag::td().wrap(render_value(value, links)))
+        })
+        .collect();
+    HtmlTag::table().with_class("properties").wrap(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_links, render_properties};
+    use crate::components::frontmatter::parse_frontmatter;
+
+    const FRONTMATTER: &str = r#"related: "[[Other]]"
+rating: 4
+nested:
+  sources: ["[[First]]", "plain"]
+  cover: "![[image.png]]"
+"#;
+
+    #[test]
+    fn links_in_nested_values() {
+        let frontmatter = parse_frontmatter(FRONTMATTER).unwrap();
+        let targets: Vec<String> = find_links(&frontmatter)
+            .iter()
+            .map(|link| link.target.to_string_lossy().to_string())
+            .collect();
+        assert_eq!(targets, vec!["Other.md", "First.md"]);
+    }
+
+    #[test]
+    fn properties_table() {
+        let frontmatter = parse_frontmatter(FRONTMATTER).unwrap();
+        let html = render_properties(&frontmatter, &find_links(&frontmatter));
+        assert!(html.starts_with("<table class=\"properties\" >"));
+        assert!(html.contains("<th>rating</th><td>4</td>"));
+        assert!(html.contains("<a href=\"other.html\">Other</a>"));
+        assert!(html.contains("<th>cover</th><td>![[image.png]]</td>"));
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        let frontmatter =
+            parse_frontmatter("\"a < b & c\": \"x < y & [[Other]] <b>\"\n1.5: [\"<i>\"]").unwrap();
+        let html = render_properties(&frontmatter, &find_links(&frontmatter));
+        assert!(html.contains(
+            "<th>a &lt; b &amp; c</th>\
+             <td>x &lt; y &amp; <a href=\"other.html\">Other</a> &lt;b&gt;</td>"
+        ));
+        assert!(html.contains("<th>1.5</th>") && html.contains("&lt;i&gt;"));
+        assert!(!html.contains("<b>") && !html.contains("<i>"));
+    }
+
+    #[test]
+    fn no_properties() {
+        let frontmatter = parse_frontmatter("{}").unwrap();
+        assert_eq!(render_properties(&frontmatter, &[]), "");
+    }
+}
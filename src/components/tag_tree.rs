@@ -1,5 +1,6 @@
 use crate::components::link::Link;
 use crate::core::html;
+use crate::core::template::{PageContext, PageTemplate, TAG_INDEX_LAYOUT};
 use crate::utils::constants::TAG_DIR;
 use crate::utils::filesys;
 use crate::utils::formatting::link_to_html;
@@ -257,7 +258,7 @@ impl Tree {
         &self,
         output_path: &Path,
         base_path: &Path,
-        template: &str,
+        template: &PageTemplate,
     ) -> std::io::Result<()> {
         // Generate the html for its own page.
         let parent_tags = vec![];
@@ -291,7 +292,7 @@ impl Tree {
         output_path: &Path,
         base_path: &Path,
         inner_tags: &Vec<&Link>,
-        template: &str,
+        template: &PageTemplate,
     ) -> std::io::Result<()> {
         let rel_dir = Self::prepare_directory(&base_path, &inner_tags)?;
 
@@ -316,10 +317,12 @@ impl Tree {
         // Title and header
         let title = format!("Tag - {} / {}", parent_tag_names.join(" / "), self.name);
         let html = template
-            .replace("{{date}}", "")
-            .replace("{{content}}", &html_content)
-            .replace("{{backlinks}}", "")
-            .replace("{{title}}", &title);
+            .render_layout(TAG_INDEX_LAYOUT, &PageContext {
+                title,
+                content: html_content,
+                ..Default::default()
+            })
+            .map_err(std::io::Error::other)?;
 
         writer.write_all(html.as_bytes())?;
 
@@ -49,3 +49,11 @@ pub enum ReadConfigError<T: fmt::Debug> {
     #[error("Invalid toml file in path {0:?}. Could not load file into configuration object.")]
     InvalidToml(T),
 }
+
+#[derive(Error, Debug)]
+pub enum TemplateError {
+    #[error("Invalid template: {0}")]
+    Parse(#[from] handlebars::TemplateError),
+    #[error("Could not render template: {0}")]
+    Render(#[from] handlebars::RenderError),
+}
@@ -73,6 +73,10 @@ pub enum TagType<'a> {
     Span, 
     Details, 
     Summary, 
+    Table,
+    Tr,
+    Th,
+    Td,
 }
 
 impl<'a> HtmlTag<'a>
@@ -122,6 +126,22 @@ impl<'a> HtmlTag<'a>
         Self::new(TagType::Li)
     }
 
+    pub fn table() -> Self {
+        Self::new(TagType::Table)
+    }
+
+    pub fn tr() -> Self {
+        Self::new(TagType::Tr)
+    }
+
+    pub fn th() -> Self {
+        Self::new(TagType::Th).set_inline(true)
+    }
+
+    pub fn td() -> Self {
+        Self::new(TagType::Td).set_inline(true)
+    }
+
     #[allow(dead_code)]
     pub fn a(link: &'a str) -> Self {
         Self::new(TagType::A(link))
@@ -203,7 +223,11 @@ impl<'a> HtmlTag<'a>
             TagType::Div => "div".to_string(),
             TagType::Span => "span".to_string(),
             TagType::Details => "details".to_string(),
-            TagType::Summary => "summary".to_string()
+            TagType::Summary => "summary".to_string(),
+            TagType::Table => "table".to_string(),
+            TagType::Tr => "tr".to_string(),
+            TagType::Th => "th".to_string(),
+            TagType::Td => "td".to_string(),
         }
 
     }
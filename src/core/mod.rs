@@ -1,3 +1,3 @@
 pub mod html;
-pub mod sanitization;
+pub mod template;
 pub mod errors;
@@ -0,0 +1,212 @@
+use handlebars::Handlebars;
+use log::debug;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use super::errors::TemplateError;
+
+/// Layout used for pages that don't ask for a specific one.
+pub const DEFAULT_LAYOUT: &str = "index";
+/// Layout of the index pages of tags.
+pub const TAG_INDEX_LAYOUT: &str = "tag_index";
+/// Layout of the archive page.
+pub const ARCHIVE_LAYOUT: &str = "archive";
+/// Layout of the graph page.
+pub const GRAPH_LAYOUT: &str = "graph";
+
+///Link to a page of the website, as it is passed to templates.
+#[derive(Debug, Clone, PartialEq, serde_derive::Serialize)]
+pub struct LinkContext {
+    pub title: String,
+    pub url: String,
+}
+
+///Information on the note that is being rendered, available in templates as `note`. The text
+///in it, including the strings of the frontmatter, is html-escaped.
+#[derive(Debug, Clone, Default, serde_derive::Serialize)]
+pub struct NoteContext {
+    pub title: String,
+    ///Creation date in the form `YYYY-MM-DD`.
+    pub date: Option<String>,
+    pub tags: Vec<LinkContext>,
+    pub backlinks: Vec<LinkContext>,
+    ///The frontmatter of the note, or `null` if it has none.
+    pub frontmatter: Value,
+}
+
+///Values of a single page that are available in templates.
+///
+///`title`, `content`, `date` and `backlinks` are ready-made html, so templates that only use
+///these placeholders keep working. `properties` is the frontmatter rendered as a table, and
+///`local_graph` the graph of the notes around the note.
+///`note` is only set on pages that are generated from notes.
+#[derive(Debug, Clone, Default, serde_derive::Serialize)]
+pub struct PageContext {
+    pub title: String,
+    pub content: String,
+    pub date: String,
+    pub backlinks: String,
+    pub properties: String,
+    pub local_graph: String,
+    pub note: Option<NoteContext>,
+}
+
+///Templates for the html pages, rendered with [Handlebars](https://handlebarsjs.com/).
+///
+///Besides the default layout, any number of named layouts can be added. Pages that ask for a
+///layout that doesn't exist are rendered with the default one.
+///Values are inserted without html escaping, since most of them are html. The text of notes
+///in [NoteContext] is escaped when it is built.
+///Besides the values in the [PageContext], templates have access to the values shared by all
+///pages (see [PageTemplate::set_global]).
+pub struct PageTemplate {
+    registry: Handlebars<'static>,
+    sources: BTreeMap<String, String>,
+    globals: Map<String, Value>,
+}
+
+impl PageTemplate {
+    ///Create a template with the given source as the default layout.
+    pub fn new<S: Into<String>>(source: S) -> Result<Self, TemplateError> {
+        let mut registry = Handlebars::new();
+        registry.register_escape_fn(handlebars::no_escape);
+        let mut template = PageTemplate {
+            registry,
+            sources: BTreeMap::new(),
+            globals: Map::new(),
+        };
+        template.add_layout(DEFAULT_LAYOUT, source)?;
+        Ok(template)
+    }
+
+    ///Add a layout, or replace it if a layout with the same name already exists.
+    pub fn add_layout<S: Into<String>>(
+        &mut self,
+        name: &str,
+        source: S,
+    ) -> Result<(), TemplateError> {
+        let source = source.into();
+        self.registry.register_template_string(name, &source)?;
+        self.sources.insert(name.to_string(), source);
+        Ok(())
+    }
+
+    pub fn has_layout(&self, name: &str) -> bool {
+        self.sources.contains_key(name)
+    }
+
+    ///Make `value` available as `key` on every page rendered with this template.
+    pub fn set_global<V: Serialize>(&mut self, key: &str, value: V) {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.globals.insert(key.to_string(), value);
+    }
+
+    ///Everything that determines the rendered pages, apart from the pages themselves.
+    pub fn fingerprint(&self) -> String {
+        let sources: Vec<String> = self
+            .sources
+            .iter()
+            .map(|(name, source)| format!("{}:\n{}", name, source))
+            .collect();
+        format!(
+            "{}\n{}",
+            sources.join("\n"),
+            Value::Object(self.globals.clone())
+        )
+    }
+
+    ///Render a page with the default layout.
+    pub fn render(&self, page: &PageContext) -> Result<String, TemplateError> {
+        self.render_layout(DEFAULT_LAYOUT, page)
+    }
+
+    ///Render a page with the given layout, or with the default layout if it doesn't exist.
+    pub fn render_layout(&self, layout: &str, page: &PageContext) -> Result<String, TemplateError> {
+        let layout = if self.has_layout(layout) {
+            layout
+        } else {
+            debug!("No layout named {}. Using the default layout.", layout);
+            DEFAULT_LAYOUT
+        };
+        let mut data = self.globals.clone();
+        if let Ok(Value::Object(values)) = serde_json::to_value(page) {
+            data.extend(values);
+        }
+        Ok(self.registry.render(layout, &data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkContext, NoteContext, PageContext, PageTemplate};
+
+    #[test]
+    fn placeholders_are_not_escaped() {
+        let mut template =
+            PageTemplate::new("<title>{{title}}</title>{{tag_nav}}{{content}}").unwrap();
+        template.set_global("tag_nav", "<ul></ul>");
+        let page = PageContext {
+            title: "Note".to_string(),
+            content: "<p>Hi & bye</p>".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            template.render(&page).unwrap(),
+            "<title>Note</title><ul></ul><p>Hi & bye</p>"
+        );
+    }
+
+    #[test]
+    fn missing_values_are_empty() {
+        let template = PageTemplate::new("[{{root}}]").unwrap();
+        assert_eq!(template.render(&PageContext::default()).unwrap(), "[]");
+    }
+
+    #[test]
+    fn loops_and_conditionals() {
+        let template = PageTemplate::new(
+            "{{#if note.backlinks}}{{#each note.backlinks}}<a href=\"{{url}}\">{{title}}</a>{{/each}}{{else}}none{{/if}}",
+        )
+        .unwrap();
+        let mut page = PageContext {
+            note: Some(NoteContext::default()),
+            ..Default::default()
+        };
+        assert_eq!(template.render(&page).unwrap(), "none");
+
+        page.note.as_mut().unwrap().backlinks.push(LinkContext {
+            title: "Other".to_string(),
+            url: "other.html".to_string(),
+        });
+        assert_eq!(
+            template.render(&page).unwrap(),
+            "<a href=\"other.html\">Other</a>"
+        );
+    }
+
+    #[test]
+    fn layouts_fall_back_to_default() {
+        let mut template = PageTemplate::new("default: {{title}}").unwrap();
+        template
+            .add_layout("article", "article: {{title}}")
+            .unwrap();
+        let page = PageContext {
+            title: "Note".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            template.render_layout("article", &page).unwrap(),
+            "article: Note"
+        );
+        assert_eq!(
+            template.render_layout("slides", &page).unwrap(),
+            "default: Note"
+        );
+    }
+
+    #[test]
+    fn invalid_template() {
+        assert!(PageTemplate::new("{{#if}}").is_err());
+    }
+}
@@ -0,0 +1,598 @@
+use serde_derive::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::exporter::Exporter;
+use crate::components::link::{Link, LinkType};
+use crate::components::note::Note;
+use crate::obsidian::comments::strip_comments;
+use crate::obsidian::embeds::extract_subtarget;
+use crate::utils::constants::INDEX_FILE;
+use crate::utils::filesys::relative_to;
+use crate::utils::utils::{self, read_file_to_str};
+
+///Format of the report of `oxidian check`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ReportFormat {
+    ///One line per issue, followed by a summary.
+    #[default]
+    Text,
+    Json,
+    ///[SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html), as
+    ///understood by code scanning tools.
+    Sarif,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IssueKind {
+    ///A link or embed whose target note does not exist.
+    BrokenLink,
+    ///A linked attachment that does not exist.
+    MissingAttachment,
+    ///A link to a heading or `^block` label that does not exist in the target note.
+    MissingSubtarget,
+    ///Several notes with the same name, so that `[[name]]` is ambiguous.
+    AmbiguousName,
+    ///A note that no other note links to.
+    OrphanNote,
+    ///A note that could not be read, e.g. because it is not valid UTF-8.
+    UnreadableNote,
+    ///Frontmatter that is not valid yaml.
+    InvalidFrontmatter,
+    ///A command of the math preamble that could not be parsed, or a preamble that could not
+    ///be read.
+    InvalidPreamble,
+    ///A path that could not be turned into the path of a page.
+    InvalidPath,
+    ///A page or file of the website that could not be written.
+    WriteFailure,
+}
+
+impl IssueKind {
+    const ALL: [IssueKind; 10] = [
+        IssueKind::BrokenLink,
+        IssueKind::MissingAttachment,
+        IssueKind::MissingSubtarget,
+        IssueKind::AmbiguousName,
+        IssueKind::OrphanNote,
+        IssueKind::UnreadableNote,
+        IssueKind::InvalidFrontmatter,
+        IssueKind::InvalidPreamble,
+        IssueKind::InvalidPath,
+        IssueKind::WriteFailure,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            IssueKind::BrokenLink => "broken-link",
+            IssueKind::MissingAttachment => "missing-attachment",
+            IssueKind::MissingSubtarget => "missing-subtarget",
+            IssueKind::AmbiguousName => "ambiguous-name",
+            IssueKind::OrphanNote => "orphan-note",
+            IssueKind::UnreadableNote => "unreadable-note",
+            IssueKind::InvalidFrontmatter => "invalid-frontmatter",
+            IssueKind::InvalidPreamble => "invalid-preamble",
+            IssueKind::InvalidPath => "invalid-path",
+            IssueKind::WriteFailure => "write-failure",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueKind::AmbiguousName
+            | IssueKind::OrphanNote
+            | IssueKind::InvalidFrontmatter
+            | IssueKind::InvalidPreamble
+            | IssueKind::InvalidPath => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            IssueKind::BrokenLink => "The target of the link does not exist.",
+            IssueKind::MissingAttachment => "The linked attachment does not exist.",
+            IssueKind::MissingSubtarget => {
+                "The heading or block label does not exist in the target note."
+            }
+            IssueKind::AmbiguousName => "Several notes have the same name.",
+            IssueKind::OrphanNote => "No note links to this note.",
+            IssueKind::UnreadableNote => "The note could not be read.",
+            IssueKind::InvalidFrontmatter => "The frontmatter is not valid yaml.",
+            IssueKind::InvalidPreamble => "The math preamble could not be parsed.",
+            IssueKind::InvalidPath => "The path could not be turned into the path of a page.",
+            IssueKind::WriteFailure => "A file of the website could not be written.",
+        }
+    }
+}
+
+///A problem found in the notes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub severity: Severity,
+    ///Path of the note, relative to the notes directory. For files of the website that could
+    ///not be written, their path relative to the output directory.
+    pub path: PathBuf,
+    ///Line of the note on which the problem occurs, starting from 1.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Issue {
+    pub(crate) fn new(kind: IssueKind, path: PathBuf, line: Option<usize>, message: String) -> Self {
+        Issue {
+            kind,
+            severity: kind.severity(),
+            path,
+            line,
+            message,
+        }
+    }
+
+    ///Report the issue with another severity than that of its kind.
+    pub(crate) fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+}
+
+///Result of checking all the notes of a directory.
+///
+///Errors are problems that show up on the website, like broken links. Warnings only point to
+///notes that are hard to reach or to links that may not go where they are meant to.
+#[derive(Debug, Default, Serialize)]
+pub struct CheckReport {
+    pub issues: Vec<Issue>,
+}
+
+impl CheckReport {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+
+    pub fn format(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_text(),
+            ReportFormat::Json => self.to_json(),
+            ReportFormat::Sarif => self.to_sarif(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for issue in &self.issues {
+            let location = match issue.line {
+                Some(line) => format!("{}:{}", issue.path.to_string_lossy(), line),
+                None => issue.path.to_string_lossy().to_string(),
+            };
+            let severity = match issue.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            text.push_str(&format!(
+                "{}: {}[{}]: {}\n",
+                location,
+                severity,
+                issue.kind.id(),
+                issue.message
+            ));
+        }
+        text.push_str(&format!(
+            "{} errors, {} warnings\n",
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        ));
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        let report = json!({
+            "errors": self.count(Severity::Error),
+            "warnings": self.count(Severity::Warning),
+            "issues": self.issues,
+        });
+        serde_json::to_string_pretty(&report).expect("Serialization of the report failed.")
+    }
+
+    pub fn to_sarif(&self) -> String {
+        let rules: Vec<_> = IssueKind::ALL
+            .iter()
+            .map(|kind| {
+                json!({
+                    "id": kind.id(),
+                    "shortDescription": { "text": kind.description() },
+                    "defaultConfiguration": { "level": kind.severity() },
+                })
+            })
+            .collect();
+        let results: Vec<_> = self
+            .issues
+            .iter()
+            .map(|issue| {
+                let mut location = json!({
+                    "artifactLocation": { "uri": sarif_uri(&issue.path) },
+                });
+                if let Some(line) = issue.line {
+                    location["region"] = json!({ "startLine": line });
+                }
+                json!({
+                    "ruleId": issue.kind.id(),
+                    "level": issue.severity,
+                    "message": { "text": issue.message },
+                    "locations": [{ "physicalLocation": location }],
+                })
+            })
+            .collect();
+        let sarif = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "oxidian",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    }
+                },
+                "results": results,
+            }],
+        });
+        serde_json::to_string_pretty(&sarif).expect("Serialization of the report failed.")
+    }
+}
+
+///Relative path as a URI reference, as required by SARIF.
+fn sarif_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '%' => uri.push_str("%25"),
+            ' ' => uri.push_str("%20"),
+            '#' => uri.push_str("%23"),
+            '?' => uri.push_str("%3F"),
+            _ => uri.push(c),
+        }
+    }
+    uri
+}
+
+///Line of each link of the note in its file, counting from 1. Line numbers refer to the file
+///as it is, including the frontmatter, so the file is read again. Comments keep their line
+///breaks when they are stripped, so links in them are left out without moving the others.
+pub(crate) fn link_lines(note: &Note) -> Vec<Option<usize>> {
+    let source = read_file_to_str(&note.path).unwrap_or_default();
+    let source = strip_comments(&source);
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    note.links
+        .iter()
+        .map(|link| {
+            let nth = occurrences.entry(&link.source_string).or_insert(0);
+            *nth += 1;
+            line_of(&source, &link.source_string, *nth - 1)
+        })
+        .collect()
+}
+
+///Line on which the `nth` occurrence (counting from 0) of `needle` starts, counting from 1.
+fn line_of(content: &str, needle: &str, nth: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
+    let mut seen = 0;
+    for (idx, line) in content.lines().enumerate() {
+        seen += line.matches(needle).count();
+        if seen > nth {
+            return Some(idx + 1);
+        }
+    }
+    None
+}
+
+fn is_external(target: &Path) -> bool {
+    let target = target.to_string_lossy();
+    target.starts_with("http://") || target.starts_with("https://") || target.starts_with("www.")
+}
+
+impl<'a> Exporter<'a> {
+    ///Check all the notes for broken links, missing attachments, links to headings or blocks
+    ///that don't exist, ambiguous note names and orphan notes.
+    ///
+    ///Links are resolved in the same way as during an export, so the report lists exactly the
+    ///problems that the website would have.
+    pub fn check(&mut self) -> CheckReport {
+        self.diagnostics().clear();
+        let notes = self.load_all_notes();
+        let backlinks = self.generate_backlinks_from_notes(&notes);
+        let keys: HashMap<PathBuf, &Note> = notes
+            .iter()
+            .map(|note| (self.note_key(&note.path), note))
+            .collect();
+
+        // Notes that could not be loaded, or that have invalid frontmatter.
+        let mut issues = self.diagnostics().issues();
+        for note in &notes {
+            issues.extend(self.check_links(note, &keys));
+        }
+
+        // Notes with the same file name, which can't be told apart by `[[name]]`.
+        let mut names: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for key in keys.keys() {
+            if let Some(stem) = key.file_stem() {
+                names
+                    .entry(stem.to_string_lossy().to_lowercase())
+                    .or_default()
+                    .push(key.clone());
+            }
+        }
+        for (name, mut paths) in names.into_iter().filter(|(_, paths)| paths.len() > 1) {
+            paths.sort();
+            let others: Vec<String> = paths[1..]
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect();
+            issues.push(Issue::new(
+                IssueKind::AmbiguousName,
+                paths[0].clone(),
+                None,
+                format!(
+                    "Note name `{}` is also used by {}, so `[[{}]]` is ambiguous",
+                    name,
+                    others.join(", "),
+                    name
+                ),
+            ));
+        }
+
+        // Notes that can only be found through search or navigation.
+        for key in keys.keys() {
+            let linked = backlinks
+                .get(key)
+                .is_some_and(|referring| referring.iter().any(|link| &link.target != key));
+            if !linked && key != Path::new(INDEX_FILE) {
+                issues.push(Issue::new(
+                    IssueKind::OrphanNote,
+                    key.clone(),
+                    None,
+                    "No other note links to this note".to_string(),
+                ));
+            }
+        }
+
+        issues.sort_by(|a, b| {
+            (&a.path, a.line, a.kind, &a.message).cmp(&(&b.path, b.line, b.kind, &b.message))
+        });
+        CheckReport { issues }
+    }
+
+    fn note_key(&self, path: &Path) -> PathBuf {
+        utils::prepend_slash(relative_to(path, self.input_directory()))
+    }
+
+    fn check_links(&self, note: &Note, notes: &HashMap<PathBuf, &Note>) -> Vec<Issue> {
+        let path = self.note_key(&note.path);
+        let mut issues = vec![];
+
+        for (link, line) in note.links.iter().zip(link_lines(note)) {
+            let issue = |kind, message| Issue::new(kind, path.clone(), line, message);
+            match link.link_type() {
+                LinkType::Note | LinkType::Embed | LinkType::Internal if link.broken => {
+                    issues.push(issue(
+                        IssueKind::BrokenLink,
+                        format!(
+                            "`{}` links to `{}`, which does not exist",
+                            link.source_string,
+                            link.target.to_string_lossy()
+                        ),
+                    ));
+                }
+                // Shown as text on the website, so there is nothing to check.
+                LinkType::Note | LinkType::Embed if link.unpublished => {}
+                LinkType::Note | LinkType::Embed | LinkType::Internal => {
+                    if let Some(missing) = self.missing_subtarget(link, notes) {
+                        issues.push(issue(
+                            IssueKind::MissingSubtarget,
+                            format!(
+                                "`{}` links to `{}`, which does not exist in `{}`",
+                                link.source_string,
+                                missing,
+                                link.target.to_string_lossy()
+                            ),
+                        ));
+                    }
+                }
+                LinkType::Attachment(_) if !is_external(&link.target) => {
+                    let (input_path, _) = self.get_paths_of_linked_attach(link);
+                    if !input_path.exists() {
+                        issues.push(issue(
+                            IssueKind::MissingAttachment,
+                            format!(
+                                "`{}` refers to `{}`, which does not exist",
+                                link.source_string,
+                                link.target.to_string_lossy()
+                            ),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+        issues
+    }
+
+    ///The heading or block label of the link, if the target note does not contain it.
+    fn missing_subtarget<'l>(
+        &self,
+        link: &'l Link,
+        notes: &HashMap<PathBuf, &Note>,
+    ) -> Option<&'l str> {
+        let subtarget = link.subtarget.as_deref()?;
+        // The content of loaded notes no longer contains the block labels, so read the file.
+        let path = match notes.get(&link.target.with_extension("md")) {
+            Some(target) => target.path.clone(),
+            None => self.input_directory().join(&link.target),
+        };
+        let content = read_file_to_str(path).ok()?;
+        match extract_subtarget(&strip_comments(&content), subtarget) {
+            Some(_) => None,
+            None => Some(subtarget),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{line_of, sarif_uri, IssueKind, ReportFormat};
+    use crate::exporting::config::ExportConfig;
+    use crate::exporting::exporter::Exporter;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const INDEX: &str = "---
+related: \"[[Topic]]\"
+---
+# Home
+See [[Topic#Details]] and [[Topic#Missing]].
+Also [[Nowhere]] and ![[missing.png]].
+";
+
+    const TOPIC: &str = "# Topic
+## Details
+Text. ^label
+Back to [[index]], [[index#^label]] and [[#^label]].
+";
+
+    fn kinds_at(report: &super::CheckReport, path: &str) -> Vec<(IssueKind, Option<usize>)> {
+        report
+            .issues
+            .iter()
+            .filter(|issue| issue.path == Path::new(path))
+            .map(|issue| (issue.kind, issue.line))
+            .collect()
+    }
+
+    #[test]
+    fn finds_issues() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(input.join("sub")).unwrap();
+        std::fs::write(input.join("index.md"), INDEX).unwrap();
+        std::fs::write(input.join("Topic.md"), TOPIC).unwrap();
+        std::fs::write(input.join("sub").join("topic.md"), "# Other topic\n").unwrap();
+        let output = dir.path().join("out");
+        let cfg = ExportConfig::default();
+
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        let report = exporter.check();
+
+        assert_eq!(
+            kinds_at(&report, "index.md"),
+            vec![
+                (IssueKind::MissingSubtarget, Some(5)),
+                (IssueKind::BrokenLink, Some(6)),
+                (IssueKind::MissingAttachment, Some(6)),
+            ]
+        );
+        assert_eq!(
+            kinds_at(&report, "Topic.md"),
+            vec![
+                (IssueKind::AmbiguousName, None),
+                (IssueKind::MissingSubtarget, Some(4))
+            ]
+        );
+        assert_eq!(
+            kinds_at(&report, "sub/topic.md"),
+            vec![(IssueKind::OrphanNote, None)]
+        );
+        assert!(report.has_errors());
+        assert!(report
+            .format(ReportFormat::Text)
+            .ends_with("4 errors, 2 warnings\n"));
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&report.format(ReportFormat::Sarif)).unwrap();
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[0]["ruleId"], "ambiguous-name");
+        assert_eq!(results[0]["level"], "warning");
+    }
+
+    #[test]
+    fn reports_notes_that_cannot_be_loaded() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("index.md"), "[[broken]]\n").unwrap();
+        std::fs::write(input.join("binary.md"), b"\xff\xfe").unwrap();
+        std::fs::write(input.join("yaml.md"), "---\ntitle: [a\n---\n[[index]]\n").unwrap();
+        let output = dir.path().join("out");
+        let cfg = ExportConfig::default();
+
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        let report = exporter.check();
+        assert_eq!(
+            kinds_at(&report, "binary.md"),
+            vec![(IssueKind::UnreadableNote, None)]
+        );
+        assert_eq!(
+            kinds_at(&report, "yaml.md"),
+            vec![(IssueKind::OrphanNote, None), (IssueKind::InvalidFrontmatter, Some(3))]
+        );
+        assert_eq!(
+            kinds_at(&report, "index.md"),
+            vec![(IssueKind::BrokenLink, Some(1))]
+        );
+    }
+
+    #[test]
+    fn links_in_comments_are_ignored() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        let index = "%% [[missing]] %%\n%%\n[[hidden]]\n%%\n[[missing]] `%%[[code]]%%`\n";
+        std::fs::write(input.join("index.md"), index).unwrap();
+        let output = dir.path().join("out");
+        let cfg = ExportConfig::default();
+
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        let report = exporter.check();
+        assert_eq!(
+            kinds_at(&report, "index.md"),
+            vec![(IssueKind::BrokenLink, Some(5))]
+        );
+    }
+
+    #[test]
+    fn lines_of_repeated_links() {
+        let content = "[[a]] and [[a]]\n\n[[a]]";
+        assert_eq!(line_of(content, "[[a]]", 0), Some(1));
+        assert_eq!(line_of(content, "[[a]]", 1), Some(1));
+        assert_eq!(line_of(content, "[[a]]", 2), Some(3));
+        assert_eq!(line_of(content, "[[a]]", 3), None);
+    }
+
+    #[test]
+    fn uris_are_escaped() {
+        assert_eq!(
+            sarif_uri(Path::new("sub dir/50% #1.md")),
+            "sub%20dir/50%25%20%231.md"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::preamble::formatter as fmt;
+use crate::utils::filesys::Codec;
 use crate::utils::utils;
 use figment::Error;
 use serde_derive::{Deserialize, Serialize};
@@ -17,6 +18,9 @@ pub struct ExportConfig {
     pub generate_nav: bool,
     pub generate_tag_index: bool,
     pub generate_archive: bool,
+    /// Generate a page with a graph of the links between the notes.
+    pub generate_graph: bool,
+    pub graph: GraphConfig,
     pub creation_date: CreationDateConfig,
     pub performance: PerformanceConfig,
     pub search: SearchConfig,
@@ -24,14 +28,96 @@ pub struct ExportConfig {
     pub enable_mermaid: bool,
     pub root_path: Option<String>,
     pub title: String,
+    /// Show the frontmatter of notes as a table of properties.
+    pub show_properties: bool,
+    /// Callout types besides those of Obsidian, like `> [!recipe]`.
+    pub callouts: Vec<CalloutType>,
+    /// Publish the notes with `draft: true` in their frontmatter, e.g. to preview them locally.
+    /// Notes with `publish: false` are never published.
+    pub include_drafts: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchConfig {
-    /// The amount of characters to store in the search index for each file.
+    /// The amount of characters of each note that are shown below its search result.
     pub max_len: usize,
     /// Enable search
     pub enable: bool,
+    pub weights: FieldWeights,
+    /// Language of the notes, for the stopwords and stemming. Notes can set their own
+    /// language with the `lang` key of their frontmatter.
+    pub language: Language,
+    /// File with one stopword per line, relative to the notebook directory, to use instead
+    /// of the built-in lists.
+    pub stopwords: Option<PathBuf>,
+    /// Split the search index into files by the first this many characters of the words, so
+    /// that only the files a query needs are downloaded. `0` writes a single file.
+    pub shard_prefix: usize,
+    /// Also write compressed copies of the files of the search index, for servers that
+    /// serve pre-compressed files.
+    pub compression: Compression,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Brotli,
+}
+
+impl Compression {
+    /// The codec of the compressed copies, if there are any.
+    pub fn codec(self) -> Option<Codec> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some(Codec::Gzip),
+            Compression::Brotli => Some(Codec::Brotli),
+        }
+    }
+}
+
+/// The languages that search can stem the words of.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    English,
+    Dutch,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::Dutch];
+
+    /// Parse a language name or code, like `dutch`, `nl` or `nl-BE`.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let code = code.trim().to_lowercase();
+        let primary = code.split(['-', '_']).next().unwrap_or_default();
+        match primary {
+            "en" | "eng" | "english" => Some(Language::English),
+            "nl" | "nld" | "dut" | "dutch" | "nederlands" => Some(Language::Dutch),
+            _ => None,
+        }
+    }
+}
+
+/// How much a match in each part of a note counts in the ranking of search results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldWeights {
+    pub title: f64,
+    pub headings: f64,
+    pub tags: f64,
+    pub body: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GraphConfig {
+    /// Show the tags as nodes of the graph, connected to the notes that use them.
+    pub show_tags: bool,
+    /// Show a graph of the notes within this many links on the page of every note, as the
+    /// `local_graph` value of the template. `0` disables the local graphs.
+    pub local_depth: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -68,18 +154,46 @@ impl fmt::FormatPreamble for MathEngine {
     }
 }
 
+/// A type of callout declared by the user, with the colour and icon of its title.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalloutType {
+    pub name: String,
+    /// `#rrggbb`, or `r, g, b`.
+    pub color: Option<String>,
+    /// The name of a Material Symbols icon, like `restaurant`.
+    pub icon: Option<String>,
+}
+
+/// Whether the math is rendered when the website is built, and to what.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MathPrerender {
+    /// Typeset the math in the browser, with the math engine.
+    #[default]
+    None,
+    /// MathML, which browsers show without any script or stylesheet.
+    Mathml,
+    /// The html of KaTeX, which only needs the stylesheet of KaTeX.
+    Html,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MathConfig {
     /// Enable math
     pub enable: bool,
     pub engine: MathEngine,
     pub preamble_path: Option<PathBuf>,
+    /// Render the math with KaTeX when building the website, with the macros of the preamble,
+    /// instead of loading `engine` in the browser.
+    pub prerender: MathPrerender,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PerformanceConfig {
-    ///Skip notes whose modification dates are older than their destination files in the output
-    ///directory.
+    ///Only render the notes whose content, links, backlinks or embedded notes changed since the
+    ///previous build, as recorded in the build manifest in the output directory. All notes are
+    ///rendered again when the template or the configuration changed, or the preamble when the
+    ///math is pre-rendered.
     pub skip_unchanged_notes: bool,
     ///Don't copy attachments whose modification dates are older than the those in the output
     ///folder.
@@ -94,6 +208,11 @@ pub struct PerformanceConfig {
     pub search_for_links: bool,
     ///Build a search index.
     pub build_search_index: bool,
+    ///The number of threads to load and compile the notes on. Uses one thread per core when 0.
+    ///It doesn't change the output, so it isn't serialized, and changing it doesn't invalidate
+    ///the previous build.
+    #[serde(default, skip_serializing)]
+    pub threads: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -114,6 +233,7 @@ impl Default for MathConfig {
             enable: true,
             engine: MathEngine::Mathjax,
             preamble_path: None,
+            prerender: MathPrerender::None,
         }
     }
 }
@@ -129,6 +249,22 @@ impl Default for SearchConfig {
         SearchConfig {
             max_len: 200,
             enable: true,
+            weights: FieldWeights::default(),
+            language: Language::default(),
+            stopwords: None,
+            shard_prefix: 0,
+            compression: Compression::default(),
+        }
+    }
+}
+
+impl Default for FieldWeights {
+    fn default() -> Self {
+        FieldWeights {
+            title: 5.0,
+            headings: 2.0,
+            tags: 3.0,
+            body: 1.0,
         }
     }
 }
@@ -140,6 +276,7 @@ impl Default for PerformanceConfig {
             skip_cached_attachments: true,
             search_for_links: true,
             build_search_index: true,
+            threads: 0,
         }
     }
 }
@@ -154,6 +291,8 @@ impl Default for ExportConfig {
             generate_nav: true,
             generate_tag_index: true,
             generate_archive: true,
+            generate_graph: false,
+            graph: GraphConfig::default(),
             ignored: vec![],
             enable_mermaid: true,
             creation_date: CreationDateConfig::default(),
@@ -162,6 +301,9 @@ impl Default for ExportConfig {
             math: MathConfig::default(),
             root_path: Some("/".to_string()),
             title: "NOTES".to_string(),
+            show_properties: false,
+            callouts: vec![],
+            include_drafts: false,
         }
     }
 }
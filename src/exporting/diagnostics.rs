@@ -0,0 +1,115 @@
+use log::warn;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use super::check::{CheckReport, Issue, IssueKind, Severity};
+
+///Collects the problems found while building the website, so the build can go on past them and
+///report them all at the end.
+///
+///The notes are built on several threads, so issues are added through a shared reference.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    issues: Mutex<Vec<Issue>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Issue>> {
+        // An issue is pushed in one go, so the list is fine even if another thread panicked.
+        self.issues.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    ///Record an issue, and log it. Errors are logged as warnings as well, since they are
+    ///reported at the end of the build.
+    pub fn add(&self, issue: Issue) {
+        let location = match issue.line {
+            Some(line) => format!("{}:{}", issue.path.to_string_lossy(), line),
+            None => issue.path.to_string_lossy().to_string(),
+        };
+        warn!("{}: {}", location, issue.message);
+        self.lock().push(issue);
+    }
+
+    ///Record an issue with the severity of its kind.
+    pub fn report(
+        &self,
+        kind: IssueKind,
+        path: impl Into<PathBuf>,
+        line: Option<usize>,
+        message: impl Into<String>,
+    ) {
+        self.add(Issue::new(kind, path.into(), line, message.into()));
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    ///The issues found so far, sorted by path and line.
+    pub fn issues(&self) -> Vec<Issue> {
+        let mut issues = self.lock().clone();
+        issues.sort_by(|a, b| {
+            (&a.path, a.line, a.kind, &a.message).cmp(&(&b.path, b.line, b.kind, &b.message))
+        });
+        issues
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.lock()
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+
+    ///Whether the build failed: if there were errors or, in `strict` mode, warnings.
+    pub fn failed(&self, strict: bool) -> bool {
+        self.count(Severity::Error) > 0 || (strict && self.count(Severity::Warning) > 0)
+    }
+
+    ///The issues found so far, as a report like that of `oxidian check`.
+    pub fn summary(&self) -> CheckReport {
+        CheckReport {
+            issues: self.issues(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostics;
+    use crate::exporting::check::{Issue, IssueKind, Severity};
+    use std::path::PathBuf;
+
+    #[test]
+    fn strict_mode_fails_on_warnings() {
+        let diagnostics = Diagnostics::new();
+        assert!(!diagnostics.failed(true));
+
+        diagnostics.add(
+            Issue::new(
+                IssueKind::BrokenLink,
+                PathBuf::from("b.md"),
+                Some(3),
+                "`[[x]]` links to `x`, which does not exist".to_string(),
+            )
+            .with_severity(Severity::Warning),
+        );
+        diagnostics.report(IssueKind::InvalidFrontmatter, "a.md", Some(2), "Invalid yaml");
+        assert!(!diagnostics.failed(false));
+        assert!(diagnostics.failed(true));
+
+        diagnostics.report(IssueKind::WriteFailure, "archive.html", None, "Disk full");
+        assert!(diagnostics.failed(false));
+
+        let paths: Vec<PathBuf> = diagnostics.issues().into_iter().map(|i| i.path).collect();
+        assert_eq!(paths, ["a.md", "archive.html", "b.md"].map(PathBuf::from));
+        assert!(diagnostics
+            .summary()
+            .to_text()
+            .ends_with("1 errors, 2 warnings\n"));
+    }
+}
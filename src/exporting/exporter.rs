@@ -1,7 +1,7 @@
 use super::load_static::{
     BUTTON_CSS, DARKMODE_SCRIPT, FOUC_SCRIPT, HTML_TEMPLATE, ICON, INDEX_CSS, KATEX_CFG,
-    LOAD_KATEX, LOAD_MATHJAX, LOAD_MERMAID, LOAD_SEARCH, MATHJAX_CFG, NAVBAR_SCRIPT, SEARCH_HTML,
-    SEARCH_SCRIPT, STOPWORDS, THM_CSS, TUFTE_CSS,
+    LOAD_KATEX, LOAD_KATEX_CSS, LOAD_MATHJAX, LOAD_MERMAID, LOAD_SEARCH, MATHJAX_CFG, NAVBAR_SCRIPT, SEARCH_HTML,
+    SEARCH_SCRIPT, THM_CSS, TUFTE_CSS, GRAPH_SCRIPT,
 };
 use crate::utils::filesys::{copy_directory, relative_to};
 use crate::utils::utils;
@@ -9,20 +9,42 @@ use log::{debug, info, warn};
 use serde_json;
 
 use super::load_static::{ADMONITIONS_CSS, BROKEN_LINKS};
-use super::search::SearchEntry;
+use super::check::{link_lines, Issue, IssueKind, Severity};
+use super::diagnostics::Diagnostics;
+use super::errors::PreambleError;
+use super::manifest::{manifest_key, Manifest};
+use super::math::MathRenderer;
+use super::search::{Analyzers, SearchIndex, SHARD_DIR};
+use serde::Serialize;
+use crate::components::embed::EmbedContext;
 use crate::components::link::{Link, LinkType};
 use crate::components::tag_tree::Tree;
-use crate::components::{archive, note};
-use crate::exporting::config::{ExportConfig, MathEngine};
-use crate::preamble::formatter::FormatPreamble;
+use crate::components::graph::{self, Graph, LinkMap};
+use crate::components::{archive, frontmatter, note};
+use crate::core::template::{PageTemplate, DEFAULT_LAYOUT};
+use crate::exporting::config::{ExportConfig, MathEngine, MathPrerender};
+use crate::obsidian::admonitions;
+use crate::preamble::formatter::{preamble_macros, FormatPreamble};
 use crate::utils::constants::TAG_DIR;
-use crate::utils::filesys::{self, get_all_notes_exclude, slugify_path, write_to_file};
-use std::collections::{HashMap, HashSet};
+use crate::utils::filesys::{
+    self, get_all_notes_exclude, remove_compressed_copies, slugify_path, write_compressed,
+    write_to_file, Aliases, Unpublished,
+};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
+use walkdir::WalkDir;
 
 pub type Backlinks = HashMap<PathBuf, HashSet<Link>>;
 
+/// The attachments to copy, from their output paths to their input paths. Every file is copied
+/// once, even if several notes link to it.
+type Attachments = BTreeMap<PathBuf, PathBuf>;
+
+/// The number of notes per thread that are loaded in full at once while rendering.
+const NOTES_PER_THREAD: usize = 4;
+
 #[derive(Debug)]
 pub struct ExportStats {
     note_count: u32,
@@ -30,6 +52,18 @@ pub struct ExportStats {
     skipped_attachments: u32,
     attachment_count: u32,
     build_time: std::time::Duration,
+    search_index: Vec<IndexFileSize>,
+}
+
+/// Size of a file of the search index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexFileSize {
+    /// Path relative to the output directory.
+    pub path: PathBuf,
+    /// Size in bytes.
+    pub size: usize,
+    /// Size in bytes of the compressed copy, if there is one.
+    pub compressed: Option<usize>,
 }
 
 impl ExportStats {
@@ -40,8 +74,14 @@ impl ExportStats {
             skipped_attachments: 0,
             attachment_count: 0,
             build_time: std::time::Duration::new(0, 0),
+            search_index: vec![],
         }
     }
+
+    /// The files of the search index of the last build, with their sizes.
+    pub fn search_index_files(&self) -> &[IndexFileSize] {
+        &self.search_index
+    }
 }
 
 impl std::fmt::Display for ExportStats {
@@ -60,7 +100,29 @@ Total Build Time: {time:?}
             time = self.build_time,
             note_skip = self.skipped_notes,
             attach_skip = self.skipped_attachments
-        )
+        )?;
+        if !self.search_index.is_empty() {
+            let size: usize = self.search_index.iter().map(|file| file.size).sum();
+            writeln!(
+                f,
+                "Search index: {:.1}kb in {} file(s)",
+                utils::byte_to_kb(size),
+                self.search_index.len()
+            )?;
+            for file in &self.search_index {
+                write!(
+                    f,
+                    "  {}: {:.1}kb",
+                    file.path.display(),
+                    utils::byte_to_kb(file.size)
+                )?;
+                if let Some(compressed) = file.compressed {
+                    write!(f, " ({:.1}kb compressed)", utils::byte_to_kb(compressed))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -69,33 +131,120 @@ pub struct Exporter<'a> {
     output_dir: &'a Path,
     cfg: &'a ExportConfig,
     pub stats: ExportStats,
-    note_template: String,
+    note_template: PageTemplate,
+    /// Notes whose output is up to date with the previous build, as found from the manifest.
+    up_to_date: HashSet<PathBuf>,
+    /// Aliases of all the notes, as found when they were last loaded.
+    aliases: Aliases,
+    /// The notes that are not published, as found when they were last loaded.
+    unpublished: Unpublished,
+    /// Renders the math when it is pre-rendered, with the macros of the preamble.
+    math: Option<MathRenderer>,
+    /// Threads to load and compile the notes on, if their number is set in the configuration.
+    pool: Option<rayon::ThreadPool>,
+    /// Problems found during the last build, which did not stop it.
+    diagnostics: Diagnostics,
 }
 
-fn get_all_notes<'b>(
+pub(crate) fn get_all_notes<'b>(
     input_dir: &Path,
     ignore: &Vec<PathBuf>,
     search_for_linked_files: bool,
+    keep_content: bool,
+    diagnostics: &Diagnostics,
 ) -> Vec<note::Note<'b>> {
-    let all_paths = get_all_notes_exclude(&input_dir, ignore);
-    let all_notes = all_paths.filter_map(|note_path| {
-        note_path.map_or(None, |path| {
-            Some(note::Note::new(path, &input_dir, search_for_linked_files, ignore).unwrap())
+    // Collect the paths first, so the notes can be parsed in parallel and keep the order of the
+    // walk through the directory.
+    let all_paths: Vec<PathBuf> = get_all_notes_exclude(input_dir, ignore)
+        .filter_map(|path| match path {
+            Ok(path) => Some(path),
+            Err(e) => {
+                let path = e.path().unwrap_or(input_dir);
+                diagnostics.report(
+                    IssueKind::UnreadableNote,
+                    relative_to(path, input_dir),
+                    None,
+                    format!(
+                        "Could not read the directory, so its notes are left out: {}",
+                        e
+                    ),
+                );
+                None
+            }
+        })
+        .collect();
+    all_paths
+        .into_par_iter()
+        .filter_map(|path| {
+            let mut note =
+                match note::Note::new(path.clone(), input_dir, search_for_linked_files, ignore) {
+                    Ok(note) => note,
+                    Err(e) => {
+                        diagnostics.report(
+                            IssueKind::UnreadableNote,
+                            relative_to(&path, input_dir),
+                            None,
+                            format!("Could not read the note, so it is left out: {}", e),
+                        );
+                        return None;
+                    }
+                };
+            if let Some(e) = note.frontmatter_error() {
+                // The line of the error is counted from the `---` above the frontmatter.
+                diagnostics.report(
+                    IssueKind::InvalidFrontmatter,
+                    relative_to(&note.path, input_dir),
+                    Some(e.marker().line() + 1),
+                    format!(
+                        "The frontmatter is ignored, since it is not valid yaml: {}",
+                        frontmatter::error_message(e)
+                    ),
+                );
+            }
+            if !keep_content {
+                note.drop_content();
+            }
+            Some(note)
         })
-    });
-    all_notes.collect()
+        .collect()
 }
 
 impl<'a> Exporter<'a> {
     pub fn new(input_dir: &'a Path, output_dir: &'a Path, cfg: &'a ExportConfig) -> Self {
         let stats = ExportStats::new();
-        let note_template = HTML_TEMPLATE.to_string();
+        let note_template =
+            PageTemplate::new(HTML_TEMPLATE).expect("The default template is invalid.");
         Exporter {
             input_dir,
             output_dir,
             cfg,
             stats,
             note_template,
+            up_to_date: HashSet::new(),
+            aliases: Aliases::new(),
+            unpublished: Unpublished::new(),
+            math: None,
+            pool: Self::thread_pool(cfg.performance.threads),
+            diagnostics: Diagnostics::new(),
+        }
+    }
+
+    fn thread_pool(threads: usize) -> Option<rayon::ThreadPool> {
+        if threads == 0 {
+            return None;
+        }
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| warn!("Could not start {} threads. Got error {}", threads, e))
+            .ok()
+    }
+
+    ///Run `op` on the threads of the exporter, or on one thread per core by default.
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
         }
     }
 
@@ -107,11 +256,103 @@ impl<'a> Exporter<'a> {
         &self.cfg
     }
 
-    fn update_backlinks(&self, backlinks: &mut Backlinks, note: &note::Note) {
+    ///The problems found during the last build. They are logged as well.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    ///Load all the published notes in the input directory, and resolve links to their aliases.
+    pub(crate) fn load_all_notes<'b>(&mut self) -> Vec<note::Note<'b>> {
+        self.load_notes(true)
+    }
+
+    ///Load the links, tags, titles and frontmatter of all the notes in the input directory,
+    ///like [Exporter::load_all_notes], but drop their content as soon as it is parsed.
+    pub(crate) fn load_note_metadata<'b>(&mut self) -> Vec<note::Note<'b>> {
+        self.load_notes(false)
+    }
+
+    fn load_notes<'b>(&mut self, keep_content: bool) -> Vec<note::Note<'b>> {
+        let ignored = self.get_excluded();
+        let notes = self.install(|| {
+            get_all_notes(
+                self.input_dir,
+                &ignored,
+                self.cfg.performance.search_for_links,
+                keep_content,
+                &self.diagnostics,
+            )
+        });
+        self.aliases = self.collect_aliases(&notes);
+        // Links to the aliases of unpublished notes are resolved as well, so they aren't broken.
+        let (mut notes, hidden): (Vec<_>, Vec<_>) = notes
+            .into_iter()
+            .partition(|note| note.is_published(self.cfg.include_drafts));
+        if !hidden.is_empty() {
+            info!("Leaving out {} unpublished notes", hidden.len());
+        }
+        self.unpublished = hidden
+            .iter()
+            .map(|note| manifest_key(&note.path, self.input_dir))
+            .collect();
+        for note in &mut notes {
+            self.resolve_aliases(note);
+        }
+        notes
+    }
+
+    ///Whether the note at `path` is published, as found when the notes were last loaded.
+    pub(crate) fn was_published(&self, path: &Path) -> bool {
+        !self.unpublished.contains(&manifest_key(path, self.input_dir))
+    }
+
+    fn collect_aliases(&self, notes: &[note::Note]) -> Aliases {
+        let mut aliases = Aliases::new();
+        for note in notes {
+            let path = utils::prepend_slash(relative_to(&note.path, self.input_dir));
+            for alias in note.aliases() {
+                if let Some(other) = aliases.insert(alias.to_lowercase(), path.clone()) {
+                    if other != path {
+                        warn!("Alias `{}` is used by both {:?} and {:?}", alias, other, path);
+                    }
+                }
+            }
+        }
+        aliases
+    }
+
+    ///Whether the aliases of the given note differ from those last seen.
+    pub(crate) fn aliases_changed(&self, note: &note::Note) -> bool {
+        let path = utils::prepend_slash(relative_to(&note.path, self.input_dir));
+        let mut current: Vec<String> = note.aliases().iter().map(|a| a.to_lowercase()).collect();
+        let mut previous: Vec<String> = self
+            .aliases
+            .iter()
+            .filter(|(_, target)| **target == path)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        current.sort();
+        current.dedup();
+        previous.sort();
+        current != previous
+    }
+
+    ///Point the broken links of a note that was loaded on its own to the aliases of other notes,
+    ///and mark its links to unpublished notes.
+    pub(crate) fn resolve_aliases(&self, note: &mut note::Note) {
+        note.resolve_aliases(&self.aliases);
+        note.hide_unpublished(&self.unpublished);
+    }
+
+    pub(crate) fn update_backlinks(&self, backlinks: &mut Backlinks, note: &note::Note) {
         for link in note
             .links
             .iter()
-            .filter(|link| link.link_type() == LinkType::Note && !link.broken)
+            .filter(|link| {
+                matches!(link.link_type(), LinkType::Note | LinkType::Embed)
+                    && !link.broken
+                    && !link.unpublished
+            })
         {
             backlinks
                 .entry(link.target.with_extension("md"))
@@ -120,12 +361,47 @@ impl<'a> Exporter<'a> {
         }
     }
 
-    fn generate_backlinks_from_notes(&self, notes: &Vec<note::Note>) -> Backlinks {
-        let mut backlinks: Backlinks = HashMap::new();
-        for note in notes {
-            self.update_backlinks(&mut backlinks, &note);
+    ///Record the broken links of the notes in the diagnostics. They go nowhere on the website,
+    ///but don't stop the build.
+    fn report_broken_links(&self, notes: &[note::Note]) {
+        let is_broken = |link: &Link| {
+            link.broken
+                && matches!(link.link_type(), LinkType::Note | LinkType::Embed | LinkType::Internal)
+        };
+        for note in notes.iter().filter(|note| note.links.iter().any(is_broken)) {
+            let path = relative_to(&note.path, self.input_dir);
+            for (link, line) in note.links.iter().zip(link_lines(note)) {
+                if !is_broken(link) {
+                    continue;
+                }
+                let message = format!(
+                    "`{}` links to `{}`, which does not exist",
+                    link.source_string,
+                    link.target.to_string_lossy()
+                );
+                self.diagnostics.add(
+                    Issue::new(IssueKind::BrokenLink, path.clone(), line, message)
+                        .with_severity(Severity::Warning),
+                );
+            }
         }
-        backlinks
+    }
+
+    pub(crate) fn generate_backlinks_from_notes(&self, notes: &Vec<note::Note>) -> Backlinks {
+        self.install(|| {
+            notes
+                .par_iter()
+                .fold(Backlinks::new, |mut backlinks, note| {
+                    self.update_backlinks(&mut backlinks, note);
+                    backlinks
+                })
+                .reduce(Backlinks::new, |mut backlinks, other| {
+                    for (target, links) in other {
+                        backlinks.entry(target).or_default().extend(links);
+                    }
+                    backlinks
+                })
+        })
     }
 
     pub fn get_excluded(&self) -> Vec<PathBuf> {
@@ -148,13 +424,90 @@ impl<'a> Exporter<'a> {
         result
     }
 
+    ///Render the notes, and add them to `search_index` if it is given. The notes are loaded
+    ///again from their metadata, a chunk at a time, and dropped once they are rendered and
+    ///indexed, so only the notes of one chunk are in memory in full.
+    ///
+    ///Returns the paths of the notes that could not be read again or rendered.
     fn compile_notes_from_vec<'b>(
         &mut self,
-        notes: &mut Vec<note::Note<'b>>,
+        notes: &[note::Note<'b>],
         backlinks: &'b Backlinks,
-    ) {
-        for mut note in notes {
-            self.compile_note(&mut note, &backlinks);
+        mut search_index: Option<(&mut SearchIndex, &Analyzers)>,
+    ) -> Vec<PathBuf> {
+        let links = self.link_map(backlinks);
+        // Find the output paths of the notes and the attachments to copy before rendering,
+        // one note after the other, so the stats don't depend on the order in which the
+        // notes are rendered.
+        let mut attachments = Attachments::new();
+        let outputs: Vec<Option<PathBuf>> = notes
+            .iter()
+            .map(|note| self.plan_note(note, false, &mut attachments))
+            .collect();
+        let ignored = self.get_excluded();
+        let indexing = search_index.is_some();
+        let chunk_size = self.install(rayon::current_num_threads) * NOTES_PER_THREAD;
+        let mut failed = vec![];
+        for (chunk, outputs) in notes.chunks(chunk_size).zip(outputs.chunks(chunk_size)) {
+            // Each note comes back with its path if it could not be read again or rendered.
+            let loaded: Vec<(Option<note::Note>, Option<&Path>)> = self.install(|| {
+                chunk
+                    .par_iter()
+                    .zip(outputs.par_iter())
+                    // Notes that are up to date are only loaded to be indexed.
+                    .filter(|(_, output_path)| indexing || output_path.is_some())
+                    .map(|(metadata, output_path)| {
+                        let Some(mut note) = self.reload_note(metadata, &ignored) else {
+                            return (None, Some(metadata.path.as_path()));
+                        };
+                        let rendered = match output_path {
+                            Some(output_path) => self.render_note(
+                                &mut note,
+                                output_path,
+                                backlinks,
+                                links.as_ref(),
+                                &ignored,
+                            ),
+                            None => true,
+                        };
+                        let failed = (!rendered).then_some(metadata.path.as_path());
+                        (indexing.then_some(note), failed)
+                    })
+                    .collect()
+            });
+            failed.extend(loaded.iter().filter_map(|(_, path)| path.map(Path::to_path_buf)));
+            // The index is built serially, so the order of its documents is that of the notes.
+            if let Some((index, analyzers)) = search_index.as_mut() {
+                for note in loaded.iter().filter_map(|(note, _)| note.as_ref()) {
+                    index.add_note(note, analyzers, &self.cfg.search, self.input_dir);
+                }
+            }
+        }
+        self.transfer_linked_files(attachments);
+        failed
+    }
+
+    ///Load the note of the given metadata again, with its content, and resolve its links to
+    ///aliases.
+    fn reload_note<'b>(
+        &self,
+        metadata: &note::Note,
+        ignored: &Vec<PathBuf>,
+    ) -> Option<note::Note<'b>> {
+        match metadata.reload(self.input_dir, self.cfg.performance.search_for_links, ignored) {
+            Ok(mut note) => {
+                self.resolve_aliases(&mut note);
+                Some(note)
+            }
+            Err(e) => {
+                self.diagnostics.report(
+                    IssueKind::UnreadableNote,
+                    relative_to(&metadata.path, self.input_dir),
+                    None,
+                    format!("Could not read the note again, so it is left out: {}", e),
+                );
+                None
+            }
         }
     }
 
@@ -165,21 +518,20 @@ impl<'a> Exporter<'a> {
         } else {
             ""
         };
-        self.note_template = self.note_template.replace("{{SEARCH_SCRIPT}}", replacement);
+        self.note_template.set_global("SEARCH_SCRIPT", replacement);
     }
 
     fn set_base_dir(&mut self) {
         info!("Adding base dir");
         if let Some(base_dir) = &self.cfg.root_path {
-            self.note_template = self
-                .note_template
-                .replace("{{root}}", &format!("<base href=\"{}\"/>", base_dir));
+            self.note_template
+                .set_global("root", format!("<base href=\"{}\"/>", base_dir));
         }
     }
 
     fn set_title(&mut self) {
         info!("Adding notebook title");
-        self.note_template = self.note_template.replace("{{name}}", &self.cfg.title);
+        self.note_template.set_global("name", &self.cfg.title);
     }
 
     fn set_search_component(&mut self) {
@@ -189,19 +541,21 @@ impl<'a> Exporter<'a> {
         } else {
             ""
         };
-        self.note_template = self.note_template.replace("{{SEARCH_BAR}}", replacement);
+        self.note_template.set_global("SEARCH_BAR", replacement);
     }
 
     fn set_math_loading_snip(&mut self) {
         info!("Adding snippet to load math engine.");
         let mut replacement = "";
         if self.cfg.math.enable {
-            replacement = match self.cfg.math.engine {
-                MathEngine::Mathjax => LOAD_MATHJAX,
-                MathEngine::Katex => LOAD_KATEX,
+            replacement = match (self.cfg.math.prerender, &self.cfg.math.engine) {
+                (MathPrerender::None, MathEngine::Mathjax) => LOAD_MATHJAX,
+                (MathPrerender::None, MathEngine::Katex) => LOAD_KATEX,
+                (MathPrerender::Mathml, _) => "",
+                (MathPrerender::Html, _) => LOAD_KATEX_CSS,
             }
         }
-        self.note_template = self.note_template.replace("{{MATH_ENGINE}}", replacement);
+        self.note_template.set_global("MATH_ENGINE", replacement);
     }
 
     fn set_mermaid_loading_snip(&mut self) {
@@ -210,11 +564,17 @@ impl<'a> Exporter<'a> {
         if self.cfg.enable_mermaid {
             replacement = LOAD_MERMAID;
         }
-        self.note_template = self.note_template.replace("{{MERMAID}}", replacement);
+        self.note_template.set_global("MERMAID", replacement);
+    }
+
+    fn set_callout_style(&mut self) {
+        info!("Adding the style of the custom callout types.");
+        let style = admonitions::custom_callout_style(&self.cfg.callouts);
+        self.note_template.set_global("CALLOUT_STYLE", style);
     }
 
     fn set_tag_nav(&mut self, tree_html: &str) {
-        self.note_template = self.note_template.replace("{{tag_nav}}", tree_html);
+        self.note_template.set_global("tag_nav", tree_html);
     }
 
     fn initialize_tag_tree() -> Tree {
@@ -252,43 +612,140 @@ impl<'a> Exporter<'a> {
         None
     }
 
+    ///Add the other html files in the template directory as layouts, named after their file stem.
+    fn load_layouts(&mut self) {
+        let Some(dir) = &self.cfg.template_dir else {
+            return;
+        };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Could not read the template directory {:?}. Got error {}", dir, e);
+                return;
+            }
+        };
+        for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+            let Some(layout) = path.file_stem().map(|stem| stem.to_string_lossy().to_string())
+            else {
+                continue;
+            };
+            if layout == DEFAULT_LAYOUT || path.extension().is_none_or(|ext| ext != "html") {
+                continue;
+            }
+            let added = utils::read_file_to_str(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| {
+                    self.note_template
+                        .add_layout(&layout, source)
+                        .map_err(|e| e.to_string())
+                });
+            match added {
+                Ok(()) => info!("Loaded layout `{}` from {:?}", layout, path),
+                Err(e) => warn!("Could not load layout {:?}. Got error {}", path, e),
+            }
+        }
+    }
+
     fn generate_archive_page_from_vec(&self, notes: &mut Vec<note::Note<'_>>) {
         for note in &mut *notes {
             note.cache_creation_time(self.cfg.creation_date.use_git);
         }
 
-        let archive_html = archive::generate_archive_page_html(
+        match archive::generate_archive_page_html(
             notes,
             self.input_dir,
             Path::new(TAG_DIR),
             &self.note_template,
-        );
-        write_to_file(&self.get_archive_dir(), &archive_html)
-            .expect("Couldn't write archive page.");
+        ) {
+            Ok(archive_html) => self.write_output(&self.get_archive_dir(), &archive_html),
+            Err(e) => self.report_write_failure(&self.get_archive_dir(), e),
+        }
     }
 
     fn get_archive_dir(&self) -> PathBuf {
         self.output_dir.join("archive.html")
     }
 
+    ///Write the graph of the notes as json, and the page that shows it.
+    fn generate_graph_page_from_vec(&self, notes: &mut Vec<note::Note<'_>>) {
+        for note in &mut *notes {
+            note.cache_creation_time(self.cfg.creation_date.use_git);
+        }
+        let graph = Graph::from_notes(notes, self.input_dir, self.cfg.graph.show_tags);
+        let json_string = serde_json::to_string(&graph).expect("Serialization of graph failed.");
+        let data_path = self.output_static_path().join("js").join("graph.json");
+        self.write_output(&data_path, &json_string);
+
+        let page_path = self.output_dir.join("graph.html");
+        let data_url = self.output_url(&data_path);
+        let script_url = self.graph_script_url();
+        match graph::generate_graph_page_html(&data_url, &script_url, &self.note_template) {
+            Ok(graph_html) => self.write_output(&page_path, &graph_html),
+            Err(e) => self.report_write_failure(&page_path, e),
+        }
+    }
+
+    ///Write a file of the website. Failures are recorded in the diagnostics.
+    fn write_output(&self, path: &Path, content: &str) {
+        if let Err(e) = write_to_file(path, content) {
+            self.report_write_failure(path, e);
+        }
+    }
+
+    fn report_write_failure(&self, path: &Path, error: impl std::fmt::Display) {
+        self.diagnostics.report(
+            IssueKind::WriteFailure,
+            relative_to(path, self.output_dir),
+            None,
+            format!("Could not write the file: {}", error),
+        );
+    }
+
     fn setup_template(&mut self) {
         info!("Loading template ...");
         let mut subtime = Instant::now();
         if let Some(template_from_file) = self.load_template() {
-            self.note_template = template_from_file;
+            match PageTemplate::new(template_from_file) {
+                Ok(template) => self.note_template = template,
+                Err(e) => warn!("Could not load the template. Using the default one. Got error {}", e),
+            }
         };
+        self.load_layouts();
+        self.note_template.set_global("config", self.cfg);
         info!("Loaded template in {:?}", Instant::now() - subtime);
 
         // Add math support
+        self.math = None;
         if self.cfg.math.enable {
             subtime = Instant::now();
-            info!("Constructing math configuration script");
-            self.generate_math_config_script()
-                .expect("Failed to create the math configuration script.");
+            let result = match MathRenderer::new(self.cfg.math.prerender) {
+                Some(renderer) => {
+                    info!("Setting up the rendering of math");
+                    self.setup_math_renderer(renderer)
+                }
+                None => {
+                    info!("Constructing math configuration script");
+                    self.generate_math_config_script()
+                }
+            };
+            match result {
+                Ok(()) => {}
+                Err(PreambleError::PreambleReadError(e)) => self.diagnostics.report(
+                    IssueKind::InvalidPreamble,
+                    self.cfg.math.preamble_path.clone().unwrap_or_default(),
+                    None,
+                    format!("Could not read the preamble, so its macros are left out: {}", e),
+                ),
+                Err(PreambleError::FileWriteError(e)) => self.report_write_failure(
+                    &self.output_static_path().join("js").join("math_cfg.js"),
+                    e,
+                ),
+            }
             info!("Converted preamble in {:?}", Instant::now() - subtime);
         }
         self.set_math_loading_snip();
         self.set_mermaid_loading_snip();
+        self.set_callout_style();
 
         if self.cfg.search.enable {
             subtime = Instant::now();
@@ -314,15 +771,13 @@ impl<'a> Exporter<'a> {
         // ----------------
         info!("Listing all the notes in {:?}", self.input_dir);
         let mut subtime = Instant::now();
+        self.diagnostics.clear();
         //let ignored = Self::get_excluded(self.input_dir, self.cfg);
-        let ignored = self.get_excluded();
-        debug!("Ignoring the following directories:\n{:?}", ignored);
+        debug!("Ignoring the following directories:\n{:?}", self.get_excluded());
         //let mut iter_notes: Vec<note::Note> = iter_notes(&self.input_dir, &ignored).collect();
-        let mut all_notes = get_all_notes(
-            self.input_dir,
-            &ignored,
-            self.cfg.performance.search_for_links,
-        );
+        // Only the metadata of the notes is kept, their content is loaded again to render them.
+        let mut all_notes = self.load_note_metadata();
+        self.report_broken_links(&all_notes);
         info!("Loaded all notes in {:?}", Instant::now() - subtime);
 
         // Generate backlinks
@@ -360,22 +815,67 @@ impl<'a> Exporter<'a> {
             info!("Generated archive page in {:?}", Instant::now() - subtime)
         }
 
-        // Compile the notes
-        // -----------------
+        // Generate a graph page
+        // ---------------------
+        if self.cfg.generate_graph {
+            info!("Generate graph page.");
+            subtime = Instant::now();
+            self.generate_graph_page_from_vec(&mut all_notes);
+            info!("Generated graph page in {:?}", Instant::now() - subtime)
+        }
 
-        subtime = Instant::now();
-        info!("Compiling the notes ...");
-        self.compile_notes_from_vec(&mut all_notes, &backlinks);
-        info!("Compiled all notes in {:?}", Instant::now() - subtime);
+        // Compare with the previous build
+        // -------------------------------
+        let mut manifest = Manifest::new(&self.fingerprint(), self.cfg, &all_notes, self.input_dir);
+        self.up_to_date.clear();
+        let previous = Manifest::load(self.output_dir);
+        if let Some(previous) = &previous {
+            self.remove_vanished_pages(previous, &manifest);
+        }
+        if self.cfg.performance.skip_unchanged_notes {
+            if let Some(previous) = previous {
+                let mut outdated = manifest.outdated_notes(&previous);
+                if self.cfg.graph.local_depth > 0 {
+                    outdated.extend(
+                        manifest.outdated_local_graphs(&previous, self.cfg.graph.local_depth),
+                    );
+                }
+                self.up_to_date = manifest
+                    .notes
+                    .keys()
+                    .filter(|key| !outdated.contains(*key))
+                    .cloned()
+                    .collect();
+            }
+        }
 
-        // Create search index
-        // -------------------
+        // Compile and index the notes
+        // ---------------------------
 
+        subtime = Instant::now();
+        info!("Compiling the notes ...");
+        let failed;
         if self.cfg.performance.build_search_index {
+            let analyzers = Analyzers::from_config(&self.cfg.search, self.input_dir);
+            let mut search_index = SearchIndex::new(&self.cfg.search.weights);
+            let indexing = Some((&mut search_index, &analyzers));
+            failed = self.compile_notes_from_vec(&all_notes, &backlinks, indexing);
+            info!("Compiled and indexed all notes in {:?}", Instant::now() - subtime);
+
             subtime = Instant::now();
-            info!("Creating search index ...");
-            self.create_search_index(&mut all_notes);
-            info!("Created search index in {:?}", Instant::now() - subtime);
+            info!("Saving search index ...");
+            self.save_search_index(search_index);
+            info!("Saved search index in {:?}", Instant::now() - subtime);
+        } else {
+            failed = self.compile_notes_from_vec(&all_notes, &backlinks, None);
+            info!("Compiled all notes in {:?}", Instant::now() - subtime);
+        }
+        // Notes that failed are not up to date, so the next build tries them again.
+        for path in failed {
+            manifest.forget(&manifest_key(&path, self.input_dir));
+        }
+        if let Err(e) = manifest.save(self.output_dir) {
+            warn!("Could not save the build manifest. Got error {}", e);
         }
 
         // Copy over all the static files
@@ -390,35 +890,58 @@ impl<'a> Exporter<'a> {
         backlinks
     }
 
-    fn create_search_index(&self, notes: &[note::Note]) {
-        let stopwords: Vec<&str> = STOPWORDS.lines().collect();
-        let search_index: Vec<SearchEntry> = notes
-            .iter()
-            .map(|note| {
-                SearchEntry::new(
-                    note,
-                    stopwords.iter(),
-                    Some(self.cfg.search.max_len),
-                    self.input_dir,
-                )
-            })
-            .collect();
+    fn save_search_index(&mut self, search_index: SearchIndex) {
+        let js_dir = self.output_dir.join("static").join("js");
+        // Shards of a previous build may have other prefixes.
+        let shard_dir = js_dir.join(SHARD_DIR);
+        if shard_dir.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&shard_dir) {
+                self.report_write_failure(&shard_dir, e);
+            }
+        }
+        let (search_index, shards) = match self.cfg.search.shard_prefix {
+            0 => (search_index, vec![]),
+            prefix_len => search_index.into_shards(prefix_len),
+        };
 
-        // Serialize the Vec to a JSON string
-        let json_string =
-            serde_json::to_string(&search_index).expect("Serialization of search index failed.");
-
-        // Write the JSON string to a file
-        let size = write_to_file(
-            &self
-                .output_dir
-                .join("static")
-                .join("js")
-                .join("search_index.json"),
-            &json_string,
-        )
-        .expect("Could not write search index to file.");
-        info!("Saved search index of {:2.2}kb", (size as f64) / 1024.);
+        let index_file = js_dir.join("search_index.json");
+        let mut files: Vec<IndexFileSize> =
+            self.write_search_file(&index_file, &search_index).into_iter().collect();
+        for (n, shard) in shards.iter().enumerate() {
+            files.extend(self.write_search_file(&shard_dir.join(format!("{}.json", n)), shard));
+        }
+        let size: usize = files.iter().map(|file| file.size).sum();
+        info!(
+            "Saved search index of {:2.2}kb in {} file(s)",
+            utils::byte_to_kb(size),
+            files.len()
+        );
+        self.stats.search_index = files;
+    }
+
+    /// Write a file of the search index as json, and a compressed copy if enabled.
+    fn write_search_file<T: Serialize>(&self, path: &Path, contents: &T) -> Option<IndexFileSize> {
+        let json = serde_json::to_string(contents).expect("Serialization of search index failed.");
+        let codec = self.cfg.search.compression.codec();
+        let written = remove_compressed_copies(path, codec)
+            .and_then(|_| write_to_file(path, &json))
+            .and_then(|size| {
+                let compressed = codec
+                    .map(|codec| write_compressed(path, json.as_bytes(), codec))
+                    .transpose()?;
+                Ok((size, compressed))
+            });
+        match written {
+            Ok((size, compressed)) => Some(IndexFileSize {
+                path: relative_to(path, self.output_dir),
+                size,
+                compressed,
+            }),
+            Err(e) => {
+                self.report_write_failure(path, e);
+                None
+            }
+        }
     }
 
     fn process_tags_from_vec(&mut self, notes: &Vec<note::Note>) {
@@ -438,14 +961,68 @@ impl<'a> Exporter<'a> {
         }
         if self.cfg.generate_tag_index {
             subtime = Instant::now();
+            // Tags of notes that were removed or unpublished since the last build keep no page.
+            self.remove_tag_indices();
             self.generate_tag_indices(&tags);
             info!("Generated tag indices in {:?}", Instant::now() - subtime);
         }
     }
 
     fn generate_tag_indices(&self, tags: &Tree) {
-        tags.build_index_pages(&self.output_dir, &Path::new(TAG_DIR), &self.note_template)
-            .expect("Failed to generate tag index pages");
+        if let Err(e) =
+            tags.build_index_pages(self.output_dir, Path::new(TAG_DIR), &self.note_template)
+        {
+            self.report_write_failure(&self.output_dir.join(TAG_DIR), e);
+        }
+    }
+
+    ///Remove the pages of the notes of the previous build that are no longer built, because they
+    ///were removed or are no longer published.
+    fn remove_vanished_pages(&self, previous: &Manifest, manifest: &Manifest) {
+        for key in previous.notes.keys().filter(|key| !manifest.notes.contains_key(*key)) {
+            let source = self.input_dir.join(key.strip_prefix("/").unwrap_or(key));
+            let page = self.input_to_output(&source, Some("html"));
+            if page.is_file() && filesys::remove_file(&page).is_err() {
+                warn!("Couldn't remove {:?}", page);
+            }
+        }
+    }
+
+    ///Remove the tag index pages of a previous export, so tags that no longer occur in any
+    ///note don't keep their page.
+    fn remove_tag_indices(&self) {
+        let tag_pages = WalkDir::new(self.output_dir.join(TAG_DIR))
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                entry.file_type().is_file()
+                    && entry.file_name().to_string_lossy().starts_with("tag-")
+                    && entry.path().extension().is_some_and(|ext| ext == "html")
+            });
+        for page in tag_pages {
+            if filesys::remove_file(page.path()).is_err() {
+                warn!("Couldn't remove tag page {:?}", page.path());
+            }
+        }
+    }
+
+    ///Regenerate the tag index pages, the archive page and the graph page from the given notes,
+    ///e.g. after a note was renamed or removed.
+    pub(crate) fn regenerate_overview_pages(&self, notes: &mut Vec<note::Note<'_>>) {
+        if self.cfg.generate_tag_index {
+            info!("Regenerating tag indices ...");
+            self.remove_tag_indices();
+            let tags = self.generate_tag_tree_from_notes(notes);
+            self.generate_tag_indices(&tags);
+        }
+        if self.cfg.generate_archive {
+            info!("Regenerating archive page ...");
+            self.generate_archive_page_from_vec(notes);
+        }
+        if self.cfg.generate_graph {
+            info!("Regenerating graph page ...");
+            self.generate_graph_page_from_vec(notes);
+        }
     }
 
     fn output_static_path(&self) -> PathBuf {
@@ -455,6 +1032,21 @@ impl<'a> Exporter<'a> {
         self.output_dir.join("static")
     }
 
+    ///The url of a file in the output directory, relative to the root of the website.
+    fn output_url(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(self.output_dir).unwrap_or(path);
+        let components: Vec<_> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        components.join("/")
+    }
+
+    ///The url of the script that draws the graphs, as saved by [Exporter::save_default_js].
+    fn graph_script_url(&self) -> String {
+        self.output_url(&self.output_static_path().join("js").join("graph.js"))
+    }
+
     fn save_static_text<T: AsRef<Path>>(&self, content: &str, path: T) {
         let output = self.output_static_path().join(path);
         match write_to_file(&output, content) {
@@ -509,6 +1101,7 @@ impl<'a> Exporter<'a> {
         self.save_javascript(DARKMODE_SCRIPT, "toggle_darkmode.js");
         self.save_javascript(FOUC_SCRIPT, "fix_fouc.js");
         self.save_javascript(BROKEN_LINKS, "disable_broken_links.js");
+        self.save_javascript(GRAPH_SCRIPT, "graph.js");
     }
 
     fn save_default_css(&self) {
@@ -558,7 +1151,7 @@ impl<'a> Exporter<'a> {
     }
 
     fn add_backlinks_to_note<'b>(
-        &mut self,
+        &self,
         new_note: &mut note::Note<'b>,
         backlinks: &'b Backlinks,
     ) {
@@ -598,8 +1191,8 @@ impl<'a> Exporter<'a> {
         if !self.cfg.performance.skip_unchanged_notes {
             return false;
         }
-        // Otherwise, check if has not been changed. If that check fails, just don't skip it.
-        crate::utils::filesys::is_older(source_path, dst_path).unwrap_or(false)
+        // Otherwise, check if the previous build is still up to date.
+        dst_path.exists() && self.up_to_date.contains(&manifest_key(source_path, self.input_dir))
     }
 
     /// Check if copying the target of the given link should be skipped.
@@ -614,15 +1207,58 @@ impl<'a> Exporter<'a> {
     }
 
     pub fn compile_note<'b>(&mut self, new_note: &mut note::Note<'b>, backlinks: &'b Backlinks) {
+        let links = self.link_map(backlinks);
+        self.compile_note_inner(new_note, backlinks, links.as_ref(), false);
+    }
+
+    /// Compile the given note, even if it has not changed since it was last exported.
+    pub fn recompile_note<'b>(&mut self, new_note: &mut note::Note<'b>, backlinks: &'b Backlinks) {
+        let links = self.link_map(backlinks);
+        self.compile_note_inner(new_note, backlinks, links.as_ref(), true);
+    }
+
+    /// The links between the notes, if they are needed for local graphs.
+    pub(crate) fn link_map(&self, backlinks: &Backlinks) -> Option<LinkMap> {
+        (self.cfg.graph.local_depth > 0).then(|| LinkMap::from_backlinks(backlinks))
+    }
+
+    fn compile_note_inner<'b>(
+        &mut self,
+        new_note: &mut note::Note<'b>,
+        backlinks: &'b Backlinks,
+        links: Option<&LinkMap>,
+        force: bool,
+    ) {
+        let mut attachments = Attachments::new();
+        if let Some(output_path) = self.plan_note(new_note, force, &mut attachments) {
+            let ignored = self.get_excluded();
+            self.render_note(new_note, &output_path, backlinks, links, &ignored);
+        }
+        self.transfer_linked_files(attachments);
+    }
+
+    ///Count the note and its attachments in the stats, and add the attachments that need to be
+    ///copied to `attachments`. Returns the output path of the note, unless it is up to date.
+    fn plan_note(
+        &mut self,
+        new_note: &note::Note,
+        force: bool,
+        attachments: &mut Attachments,
+    ) -> Option<PathBuf> {
         self.stats.note_count += 1;
 
         let output_path = self.input_to_output(&new_note.path, Some("html"));
-        let skip_note = self.should_skip_note(&new_note.path, &output_path);
+        let skip_note = !force && self.should_skip_note(&new_note.path, &output_path);
 
-        for link in new_note.links.iter().filter(|l| l.is_attachment) {
+        for link in new_note
+            .links
+            .iter()
+            .filter(|l| l.is_attachment && l.link_type() != LinkType::Embed)
+        {
             self.stats.attachment_count += 1;
             if !self.should_skip_attachment(&link) {
-                self.transfer_linked_file(&link);
+                let (input_path, output_path) = self.get_paths_of_linked_attach(link);
+                attachments.insert(output_path, input_path);
             } else {
                 self.stats.skipped_attachments += 1;
             }
@@ -630,24 +1266,72 @@ impl<'a> Exporter<'a> {
 
         if skip_note {
             self.stats.skipped_notes += 1;
-            return;
+            return None;
         }
+        Some(output_path)
+    }
 
+    ///Render the note to `output_path`. Returns whether its page was written; failures are
+    ///recorded in the diagnostics.
+    fn render_note<'b>(
+        &self,
+        new_note: &mut note::Note<'b>,
+        output_path: &Path,
+        backlinks: &'b Backlinks,
+        links: Option<&LinkMap>,
+        ignored: &Vec<PathBuf>,
+    ) -> bool {
         debug!("Exporting note {:?}", new_note.path);
         self.add_backlinks_to_note(new_note, backlinks);
+        if let Some(links) = links {
+            let local_graph =
+                links.local_graph(new_note, self.input_dir, self.cfg.graph.local_depth);
+            new_note.set_local_graph(local_graph.to_local_html(&self.graph_script_url()));
+        }
 
-        new_note
-            .to_html(&output_path, &self.note_template)
-            .expect("Failed to export note");
+        let mut embeds = EmbedContext::new(
+            self.input_dir,
+            self.cfg.performance.search_for_links,
+            ignored,
+            &self.aliases,
+            &self.unpublished,
+        )
+        .with_math(self.math.as_ref());
+        if let Err(e) = new_note.to_html(output_path, &self.note_template, &mut embeds) {
+            self.diagnostics.report(
+                IssueKind::WriteFailure,
+                relative_to(&new_note.path, self.input_dir),
+                None,
+                format!("Could not write the page of the note: {}", e),
+            );
+            return false;
+        }
+        true
+    }
+
+    ///Record the commands of the preamble that can't be parsed in the diagnostics.
+    fn report_preamble_errors(&self, preamble_path: &Path, errors: Vec<(usize, String)>) {
+        for (line, error) in errors {
+            self.diagnostics.report(
+                IssueKind::InvalidPreamble,
+                preamble_path,
+                Some(line),
+                format!("Could not parse the command, so it is left out: {}", error),
+            );
+        }
     }
 
     ///Generate some javascript to load the math rendering engine.
-    fn generate_math_config_script(&self) -> Result<(), super::errors::PreambleError> {
+    ///Commands of the preamble that can't be parsed are left out, and recorded in the
+    ///diagnostics.
+    fn generate_math_config_script(&self) -> Result<(), PreambleError> {
         let mut preamble_html = "".to_string();
         if let Some(preamble_path) = &self.cfg.math.preamble_path {
             info!("Converting preamble {}", preamble_path.to_string_lossy());
             let preamble = utils::read_file_to_str(self.input_dir.join(preamble_path))?;
-            preamble_html = self.cfg.math.engine.preamble_to_html(&preamble);
+            let errors;
+            (preamble_html, errors) = self.cfg.math.engine.preamble_to_html(&preamble);
+            self.report_preamble_errors(preamble_path, errors);
         } else {
             info!("No preamble path was provided.")
         }
@@ -663,23 +1347,62 @@ impl<'a> Exporter<'a> {
         Ok(())
     }
 
+    ///Everything besides the notes that the pages depend on: the template, and the macros of
+    ///the preamble when math is rendered while building.
+    fn fingerprint(&self) -> String {
+        let mut fingerprint = self.note_template.fingerprint();
+        if let Some(math) = &self.math {
+            for (name, expansion) in math.macros() {
+                fingerprint.push_str(&format!("\n{}: {}", name, expansion));
+            }
+        }
+        fingerprint
+    }
+
+    ///Use `renderer` to render the math of the notes, with the macros of the preamble.
+    ///Commands of the preamble that can't be parsed are left out, and recorded in the
+    ///diagnostics.
+    fn setup_math_renderer(&mut self, mut renderer: MathRenderer) -> Result<(), PreambleError> {
+        if let Some(preamble_path) = &self.cfg.math.preamble_path {
+            info!("Reading the macros of preamble {}", preamble_path.to_string_lossy());
+            let preamble = utils::read_file_to_str(self.input_dir.join(preamble_path))?;
+            let (macros, errors) = preamble_macros(&preamble);
+            for (name, expansion) in macros {
+                renderer.add_macro(&name, &expansion);
+            }
+            self.report_preamble_errors(preamble_path, errors);
+        }
+        self.math = Some(renderer);
+        Ok(())
+    }
+
     ///Translate a given path from the input directory to output directory.
     ///Besides replacing the base directory, also slugify the path.
     pub fn input_to_output(&self, path: &Path, extension: Option<&str>) -> PathBuf {
         debug!("Before slug: {}", path.to_string_lossy());
         debug!("input dir: {}", self.input_dir.to_string_lossy());
 
-        let output_path = self.input_dir.join(
-            self.slugify_path(&relative_to(&path, &self.input_dir), extension)
-                .expect("Could not slugify path."),
-        );
+        let relative = relative_to(path, self.input_dir);
+        let slugged = self.slugify_path(&relative, extension).unwrap_or_else(|e| {
+            self.diagnostics.report(
+                IssueKind::InvalidPath,
+                relative.clone(),
+                None,
+                format!("{} The path is used as it is.", e),
+            );
+            match extension {
+                Some(extension) => relative.with_extension(extension),
+                None => relative.clone(),
+            }
+        });
+        let output_path = self.input_dir.join(slugged);
         debug!("After slug: {}", output_path.to_string_lossy());
-        utils::move_to(&output_path, &self.input_dir, &self.output_dir)
+        utils::move_to(&output_path, self.input_dir, self.output_dir)
             .unwrap_or_else(|_| self.output_dir.join(&output_path))
     }
 
     ///Get the source and destination files for the linked attachment.
-    fn get_paths_of_linked_attach(&self, link: &Link) -> (PathBuf, PathBuf) {
+    pub(crate) fn get_paths_of_linked_attach(&self, link: &Link) -> (PathBuf, PathBuf) {
         let output_path = self.input_to_output(&link.target, None);
 
         let input_path = match &self.cfg.attachment_dir {
@@ -689,26 +1412,506 @@ impl<'a> Exporter<'a> {
         (input_path, output_path)
     }
 
-    fn transfer_linked_file(&mut self, link: &Link) {
-        // Only move linked attachments
-        if !link.is_attachment {
+    ///Copy the attachments, keyed by their output path, to the output directory.
+    fn transfer_linked_files(&self, attachments: Attachments) {
+        self.install(|| {
+            attachments.into_par_iter().for_each(|(output_path, input_path)| {
+                self.transfer_linked_file(&input_path, &output_path)
+            })
+        });
+    }
+
+    fn transfer_linked_file(&self, input_path: &Path, output_path: &Path) {
+        if !input_path.exists() {
+            // Like a broken link, this leaves a link on the website that goes nowhere.
+            self.diagnostics.add(
+                Issue::new(
+                    IssueKind::MissingAttachment,
+                    relative_to(input_path, self.input_dir),
+                    None,
+                    "A note links to this attachment, which does not exist".to_string(),
+                )
+                .with_severity(Severity::Warning),
+            );
             return;
         }
+        let copied = match output_path.parent() {
+            Some(container) => filesys::create_dir_if_not_exists(container),
+            None => Ok(()),
+        }
+        .and_then(|_| std::fs::copy(input_path, output_path));
+        if let Err(e) = copied {
+            self.report_write_failure(output_path, e);
+        }
+    }
+}
 
-        let (input_path, output_path) = self.get_paths_of_linked_attach(link);
-        if let Some(container) = &output_path.parent() {
-            filesys::create_dir_if_not_exists(container).unwrap_or_else(|e| {
-                warn!(
-                    "Could not create the containing directory for attachment {:?}! got error {:?}",
-                    output_path, e
-                );
-            });
+#[cfg(test)]
+mod tests {
+    use super::{Exporter, NOTES_PER_THREAD};
+    use crate::exporting::check::{Issue, IssueKind};
+    use crate::exporting::config::{
+        Compression, ExportConfig, GraphConfig, MathConfig, MathPrerender, PerformanceConfig,
+        SearchConfig,
+    };
+    use crate::exporting::manifest::Manifest;
+    use crate::exporting::search::{Analyzers, SearchIndex};
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+    use walkdir::WalkDir;
+
+    /// The contents of all files in `dir`, by their path relative to it.
+    fn files(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        WalkDir::new(dir)
+            .into_iter()
+            .map(Result::unwrap)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| {
+                let path = entry.path().strip_prefix(dir).unwrap().to_path_buf();
+                (path, std::fs::read(entry.path()).unwrap())
+            })
+            .collect()
+    }
+
+    fn assert_same_files(expected: &Path, actual: &Path) {
+        let (expected, actual) = (files(expected), files(actual));
+        assert!(expected.keys().eq(actual.keys()));
+        for (path, content) in &expected {
+            assert!(actual[path] == *content, "{} differs", path.display());
         }
-        if let Err(err) = std::fs::copy(&input_path, &output_path) {
-            warn!(
-                "Could not copy the attachment from {:?} to {:?}! Got error {:?}",
-                input_path, output_path, err
+    }
+
+    /// A vault of `count` notes that link to each other and share tags.
+    fn write_vault(input: &Path, count: usize) {
+        std::fs::create_dir_all(input.join("sub")).unwrap();
+        for n in 0..count {
+            let dir = if n % 3 == 0 {
+                input.join("sub")
+            } else {
+                input.to_path_buf()
+            };
+            let content = format!(
+                "# Note {n}\n\nLinks to [[note{}]] and [[note{}#Note]]. #tag{}\n\nSome words about topic {n}.\n",
+                (n + 1) % count,
+                (n * 7) % count,
+                n % 4,
             );
+            std::fs::write(dir.join(format!("note{}.md", n)), content).unwrap();
+        }
+    }
+
+    fn export_with_threads(input: &Path, output: &Path, threads: usize) -> super::ExportStats {
+        let cfg = ExportConfig {
+            performance: PerformanceConfig {
+                threads,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut exporter = Exporter::new(input, output, &cfg);
+        exporter.export();
+        assert!(exporter.diagnostics().issues().is_empty());
+        exporter.stats
+    }
+
+    #[test]
+    fn unpublished_notes_are_left_out() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("index.md"), "See [[secret]] and [[draft|the draft]].\n").unwrap();
+        std::fs::write(input.join("secret.md"), "---\npublish: false\n---\n[[index]]\n").unwrap();
+        std::fs::write(input.join("draft.md"), "---\ndraft: true\n---\n#wip [[index]]\n").unwrap();
+        let output = dir.path().join("out");
+
+        let mut cfg = ExportConfig::default();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        exporter.export();
+        let index = std::fs::read_to_string(output.join("index.html")).unwrap();
+        assert!(index.contains("See secret and the draft."));
+        assert!(!index.contains("backlinks"));
+        assert!(!output.join("secret.html").exists());
+        assert!(!output.join("draft.html").exists());
+        assert!(!output.join("tags").join("tag-wip.html").exists());
+        assert!(exporter.diagnostics().issues().is_empty());
+
+        cfg.include_drafts = true;
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        exporter.export();
+        let index = std::fs::read_to_string(output.join("index.html")).unwrap();
+        assert!(index.contains("<a href=\"draft.html\">the draft</a>"));
+        assert!(output.join("draft.html").exists());
+        assert!(!output.join("secret.html").exists());
+
+        cfg.include_drafts = false;
+        Exporter::new(&input, &output, &cfg).export();
+        assert!(!output.join("draft.html").exists());
+    }
+
+    #[test]
+    fn graph_files_are_in_the_static_directory() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("a.md"), "[[b]]\n").unwrap();
+        std::fs::write(input.join("b.md"), "B\n").unwrap();
+        let output = dir.path().join("out");
+
+        let cfg = ExportConfig {
+            static_dir: Some("assets".into()),
+            generate_graph: true,
+            graph: GraphConfig {
+                local_depth: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        Exporter::new(&input, &output, &cfg).export();
+        assert!(output.join("assets").join("js").join("graph.json").is_file());
+        assert!(output.join("assets").join("js").join("graph.js").is_file());
+        let page = std::fs::read_to_string(output.join("graph.html")).unwrap();
+        assert!(page.contains("data-graph-src=\"assets/js/graph.json\""));
+        assert!(page.contains("<script src=\"assets/js/graph.js\""));
+        let note = std::fs::read_to_string(output.join("a.html")).unwrap();
+        assert!(note.contains("<script src=\"assets/js/graph.js\""));
+    }
+
+    #[test]
+    fn failed_notes_are_left_out_of_the_manifest() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("a.md"), "A\n").unwrap();
+        std::fs::write(input.join("b.md"), "B\n").unwrap();
+        let output = dir.path().join("out");
+        // The page of `b` can't be written where a directory is.
+        std::fs::create_dir_all(output.join("b.html")).unwrap();
+
+        let cfg = ExportConfig::default();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        exporter.export();
+        let issues = exporter.diagnostics().issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::WriteFailure);
+        assert_eq!(issues[0].path, Path::new("b.md"));
+        let manifest = Manifest::load(&output).unwrap();
+        assert!(manifest.notes.contains_key(Path::new("a.md")));
+        assert!(!manifest.notes.contains_key(Path::new("b.md")));
+
+        // The next build renders it again, even though it did not change.
+        std::fs::remove_dir(output.join("b.html")).unwrap();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        exporter.export();
+        assert!(output.join("b.html").is_file());
+        assert_eq!(exporter.stats.skipped_notes, 1);
+        assert!(Manifest::load(&output).unwrap().notes.contains_key(Path::new("b.md")));
+    }
+
+    #[test]
+    fn compressed_copies_of_other_builds_are_removed() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("a.md"), "Some words to index.\n").unwrap();
+        let output = dir.path().join("out");
+        let index = output.join("static").join("js").join("search_index.json");
+        let copy = |extension: &str| index.with_extension(format!("json.{}", extension));
+
+        let export = |compression| {
+            let cfg = ExportConfig {
+                search: SearchConfig {
+                    compression,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut exporter = Exporter::new(&input, &output, &cfg);
+            exporter.export();
+            assert!(exporter.diagnostics().issues().is_empty());
+            exporter.stats
+        };
+        let stats = export(Compression::Gzip);
+        assert!(copy("gz").is_file());
+        let files = stats.search_index_files();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].compressed.is_some());
+        assert!(stats.to_string().contains("static/js/search_index.json: "));
+
+        export(Compression::Brotli);
+        assert!(!copy("gz").exists());
+        assert!(copy("br").is_file());
+
+        let stats = export(Compression::None);
+        assert!(index.is_file());
+        assert!(!copy("gz").exists());
+        assert!(!copy("br").exists());
+        assert!(!stats.to_string().contains("compressed"));
+    }
+
+    #[test]
+    fn output_does_not_depend_on_the_number_of_threads() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        write_vault(&input, 20);
+
+        let single = dir.path().join("single");
+        let single_stats = export_with_threads(&input, &single, 1);
+        let multi = dir.path().join("multi");
+        let multi_stats = export_with_threads(&input, &multi, 4);
+
+        assert_same_files(&single, &multi);
+        assert_eq!(single_stats.note_count, multi_stats.note_count);
+        assert_eq!(single_stats.skipped_notes, multi_stats.skipped_notes);
+        assert_eq!(single_stats.attachment_count, multi_stats.attachment_count);
+        assert_eq!(
+            single_stats.skipped_attachments,
+            multi_stats.skipped_attachments
+        );
+        assert_eq!(single_stats.search_index, multi_stats.search_index);
+    }
+
+    #[test]
+    fn notes_are_rendered_and_indexed_in_chunks() {
+        // One chunk on four threads, four chunks on a single thread.
+        let count = 3 * NOTES_PER_THREAD + 1;
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        write_vault(&input, count);
+
+        let baseline = dir.path().join("baseline");
+        export_with_threads(&input, &baseline, 4);
+        let chunked = dir.path().join("chunked");
+        export_with_threads(&input, &chunked, 1);
+        assert_same_files(&baseline, &chunked);
+
+        // The documents are in the order of the notes, as if they were indexed at once.
+        let cfg = ExportConfig::default();
+        let notes = Exporter::new(&input, &baseline, &cfg).load_all_notes();
+        let analyzers = Analyzers::from_config(&cfg.search, &input);
+        let expected = SearchIndex::from_notes(&notes, &analyzers, &cfg.search, &input);
+        let json = chunked.join("static").join("js").join("search_index.json");
+        let written: SearchIndex =
+            serde_json::from_str(&std::fs::read_to_string(json).unwrap()).unwrap();
+        // Every note has a document for its heading, and one for the text above it.
+        assert_eq!(written.docs.len(), 2 * count);
+        assert_eq!(written.docs, expected.docs);
+    }
+
+    #[test]
+    fn notes_that_cannot_be_read_again_are_left_out() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        write_vault(&input, 2 * NOTES_PER_THREAD);
+        let output = dir.path().join("out");
+
+        let cfg = ExportConfig {
+            performance: PerformanceConfig {
+                threads: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        let notes = exporter.load_note_metadata();
+        let backlinks = exporter.generate_backlinks_from_notes(&notes);
+        // The note is changed into invalid UTF-8 after its metadata was loaded.
+        let unreadable = input.join("note5.md");
+        std::fs::write(&unreadable, [0xff, 0xfe, 0xfd]).unwrap();
+
+        let analyzers = Analyzers::from_config(&cfg.search, &input);
+        let mut index = SearchIndex::new(&cfg.search.weights);
+        let failed =
+            exporter.compile_notes_from_vec(&notes, &backlinks, Some((&mut index, &analyzers)));
+        assert_eq!(failed, vec![unreadable]);
+        let issues = exporter.diagnostics().issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::UnreadableNote);
+        assert_eq!(issues[0].path, Path::new("note5.md"));
+
+        assert!(!output.join("note5.html").exists());
+        assert!(output.join("note4.html").is_file());
+        assert!(output.join("note7.html").is_file());
+        assert_eq!(index.docs.len(), 2 * (notes.len() - 1));
+        assert!(index.docs.iter().all(|doc| doc.title != "note5"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_that_cannot_be_written_are_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("a.md"), "A\n").unwrap();
+        let output = dir.path().join("out");
+        let js_dir = output.join("static").join("js");
+        std::fs::create_dir_all(&js_dir).unwrap();
+        std::fs::set_permissions(&js_dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+        if std::fs::write(js_dir.join("probe"), "").is_ok() {
+            // Root can write to read-only directories, but not to a file where a directory is.
+            std::fs::remove_file(js_dir.join("probe")).unwrap();
+            std::fs::create_dir(js_dir.join("search_index.json")).unwrap();
         }
+
+        let cfg = ExportConfig::default();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        exporter.export();
+        std::fs::set_permissions(&js_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let issues = exporter.diagnostics().issues();
+        let in_js_dir = |issue: &Issue| issue.path.starts_with("static/js");
+        assert!(issues
+            .iter()
+            .all(|issue| issue.kind == IssueKind::WriteFailure && in_js_dir(issue)));
+        let index = Path::new("static/js/search_index.json");
+        assert!(issues.iter().any(|issue| issue.path == index));
+        assert!(output.join("a.html").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_that_cannot_be_read_are_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        let locked = input.join("locked");
+        std::fs::create_dir_all(&locked).unwrap();
+        std::fs::write(input.join("a.md"), "A\n").unwrap();
+        std::fs::write(locked.join("b.md"), "B\n").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        if std::fs::read_dir(&locked).is_ok() {
+            // Root can read it anyway.
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+            return;
+        }
+
+        let output = dir.path().join("out");
+        let cfg = ExportConfig::default();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        exporter.export();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let issues = exporter.diagnostics().issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::UnreadableNote);
+        assert_eq!(issues[0].path, Path::new("locked"));
+        assert!(output.join("a.html").is_file());
+        assert!(!output.join("locked").join("b.html").exists());
+    }
+
+    #[test]
+    fn prerendered_pages_depend_on_the_preamble() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("a.md"), "$\\RR$, [$x$](b.md) and [[b|$y$]]\n").unwrap();
+        std::fs::write(input.join("b.md"), "B\n").unwrap();
+        let preamble = input.join("preamble.sty");
+        std::fs::write(&preamble, "\\newcommand{\\RR}{\\mathbb{R}}\n").unwrap();
+        let output = dir.path().join("out");
+
+        let cfg = ExportConfig {
+            math: MathConfig {
+                preamble_path: Some("preamble.sty".into()),
+                prerender: MathPrerender::Mathml,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let export = || {
+            let mut exporter = Exporter::new(&input, &output, &cfg);
+            exporter.export();
+            assert!(exporter.diagnostics().issues().is_empty());
+            exporter.stats.skipped_notes
+        };
+        assert_eq!(export(), 0);
+        let page = std::fs::read_to_string(output.join("a.html")).unwrap();
+        assert!(page.contains("<mi mathvariant=\"double-struck\">R</mi>"));
+        assert!(page.contains("<a href=\"b.html\"><span class=\"katex\"><math"));
+        assert!(page.contains("<mi>x</mi>"));
+        assert!(page.contains("<mi>y</mi>"));
+        assert!(!page.contains('$'));
+        assert_eq!(export(), 2);
+
+        // The macros are baked into the pages, so they are all rendered again.
+        std::fs::write(&preamble, "\\newcommand{\\RR}{\\mathbf{R}}\n").unwrap();
+        assert_eq!(export(), 0);
+        let page = std::fs::read_to_string(output.join("a.html")).unwrap();
+        assert!(page.contains("<mi mathvariant=\"bold\">R</mi>"));
+    }
+
+    #[test]
+    fn embeds_alone_on_a_line_are_blocks() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("a.md"), "Intro\n\n![[b]]\n\nText ![[b]]\n").unwrap();
+        std::fs::write(input.join("b.md"), "B\n").unwrap();
+        let output = dir.path().join("out");
+
+        Exporter::new(&input, &output, &ExportConfig::default()).export();
+        let page = std::fs::read_to_string(output.join("a.html")).unwrap();
+        assert_eq!(page.matches("<div class=\"embed\"").count(), 2);
+        assert!(page.contains("<p>Intro</p>\n<div class=\"embed\""));
+        assert!(!page.contains("<p><div"));
+        assert!(page.contains("<p>Text "));
+    }
+
+    #[test]
+    fn text_of_notes_is_escaped_in_templates() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        let templates = dir.path().join("templates");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::create_dir_all(&templates).unwrap();
+        let template = "{{title}}|{{#each note.tags}}{{title}}{{/each}}|{{note.frontmatter.author}}\
+                        |{{#each note.backlinks}}{{title}}{{/each}}|{{backlinks}}{{content}}";
+        std::fs::write(templates.join("index.html"), template).unwrap();
+        let frontmatter = "---\nauthor: <script>alert(1)</script>\n---\n";
+        std::fs::write(input.join("a&b.md"), format!("{}#tag **text**\n", frontmatter)).unwrap();
+        std::fs::write(input.join("<i>c.md"), "[[a&b]]\n").unwrap();
+        let output = dir.path().join("out");
+
+        let cfg = ExportConfig {
+            template_dir: Some(templates),
+            ..Default::default()
+        };
+        Exporter::new(&input, &output, &cfg).export();
+        let page = std::fs::read_to_string(output.join("a-b.html")).unwrap();
+        assert!(page.starts_with("a&amp;b|tag|&lt;script&gt;alert(1)&lt;/script&gt;|&lt;i&gt;c"));
+        assert!(!page.contains("<script>"));
+        assert!(!page.contains("<i>"));
+        assert!(page.contains("<strong>text</strong>"));
+    }
+
+    #[test]
+    fn backlinks_are_in_the_same_order_on_the_page_and_in_templates() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        let templates = dir.path().join("templates");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::create_dir_all(&templates).unwrap();
+        let template = "{{#each note.backlinks}}{{title}},{{/each}}|{{backlinks}}";
+        std::fs::write(templates.join("index.html"), template).unwrap();
+        std::fs::write(input.join("target.md"), "Text\n").unwrap();
+        std::fs::write(input.join("b.md"), "[[target|Zebra]]\n").unwrap();
+        std::fs::write(input.join("c.md"), "[[target]]\n").unwrap();
+        let output = dir.path().join("out");
+
+        let cfg = ExportConfig {
+            template_dir: Some(templates),
+            ..Default::default()
+        };
+        Exporter::new(&input, &output, &cfg).export();
+        let page = std::fs::read_to_string(output.join("target.html")).unwrap();
+        let (titles, html) = page.split_once('|').unwrap();
+        let titles: Vec<&str> = titles.split_terminator(',').collect();
+        assert_eq!(titles.len(), 2);
+        let positions: Vec<usize> = titles
+            .iter()
+            .map(|title| html.find(&format!(">{}</a>", title)).unwrap())
+            .collect();
+        assert!(positions[0] < positions[1]);
     }
 }
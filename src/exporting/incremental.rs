@@ -1,16 +1,147 @@
 use crate::utils::filesys::{is_note, relative_to};
-use crate::utils::filesys;
+use crate::utils::{filesys, utils};
 
+use super::check::IssueKind;
 use super::exporter::{Backlinks, Exporter};
 use crate::components::note::Note;
 use notify::{
     event::{ModifyKind, RemoveKind, RenameMode},
     Event, EventKind,
 };
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
-use log::{info, warn};
+use log::{debug, info, warn};
 
 impl<'a> Exporter<'a> {
+    /// Express the path of a note in the same way as the targets of the links in [Backlinks].
+    ///
+    /// Only the parent directory is resolved, so this also works for notes that were removed.
+    fn backlink_key(&self, path: &Path) -> PathBuf {
+        let resolved = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => parent
+                .canonicalize()
+                .map(|dir| dir.join(name))
+                .unwrap_or_else(|_| path.to_path_buf()),
+            _ => path.to_path_buf(),
+        };
+        utils::prepend_slash(relative_to(resolved, self.input_directory()))
+    }
+
+    /// Replace the backlinks that originate from the note at `source` by those of `note`.
+    /// If `note` is `None`, the backlinks from `source` are only removed.
+    ///
+    /// Returns the notes whose set of backlinks changed.
+    fn replace_backlinks_from(
+        &self,
+        backlinks: &mut Backlinks,
+        source: &Path,
+        note: Option<&Note>,
+    ) -> HashSet<PathBuf> {
+        let source = self.backlink_key(source);
+        let is_from_source = |link: &crate::components::link::Link| link.target == source;
+
+        // Remove the old backlinks, but remember what they looked like.
+        let mut previous = Backlinks::new();
+        for (target, referring) in backlinks.iter_mut() {
+            if referring.iter().any(is_from_source) {
+                previous.insert(target.clone(), referring.clone());
+                referring.retain(|link| !is_from_source(link));
+            }
+        }
+
+        if let Some(note) = note {
+            self.update_backlinks(backlinks, note);
+        }
+
+        let mut changed: HashSet<PathBuf> = backlinks
+            .iter()
+            .filter(|(target, referring)| {
+                referring.iter().any(is_from_source) && previous.get(*target) != Some(referring)
+            })
+            .map(|(target, _)| target.clone())
+            .collect();
+        changed.extend(
+            previous
+                .into_iter()
+                .filter(|(target, referring)| backlinks.get(target) != Some(referring))
+                .map(|(target, _)| target),
+        );
+        backlinks.retain(|_, referring| !referring.is_empty());
+        debug!("Backlinks changed for {:?}", changed);
+        changed
+    }
+
+    /// Recompile the notes at the given paths (relative to the input directory), so their
+    /// links and backlinks are up to date.
+    fn rerender_notes(&mut self, targets: HashSet<PathBuf>, backlinks: &Backlinks) {
+        let ignored = self.get_excluded();
+        for target in targets {
+            let path = self.input_directory().join(&target);
+            if !path.is_file() {
+                continue;
+            }
+            match Note::new(
+                path,
+                self.input_directory(),
+                self.config().performance.search_for_links,
+                &ignored,
+            ) {
+                Ok(note) if !note.is_published(self.config().include_drafts) => {}
+                Ok(mut note) => {
+                    self.resolve_aliases(&mut note);
+                    info!("Updating links of {:?}", note.path);
+                    self.recompile_note(&mut note, backlinks);
+                }
+                Err(e) => warn!("Could not load {:?} to update its links: {}", target, e),
+            }
+        }
+    }
+
+    /// Update the website after the note at `from` was renamed to `to`, or removed if `to` is
+    /// `None`.
+    ///
+    /// The metadata of all the notes in the vault is loaded again on every rename or removal,
+    /// since any note may link to the old path (or to the new path, while it didn't exist yet),
+    /// and the tag index, archive and graph pages list all the notes. Only the notes whose links
+    /// or backlinks changed are rendered again.
+    fn handle_note_moved(&mut self, from: &Path, to: Option<&Path>, backlinks: &mut Backlinks) {
+        let original_out = self.input_to_output(from, Some("html"));
+        if filesys::remove_file(&original_out).is_err() {
+            warn!("Couldn't remove {}", original_out.to_string_lossy());
+        }
+
+        let mut notes = self.load_note_metadata();
+        let new_backlinks = self.generate_backlinks_from_notes(&notes);
+
+        let referring_to = |links: &Backlinks, path: &Path| -> Vec<PathBuf> {
+            links
+                .get(path)
+                .map(|referring| referring.iter().map(|link| link.target.clone()).collect())
+                .unwrap_or_default()
+        };
+        let mut affected: HashSet<PathBuf> =
+            referring_to(backlinks, &self.backlink_key(from)).into_iter().collect();
+        if let Some(to) = to {
+            let new_key = self.backlink_key(to);
+            affected.extend(referring_to(&new_backlinks, &new_key));
+            affected.insert(new_key);
+        }
+        affected.extend(
+            backlinks
+                .keys()
+                .chain(new_backlinks.keys())
+                .filter(|target| backlinks.get(*target) != new_backlinks.get(*target))
+                .cloned(),
+        );
+        debug!("Notes affected by the move of {:?}: {:?}", from, affected);
+        *backlinks = new_backlinks;
+
+        // Only the metadata of the notes was loaded, so the affected ones are loaded again.
+        self.rerender_notes(affected, backlinks);
+        self.regenerate_overview_pages(&mut notes);
+    }
+
     fn handle_content_changed(
         &mut self,
         event: Event,
@@ -23,16 +154,54 @@ impl<'a> Exporter<'a> {
                 continue;
             }
             info!("Filechange detected in {:?}", path.to_string_lossy());
-            let mut note = Note::new(
-                relative_to(path, std::env::current_dir().unwrap()),
+            let mut note = match Note::new(
+                relative_to(&path, std::env::current_dir().unwrap()),
                 self.input_directory(),
                 self.config().performance.search_for_links,
                 &ignored,
-            )
-            .unwrap();
+            ) {
+                Ok(note) => note,
+                Err(e) => {
+                    self.diagnostics().report(
+                        IssueKind::UnreadableNote,
+                        relative_to(&path, self.input_directory()),
+                        None,
+                        format!("Could not read the changed note: {}", e),
+                    );
+                    continue;
+                }
+            };
+            let published = note.is_published(self.config().include_drafts);
+            if self.aliases_changed(&note) || published != self.was_published(&note.path) {
+                // Links in other notes may now resolve differently, or be shown as text.
+                info!("Aliases or publication of {:?} changed", note.path);
+                self.handle_note_moved(&path, Some(&path), backlinks);
+                continue;
+            }
+            if !published {
+                info!("{:?} is not published. Ignoring the change.", note.path);
+                continue;
+            }
+            self.resolve_aliases(&mut note);
+            let key = self.backlink_key(&note.path);
+            let depth = self.config().graph.local_depth;
+            let neighbours_before = self
+                .link_map(backlinks)
+                .map(|links| links.neighbourhood(&key.to_string_lossy(), depth));
+            let mut changed = self.replace_backlinks_from(backlinks, &note.path, Some(&note));
+            if !changed.is_empty() {
+                // The local graphs of the notes around this one show its links as well.
+                let neighbours_after = self
+                    .link_map(backlinks)
+                    .map(|links| links.neighbourhood(&key.to_string_lossy(), depth));
+                for neighbours in neighbours_before.into_iter().chain(neighbours_after) {
+                    changed.extend(neighbours.into_iter().map(PathBuf::from));
+                }
+            }
+            changed.remove(&key);
             info!("Recompiling note {:?} at {:?}", note.title, note.path);
-            self.compile_note(&mut note, &backlinks);
-            // TODO -- update the backlinks for each linked page.
+            self.recompile_note(&mut note, backlinks);
+            self.rerender_notes(changed, backlinks);
         }
     }
 
@@ -40,7 +209,7 @@ impl<'a> Exporter<'a> {
         &mut self, 
         event: Event,
         rename_kind: RenameMode,
-        _backlinks: &mut Backlinks
+        backlinks: &mut Backlinks
     ) {
         let ignored = &self.get_excluded();
         match rename_kind {
@@ -53,11 +222,7 @@ impl<'a> Exporter<'a> {
                         info!("Detected rename from {} to {}", 
                             from.to_string_lossy(), to.to_string_lossy()
                         );
-                        let original_out = self.input_to_output(from, Some(".html"));
-                        let new_out = self.input_to_output(to, Some(".html"));
-                        if filesys::move_file(original_out, new_out).is_err() {
-                            warn!("Failed to move.");
-                        }
+                        self.handle_note_moved(from, Some(to), backlinks);
                     }
                 }
             }, 
@@ -91,16 +256,14 @@ impl<'a> Exporter<'a> {
         }
     }
 
-    fn handle_file_removal(&mut self, event: Event, _backlinks: &mut Backlinks) {
+    fn handle_file_removal(&mut self, event: Event, backlinks: &mut Backlinks) {
         let ignored = self.get_excluded();
         for path in event.paths {
             if !is_note(&path, &ignored) {
                 continue;
             }
-            let output_path = self.input_to_output(&path, Some(".html"));
-            if filesys::remove_file(&output_path).is_err() {
-                warn!("Couldn't remove {}", output_path.to_string_lossy()); 
-            }
+            info!("Detected removal of {}", path.to_string_lossy());
+            self.handle_note_moved(&path, None, backlinks);
         }
     }
 
@@ -122,3 +285,160 @@ impl<'a> Exporter<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::exporter::{Backlinks, Exporter};
+    use crate::exporting::config::ExportConfig;
+    use notify::event::{DataChange, ModifyKind, RemoveKind, RenameMode};
+    use notify::{Event, EventKind};
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    fn write_notes(input: &Path, notes: &[(&str, &str)]) {
+        std::fs::create_dir_all(input).unwrap();
+        for (name, content) in notes {
+            std::fs::write(input.join(format!("{}.md", name)), content).unwrap();
+        }
+    }
+
+    fn modified(path: PathBuf) -> Event {
+        Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content))).add_path(path)
+    }
+
+    fn renamed(from: PathBuf, to: PathBuf) -> Event {
+        Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(from)
+            .add_path(to)
+    }
+
+    fn removed(path: PathBuf) -> Event {
+        Event::new(EventKind::Remove(RemoveKind::File)).add_path(path)
+    }
+
+    /// Remove the pages of the given notes, so the pages that are rendered again show up.
+    fn remove_pages(output: &Path, names: &[&str]) {
+        for name in names {
+            std::fs::remove_file(output.join(format!("{}.html", name))).unwrap();
+        }
+    }
+
+    fn rendered<'n>(output: &Path, names: &[&'n str]) -> Vec<&'n str> {
+        names
+            .iter()
+            .filter(|name| output.join(format!("{}.html", name)).exists())
+            .copied()
+            .collect()
+    }
+
+    fn page(output: &Path, name: &str) -> String {
+        std::fs::read_to_string(output.join(format!("{}.html", name))).unwrap()
+    }
+
+    /// The notes that link to `target`, sorted.
+    fn referring(backlinks: &Backlinks, target: &str) -> Vec<PathBuf> {
+        let mut notes: Vec<PathBuf> = backlinks
+            .get(Path::new(target))
+            .map(|links| links.iter().map(|link| link.target.clone()).collect())
+            .unwrap_or_default();
+        notes.sort();
+        notes
+    }
+
+    #[test]
+    fn edited_links_update_backlinks() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        let output = dir.path().join("out");
+        let names = ["a", "b", "c"];
+        write_notes(&input, &[("a", "See [[b]].\n"), ("b", "B\n"), ("c", "C\n")]);
+        let cfg = ExportConfig::default();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        let mut backlinks = exporter.export();
+        assert_eq!(referring(&backlinks, "b.md"), [PathBuf::from("a.md")]);
+
+        // The note that lost the link and the one that gained it are rendered again.
+        remove_pages(&output, &names);
+        write_notes(&input, &[("a", "See [[c]].\n")]);
+        exporter.handle_event(modified(input.join("a.md")), &mut backlinks, false);
+        assert_eq!(rendered(&output, &names), names);
+        assert!(!backlinks.contains_key(Path::new("b.md")));
+        assert_eq!(referring(&backlinks, "c.md"), [PathBuf::from("a.md")]);
+        let c = page(&output, "c");
+        assert!(c.contains("backlinks") && c.contains("a.html"));
+        assert!(!page(&output, "b").contains("backlinks"));
+
+        // Other notes are left alone if the links stay the same.
+        remove_pages(&output, &names);
+        write_notes(&input, &[("a", "Still see [[c]].\n")]);
+        exporter.handle_event(modified(input.join("a.md")), &mut backlinks, false);
+        assert_eq!(rendered(&output, &names), ["a"]);
+        assert_eq!(referring(&backlinks, "c.md"), [PathBuf::from("a.md")]);
+    }
+
+    #[test]
+    fn backlinks_of_a_note_are_removed() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        let output = dir.path().join("out");
+        write_notes(&input, &[("a", "[[b]] and [[c]]\n"), ("b", "B\n"), ("c", "[[b]]\n")]);
+        let cfg = ExportConfig::default();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        let mut backlinks = exporter.export();
+
+        let changed = exporter.replace_backlinks_from(&mut backlinks, &input.join("a.md"), None);
+        assert_eq!(changed, [PathBuf::from("b.md"), PathBuf::from("c.md")].into());
+        assert_eq!(referring(&backlinks, "b.md"), [PathBuf::from("c.md")]);
+        assert!(!backlinks.contains_key(Path::new("c.md")));
+    }
+
+    #[test]
+    fn renamed_note_is_moved_and_links_are_updated() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        let output = dir.path().join("out");
+        write_notes(
+            &input,
+            &[("a", "See [[d]].\n"), ("b", "B\n"), ("c", "See [[b]].\n"), ("e", "E\n")],
+        );
+        let cfg = ExportConfig::default();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        let mut backlinks = exporter.export();
+        assert!(page(&output, "a").contains("class=\"broken\""));
+
+        remove_pages(&output, &["a", "c", "e"]);
+        std::fs::rename(input.join("b.md"), input.join("d.md")).unwrap();
+        let event = renamed(input.join("b.md"), input.join("d.md"));
+        exporter.handle_event(event, &mut backlinks, false);
+        assert!(!output.join("b.html").exists());
+        assert_eq!(rendered(&output, &["a", "c", "d", "e"]), ["a", "c", "d"]);
+        assert!(!page(&output, "a").contains("class=\"broken\""));
+        assert!(page(&output, "c").contains("class=\"broken\""));
+        assert!(!backlinks.contains_key(Path::new("b.md")));
+        assert_eq!(referring(&backlinks, "d.md"), [PathBuf::from("a.md")]);
+    }
+
+    #[test]
+    fn removed_note_is_removed_and_links_are_updated() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes");
+        let output = dir.path().join("out");
+        write_notes(
+            &input,
+            &[("a", "See [[b]].\n"), ("b", "See [[c]].\n"), ("c", "C\n"), ("e", "E\n")],
+        );
+        let cfg = ExportConfig::default();
+        let mut exporter = Exporter::new(&input, &output, &cfg);
+        let mut backlinks = exporter.export();
+        assert!(page(&output, "c").contains("backlinks"));
+
+        remove_pages(&output, &["a", "c", "e"]);
+        std::fs::remove_file(input.join("b.md")).unwrap();
+        exporter.handle_event(removed(input.join("b.md")), &mut backlinks, false);
+        assert!(!output.join("b.html").exists());
+        assert_eq!(rendered(&output, &["a", "c", "e"]), ["a", "c"]);
+        assert!(page(&output, "a").contains("class=\"broken\""));
+        assert!(!page(&output, "c").contains("backlinks"));
+        assert!(backlinks.is_empty());
+    }
+}
@@ -6,6 +6,7 @@ pub const HTML_TEMPLATE: &str = include_str!("templates/template.html");
 
 // Data 
 pub const STOPWORDS: &str = include_str!("data/stopwords.csv");
+pub const STOPWORDS_NL: &str = include_str!("data/stopwords_nl.csv");
 
 // Javascript
 pub const MATHJAX_CFG: &str = include_str!("templates/static/js/mathjax_cfg.js");
@@ -15,10 +16,13 @@ pub const NAVBAR_SCRIPT: &str = include_str!("templates/static/js/navbar.js");
 pub const DARKMODE_SCRIPT: &str = include_str!("templates/static/js/toggle_darkmode.js");
 pub const FOUC_SCRIPT: &str = include_str!("templates/static/js/fix_fouc.js");
 pub const BROKEN_LINKS: &str = include_str!("templates/static/js/disable_broken_links.js");
+pub const GRAPH_SCRIPT: &str = include_str!("templates/static/js/graph.js");
+pub const LIVE_RELOAD_SCRIPT: &str = include_str!("templates/static/js/live_reload.js");
 
 // HTML snippets
 pub const LOAD_MATHJAX: &str = include_str!("templates/snippets/include_mathjax.html");
 pub const LOAD_KATEX: &str = include_str!("templates/snippets/include_katex.html");
+pub const LOAD_KATEX_CSS: &str = include_str!("templates/snippets/include_katex_css.html");
 pub const LOAD_SEARCH: &str = include_str!("templates/snippets/include_search_lib.html");
 pub const SEARCH_HTML: &str = include_str!("templates/snippets/search_bar.html");
 pub const LOAD_MERMAID: &str = include_str!("templates/snippets/include_mermaid.html");
@@ -0,0 +1,403 @@
+use fnv::FnvHasher;
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+use super::config::ExportConfig;
+use crate::components::link::LinkType;
+use crate::components::note::Note;
+use crate::utils::constants::MANIFEST_FILE;
+use crate::utils::errors::FileWriteError;
+use crate::utils::filesys::{relative_to, write_to_file};
+use crate::utils::utils::{self, read_file_to_str};
+
+///Everything a rendered note depends on, as recorded in the [Manifest].
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteRecord {
+    pub content_hash: u64,
+    pub title: String,
+    ///Notes this note links to (including embeds).
+    pub links: Vec<PathBuf>,
+    ///Notes this note embeds.
+    pub embeds: Vec<PathBuf>,
+    ///Targets of links that could not be resolved.
+    pub broken_links: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+///Record of the inputs of a build, stored in the output directory.
+///
+///Comparing the manifest of the current build to that of the previous one tells which notes
+///have to be rendered again.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub config_hash: u64,
+    pub template_hash: u64,
+    pub notes: BTreeMap<PathBuf, NoteRecord>,
+}
+
+///FNV-1a hash of `bytes`. Unlike the hasher of the standard library, it is the same with every
+///version of Rust, so the hashes of a saved manifest can be compared to those of a new build.
+fn hash_of(bytes: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+///Path of a note as it is used in the manifest, i.e., in the same way as the targets of links.
+pub fn manifest_key(path: &Path, input_dir: &Path) -> PathBuf {
+    utils::prepend_slash(relative_to(path, input_dir))
+}
+
+impl NoteRecord {
+    pub fn from_note(note: &Note) -> Self {
+        let content_hash = match std::fs::read(&note.path) {
+            Ok(bytes) => hash_of(&bytes),
+            Err(_) => hash_of(note.content.as_bytes()),
+        };
+
+        let mut links = vec![];
+        let mut embeds = vec![];
+        let mut broken_links = vec![];
+        for link in &note.links {
+            if link.broken {
+                broken_links.push(link.target.to_string_lossy().to_string());
+                continue;
+            }
+            if link.unpublished {
+                // Shown as text, so the note only changes when the target is published again.
+                continue;
+            }
+            match link.link_type() {
+                LinkType::Note => links.push(link.target.with_extension("md")),
+                LinkType::Embed => {
+                    links.push(link.target.with_extension("md"));
+                    embeds.push(link.target.with_extension("md"));
+                }
+                _ => {}
+            }
+        }
+        let mut tags: Vec<String> = note.tags.iter().map(|tag| tag.tag_path.clone()).collect();
+        for list in [&mut links, &mut embeds] {
+            list.sort();
+            list.dedup();
+        }
+        broken_links.sort();
+        broken_links.dedup();
+        tags.sort();
+        tags.dedup();
+
+        NoteRecord {
+            content_hash,
+            title: note.title.clone(),
+            links,
+            embeds,
+            broken_links,
+            tags,
+        }
+    }
+}
+
+impl Manifest {
+    pub fn new(template: &str, cfg: &ExportConfig, notes: &[Note], input_dir: &Path) -> Self {
+        let config_hash = match serde_json::to_string(cfg) {
+            Ok(serialized) => hash_of(serialized.as_bytes()),
+            Err(e) => {
+                warn!("Could not serialize the configuration. Got error {}", e);
+                0
+            }
+        };
+        Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config_hash,
+            template_hash: hash_of(template.as_bytes()),
+            notes: notes
+                .iter()
+                .map(|note| {
+                    (
+                        manifest_key(&note.path, input_dir),
+                        NoteRecord::from_note(note),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    ///Leave the note at `key` out, so it is rendered again by the next build, e.g. because
+    ///rendering it failed.
+    pub fn forget(&mut self, key: &Path) {
+        self.notes.remove(key);
+    }
+
+    fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(MANIFEST_FILE)
+    }
+
+    ///Load the manifest of a previous build, if there is one.
+    pub fn load(output_dir: &Path) -> Option<Self> {
+        let path = Self::path(output_dir);
+        let content = read_file_to_str(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                warn!(
+                    "Ignoring invalid build manifest {:?}. Got error {}",
+                    path, e
+                );
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<usize, FileWriteError> {
+        let content = serde_json::to_string(self).expect("Serialization of manifest failed.");
+        write_to_file(&Self::path(output_dir), &content)
+    }
+
+    ///Get the notes that have to be rendered again, compared to the `previous` build.
+    ///
+    ///A note is outdated if its own record changed, if the notes linking to it changed (since
+    ///its backlinks need updating), or if one of the notes it (indirectly) embeds changed.
+    ///If the template or the configuration changed, all notes are outdated.
+    pub fn outdated_notes(&self, previous: &Manifest) -> HashSet<PathBuf> {
+        if self.version != previous.version
+            || self.config_hash != previous.config_hash
+            || self.template_hash != previous.template_hash
+        {
+            debug!("Template or configuration changed since the previous build.");
+            return self.notes.keys().cloned().collect();
+        }
+
+        let changed: HashSet<&PathBuf> = self
+            .notes
+            .iter()
+            .filter(|(key, record)| previous.notes.get(*key) != Some(record))
+            .map(|(key, _)| key)
+            .chain(
+                previous
+                    .notes
+                    .keys()
+                    .filter(|key| !self.notes.contains_key(*key)),
+            )
+            .collect();
+
+        let mut outdated: HashSet<PathBuf> = changed.iter().map(|key| (*key).clone()).collect();
+
+        // The backlinks of notes that are linked to by a changed note may have changed.
+        for key in &changed {
+            let old = previous.notes.get(*key);
+            let new = self.notes.get(*key);
+            if old.map(|r| (&r.title, &r.links)) != new.map(|r| (&r.title, &r.links)) {
+                for record in old.into_iter().chain(new) {
+                    outdated.extend(record.links.iter().cloned());
+                }
+            }
+        }
+
+        // Notes that embed changed content are outdated as well, even if it is nested.
+        let mut changed_content: HashSet<&PathBuf> = changed
+            .into_iter()
+            .filter(|key| {
+                previous.notes.get(*key).map(|r| r.content_hash)
+                    != self.notes.get(*key).map(|r| r.content_hash)
+            })
+            .collect();
+        loop {
+            let embedding: Vec<&PathBuf> = self
+                .notes
+                .iter()
+                .filter(|(key, record)| {
+                    !changed_content.contains(key)
+                        && record.embeds.iter().any(|e| changed_content.contains(e))
+                })
+                .map(|(key, _)| key)
+                .collect();
+            if embedding.is_empty() {
+                break;
+            }
+            changed_content.extend(embedding);
+        }
+        outdated.extend(changed_content.into_iter().cloned());
+
+        outdated.retain(|key| self.notes.contains_key(key));
+        outdated
+    }
+
+    ///Get the notes whose local graph with the given `depth` may differ from the `previous`
+    ///build, i.e., the notes within `depth` links of a note whose title or links changed.
+    pub fn outdated_local_graphs(&self, previous: &Manifest, depth: usize) -> HashSet<PathBuf> {
+        let mut neighbours: HashMap<&PathBuf, HashSet<&PathBuf>> = HashMap::new();
+        for manifest in [self, previous] {
+            for (key, record) in &manifest.notes {
+                for target in &record.links {
+                    neighbours.entry(key).or_default().insert(target);
+                    neighbours.entry(target).or_default().insert(key);
+                }
+            }
+        }
+
+        let mut found: HashSet<&PathBuf> = self
+            .notes
+            .keys()
+            .chain(previous.notes.keys())
+            .filter(|key| {
+                let old = previous.notes.get(*key).map(|r| (&r.title, &r.links));
+                let new = self.notes.get(*key).map(|r| (&r.title, &r.links));
+                old != new
+            })
+            .collect();
+        let mut frontier: Vec<&PathBuf> = found.iter().cloned().collect();
+        for _ in 0..depth {
+            let mut next = vec![];
+            for key in frontier {
+                for neighbour in neighbours.get(key).into_iter().flatten() {
+                    if found.insert(neighbour) {
+                        next.push(*neighbour);
+                    }
+                }
+            }
+            frontier = next;
+        }
+
+        found
+            .into_iter()
+            .filter(|key| self.notes.contains_key(*key))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_of, Manifest, NoteRecord};
+    use std::path::PathBuf;
+
+    fn record(content_hash: u64, links: &[&str], embeds: &[&str]) -> NoteRecord {
+        NoteRecord {
+            content_hash,
+            title: "".to_string(),
+            links: links.iter().map(PathBuf::from).collect(),
+            embeds: embeds.iter().map(PathBuf::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn manifest(notes: Vec<(&str, NoteRecord)>) -> Manifest {
+        Manifest {
+            notes: notes
+                .into_iter()
+                .map(|(key, record)| (PathBuf::from(key), record))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn sorted(outdated: std::collections::HashSet<PathBuf>) -> Vec<PathBuf> {
+        let mut outdated: Vec<PathBuf> = outdated.into_iter().collect();
+        outdated.sort();
+        outdated
+    }
+
+    #[test]
+    fn hashes_are_stable() {
+        // FNV-1a test vectors; a saved manifest must keep matching new builds.
+        assert_eq!(hash_of(b""), 0xcbf29ce484222325);
+        assert_eq!(hash_of(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash_of(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn forgotten_note_is_outdated() {
+        let previous = manifest(vec![
+            ("a.md", record(1, &[], &[])),
+            ("b.md", record(2, &[], &[])),
+        ]);
+        let mut saved = previous.clone();
+        saved.forget(&PathBuf::from("b.md"));
+        assert_eq!(sorted(previous.outdated_notes(&saved)), vec![PathBuf::from("b.md")]);
+    }
+
+    #[test]
+    fn unchanged_build_is_up_to_date() {
+        let previous = manifest(vec![
+            ("a.md", record(1, &["b.md"], &[])),
+            ("b.md", record(2, &[], &[])),
+        ]);
+        assert!(previous.clone().outdated_notes(&previous).is_empty());
+    }
+
+    #[test]
+    fn new_link_outdates_target() {
+        let previous = manifest(vec![
+            ("a.md", record(1, &[], &[])),
+            ("b.md", record(2, &[], &[])),
+        ]);
+        let current = manifest(vec![
+            ("a.md", record(3, &["b.md"], &[])),
+            ("b.md", record(2, &[], &[])),
+        ]);
+        assert_eq!(
+            sorted(current.outdated_notes(&previous)),
+            vec![PathBuf::from("a.md"), PathBuf::from("b.md")]
+        );
+    }
+
+    #[test]
+    fn content_change_outdates_embedding_notes() {
+        let previous = manifest(vec![
+            ("a.md", record(1, &["b.md"], &["b.md"])),
+            ("b.md", record(2, &["c.md"], &["c.md"])),
+            ("c.md", record(3, &[], &[])),
+            ("d.md", record(4, &[], &[])),
+        ]);
+        let mut current = previous.clone();
+        current
+            .notes
+            .get_mut(&PathBuf::from("c.md"))
+            .unwrap()
+            .content_hash = 5;
+        assert_eq!(
+            sorted(current.outdated_notes(&previous)),
+            vec![
+                PathBuf::from("a.md"),
+                PathBuf::from("b.md"),
+                PathBuf::from("c.md")
+            ]
+        );
+    }
+
+    #[test]
+    fn template_change_outdates_everything() {
+        let previous = manifest(vec![
+            ("a.md", record(1, &[], &[])),
+            ("b.md", record(2, &[], &[])),
+        ]);
+        let mut current = previous.clone();
+        current.template_hash = 1;
+        assert_eq!(current.outdated_notes(&previous).len(), 2);
+    }
+
+    #[test]
+    fn link_change_outdates_local_graphs() {
+        let previous = manifest(vec![
+            ("a.md", record(1, &["b.md"], &[])),
+            ("b.md", record(2, &["c.md"], &[])),
+            ("c.md", record(3, &[], &[])),
+            ("d.md", record(4, &[], &[])),
+        ]);
+        let mut current = previous.clone();
+        current.notes.insert(PathBuf::from("c.md"), record(5, &["d.md"], &[]));
+        assert_eq!(
+            sorted(current.outdated_local_graphs(&previous, 1)),
+            vec![
+                PathBuf::from("b.md"),
+                PathBuf::from("c.md"),
+                PathBuf::from("d.md")
+            ]
+        );
+        assert_eq!(current.outdated_local_graphs(&previous, 2).len(), 4);
+    }
+}
@@ -0,0 +1,105 @@
+use katex::{Opts, OutputType};
+use log::warn;
+use std::collections::BTreeMap;
+
+use super::config::MathPrerender;
+use super::wrap_pulldown_cmark::text_to_html;
+
+///Renders math with KaTeX when the website is built, so pages need no math engine.
+///
+///KaTeX runs in a javascript engine of its own on each thread, which is started the first time
+///math is rendered on that thread.
+#[derive(Debug, Clone)]
+pub struct MathRenderer {
+    inline: Opts,
+    display: Opts,
+    macros: BTreeMap<String, String>,
+}
+
+impl MathRenderer {
+    ///A renderer to MathML or KaTeX html, or none if math is typeset in the browser.
+    pub fn new(prerender: MathPrerender) -> Option<Self> {
+        let output = match prerender {
+            MathPrerender::None => return None,
+            MathPrerender::Mathml => OutputType::Mathml,
+            MathPrerender::Html => OutputType::Html,
+        };
+        let opts = |display| {
+            let mut opts = Opts::default();
+            opts.set_display_mode(display);
+            opts.set_output_type(output);
+            // Invalid math is shown in red, like in the browser, instead of failing.
+            opts.set_throw_on_error(false);
+            opts
+        };
+        Some(MathRenderer {
+            inline: opts(false),
+            display: opts(true),
+            macros: BTreeMap::new(),
+        })
+    }
+
+    ///Define a macro, like `\RR` to `\mathbb{R}`.
+    pub fn add_macro(&mut self, name: &str, expansion: &str) {
+        self.inline.add_macro(name.to_string(), expansion.to_string());
+        self.display.add_macro(name.to_string(), expansion.to_string());
+        self.macros.insert(name.to_string(), expansion.to_string());
+    }
+
+    ///The macros that were defined, by name.
+    pub fn macros(&self) -> &BTreeMap<String, String> {
+        &self.macros
+    }
+
+    ///The html of `math`, which is given with its delimiters. If KaTeX fails, the source is
+    ///kept.
+    pub fn render(&self, math: &str) -> String {
+        let (tex, opts) = if let Some(tex) = strip_delimiters(math, "$$", "$$")
+            .or_else(|| strip_delimiters(math, r"\[", r"\]"))
+        {
+            (tex, &self.display)
+        } else if let Some(tex) = strip_delimiters(math, "$", "$")
+            .or_else(|| strip_delimiters(math, r"\(", r"\)"))
+        {
+            (tex, &self.inline)
+        } else {
+            (math, &self.inline)
+        };
+        katex::render_with_opts(tex, opts).unwrap_or_else(|e| {
+            warn!("Could not render math `{}`: {}", math, e);
+            text_to_html(math)
+        })
+    }
+}
+
+fn strip_delimiters<'m>(math: &'m str, start: &str, end: &str) -> Option<&'m str> {
+    math.strip_prefix(start)?.strip_suffix(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MathRenderer;
+    use crate::exporting::config::MathPrerender;
+
+    #[test]
+    fn renders_math_with_macros() {
+        assert!(MathRenderer::new(MathPrerender::None).is_none());
+
+        let mut renderer = MathRenderer::new(MathPrerender::Mathml).unwrap();
+        renderer.add_macro(r"\RR", r"\mathbb{R}");
+        let inline = renderer.render(r"$x \in \RR$");
+        assert!(inline.starts_with("<span class=\"katex\"><math"));
+        assert!(inline.contains("<mi mathvariant=\"double-struck\">R</mi>"));
+        assert!(!inline.contains("display=\"block\""));
+        assert!(renderer.render(r"\(x\)").contains("<mi>x</mi>"));
+
+        let display = renderer.render("$$\n\\frac{1}{2}\n$$");
+        assert!(display.contains("display=\"block\""));
+        assert!(display.contains("<mfrac>"));
+        assert!(renderer.render(r"\[x\]").contains("display=\"block\""));
+
+        let html = MathRenderer::new(MathPrerender::Html).unwrap().render("$x$");
+        assert!(html.contains("katex-html"));
+        assert!(!html.contains("<math"));
+    }
+}
@@ -2,6 +2,11 @@ pub mod exporter;
 pub mod load_static;
 pub mod config; 
 pub mod search;
+pub mod query;
 pub mod wrap_pulldown_cmark;
 pub mod incremental;
+pub mod manifest;
+pub mod check;
+pub mod diagnostics;
+pub mod math;
 mod errors;
@@ -0,0 +1,219 @@
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::exporter::Exporter;
+use super::search::{excerpt, match_factor, page_url, Analyzer, Analyzers, SearchIndex};
+use crate::components::note::Note;
+use crate::utils::filesys::relative_to;
+use unicode_segmentation::UnicodeSegmentation;
+
+///Format of the results of `oxidian search`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ResultFormat {
+    ///One result per line, with its snippet below it.
+    #[default]
+    Text,
+    Json,
+}
+
+///A query of `oxidian search`: the words to search for, and the tags the notes must have.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    ///Only notes with all of these tags, or with tags nested in them, match.
+    pub tags: Vec<String>,
+    ///Only search the titles of the notes.
+    pub titles_only: bool,
+}
+
+impl SearchQuery {
+    ///Parse a query like `meeting notes tag:project/x`.
+    pub fn parse(query: &str, titles_only: bool) -> Self {
+        let mut words = vec![];
+        let mut tags = vec![];
+        for word in query.split_whitespace() {
+            match word.strip_prefix("tag:") {
+                Some(tag) => tags.push(tag.trim_start_matches('#').to_lowercase()),
+                None => words.push(word),
+            }
+        }
+        SearchQuery {
+            text: words.join(" "),
+            tags,
+            titles_only,
+        }
+    }
+
+    fn has_tags(&self, note: &Note) -> bool {
+        self.tags.iter().all(|wanted| {
+            note.tags.iter().any(|tag| {
+                tag.tag_path == *wanted || tag.tag_path.starts_with(&format!("{}/", wanted))
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchResult {
+    ///Path of the note, relative to the notebook directory.
+    pub path: PathBuf,
+    ///Url of the page, with the id of the heading of the section.
+    pub url: String,
+    pub title: String,
+    ///The headings the section is nested in, ending with its own heading.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub breadcrumb: Vec<String>,
+    pub score: f64,
+    ///The text around the first match.
+    pub snippet: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SearchResults {
+    pub results: Vec<SearchResult>,
+}
+
+impl SearchResults {
+    pub fn format(&self, format: ResultFormat) -> String {
+        match format {
+            ResultFormat::Text => self.to_text(),
+            ResultFormat::Json => self.to_json() + "\n",
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for result in &self.results {
+            let mut name = vec![result.title.as_str()];
+            name.extend(result.breadcrumb.iter().map(String::as_str));
+            text.push_str(&format!(
+                "{}: {}\n",
+                result.path.to_string_lossy(),
+                name.join(" › ")
+            ));
+            if !result.snippet.is_empty() {
+                text.push_str(&format!("    {}\n", result.snippet));
+            }
+        }
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.results).expect("Serialization of results failed.")
+    }
+}
+
+///The text around the first word of `text` that matches one of the tokens, cut at `max_len`
+///characters.
+fn snippet(text: &str, tokens: &[String], analyzer: &Analyzer, max_len: usize) -> String {
+    let stems: Vec<String> = tokens.iter().map(|token| analyzer.stem(token)).collect();
+    let first_match = text.unicode_word_indices().find(|(_, word)| {
+        let word = word.to_lowercase();
+        let stem = analyzer.stem(&word);
+        tokens.iter().any(|token| match_factor(token, &word) > 0.) || stems.contains(&stem)
+    });
+    let Some((start, _)) = first_match else {
+        return excerpt(text, max_len);
+    };
+    // Show some context before the match, starting at a word.
+    let before: Vec<usize> = text[..start].char_indices().map(|(idx, _)| idx).collect();
+    let from = match before.len().checked_sub(max_len / 4) {
+        None | Some(0) => 0,
+        Some(context) => {
+            let from = before[context];
+            text[from..start]
+                .find(char::is_whitespace)
+                .map_or(start, |space| from + space)
+        }
+    };
+    let snippet = excerpt(&text[from..], max_len);
+    if from > 0 {
+        format!("…{}", snippet.trim_start())
+    } else {
+        snippet
+    }
+}
+
+impl Exporter<'_> {
+    ///Search the notes, like the search bar of the exported notes does.
+    pub fn search(&mut self, query: &SearchQuery, limit: usize) -> SearchResults {
+        let notes = self.load_all_notes();
+        let input_dir = self.input_directory();
+        let cfg = &self.config().search;
+        let analyzers = Analyzers::from_config(cfg, input_dir);
+        let analyzer = analyzers.default_analyzer();
+        let (index, texts) = SearchIndex::from_notes_with_text(&notes, &analyzers, cfg, input_dir);
+
+        // The documents of a note all have the url of its page, up to the `#`.
+        let pages: HashMap<String, &Note> = notes
+            .iter()
+            .map(|note| (page_url(note, input_dir), note))
+            .collect();
+
+        let tokens = analyzer.tokenize(&query.text);
+        let hits: Vec<(usize, f64)> = if tokens.is_empty() {
+            // Only filters: list the notes.
+            (0..index.docs.len())
+                .filter(|doc| index.docs[*doc].breadcrumb.is_empty())
+                .map(|doc| (doc, 0.))
+                .collect()
+        } else {
+            let weights = if query.titles_only {
+                [index.weights[0], 0., 0., 0.]
+            } else {
+                index.weights
+            };
+            index
+                .search_with_weights(&query.text, analyzer, &weights, usize::MAX)
+                .into_iter()
+                .map(|hit| (hit.doc, hit.score))
+                .collect()
+        };
+
+        let results = hits
+            .into_iter()
+            .filter_map(|(doc, score)| {
+                let document = &index.docs[doc];
+                let page = document.path.split('#').next().unwrap_or_default();
+                let note = pages.get(page)?;
+                query.has_tags(note).then(|| SearchResult {
+                    path: relative_to(&note.path, input_dir),
+                    url: document.path.clone(),
+                    title: document.title.clone(),
+                    breadcrumb: document.breadcrumb.clone(),
+                    score,
+                    snippet: snippet(&texts[doc], &tokens, analyzer, cfg.max_len),
+                })
+            })
+            .take(limit)
+            .collect();
+        SearchResults { results }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{snippet, SearchQuery};
+    use crate::exporting::config::Language;
+    use crate::exporting::search::Analyzer;
+
+    #[test]
+    fn parse_tag_filters() {
+        let query = SearchQuery::parse("meeting tag:Project/x notes tag:#daily", false);
+        assert_eq!(query.text, "meeting notes");
+        assert_eq!(query.tags, vec!["project/x", "daily"]);
+    }
+
+    #[test]
+    fn snippet_around_match() {
+        let analyzer = Analyzer::new(Language::English, ["the"]);
+        let text = "One two three four five six seven eight nine ten. Gardening is fun.";
+        let tokens = analyzer.tokenize("garden");
+        assert_eq!(
+            snippet(text, &tokens, &analyzer, 30),
+            "…ten. Gardening is fun."
+        );
+        assert_eq!(snippet("Short text", &tokens, &analyzer, 30), "Short text");
+    }
+}
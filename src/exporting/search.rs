@@ -1,48 +1,805 @@
-use crate::utils::filesys::{relative_to, slugify_path};
 use crate::components::note::Note;
-use crate::utils::utils::prepend_slash;
-use std::path::{Path, PathBuf};
+use crate::exporting::config::{FieldWeights, Language, SearchConfig};
+use crate::exporting::load_static::{STOPWORDS, STOPWORDS_NL};
+use crate::utils::filesys::{relative_to, slugify_path};
+use crate::utils::utils::{markdown_options, prepend_slash, read_file_to_str};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
 
+use log::warn;
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use rust_stemmers::{Algorithm, Stemmer};
 use serde_derive::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Version of the format of the search index, read by `search.js`.
+pub const INDEX_VERSION: u32 = 2;
+
+/// The fields of a document in the search index, in the order of the term frequencies.
+pub const FIELDS: [&str; 4] = ["title", "headings", "tags", "body"];
+
+/// Directory of the shards of the search index, next to the index itself.
+pub const SHARD_DIR: &str = "search";
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SearchEntry<'a> {
-    pub path: PathBuf,
-    pub title: &'a str,
-    pub content: String,
+/// Parameters of the BM25 ranking function.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// Splits text into lowercase words, drops the stopwords and stems the words, for one
+/// language.
+#[derive(Debug, Clone)]
+pub struct Analyzer {
+    language: Language,
+    stopwords: HashSet<String>,
 }
 
-impl<'a> SearchEntry<'a> {
-    pub fn new<U, T>(
-        note: &'a Note<'a>,
-        stopwords: T,
-        max_len: Option<usize>,
-        input_dir: &Path,
-    ) -> Self
+impl Analyzer {
+    pub fn new<I, S>(language: Language, stopwords: I) -> Self
     where
-        U: AsRef<str>,
-        T: Iterator<Item = U>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
     {
-        let mut content = match max_len {
-            Some(l) => note.content.chars().take(l).collect(),
-            None => note.content.clone(),
+        Analyzer {
+            language,
+            stopwords: stopwords
+                .into_iter()
+                .map(|word| word.as_ref().trim().to_lowercase())
+                .filter(|word| !word.is_empty() && !word.starts_with('#'))
+                .collect(),
+        }
+    }
+
+    /// An analyzer with the built-in stopwords of `language`.
+    pub fn for_language(language: Language) -> Self {
+        let stopwords = match language {
+            Language::English => STOPWORDS,
+            Language::Dutch => STOPWORDS_NL,
+        };
+        Analyzer::new(language, stopwords.lines())
+    }
+
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    pub fn is_stopword(&self, word: &str) -> bool {
+        self.stopwords.contains(word)
+    }
+
+    /// The words of `text`, as defined by Unicode, in lowercase and without stopwords.
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        text.unicode_words()
+            .map(|word| word.to_lowercase().replace('’', "'"))
+            .filter(|word| !self.is_stopword(word))
+            .collect()
+    }
+
+    pub fn stem(&self, word: &str) -> String {
+        let algorithm = match self.language {
+            Language::English => Algorithm::English,
+            Language::Dutch => Algorithm::Dutch,
+        };
+        Stemmer::create(algorithm).stem(word).into_owned()
+    }
+
+    pub fn stopwords(&self) -> impl Iterator<Item = &String> {
+        self.stopwords.iter()
+    }
+}
+
+/// The analyzers of all languages, and the language of the notes without a `lang` key.
+#[derive(Debug, Clone)]
+pub struct Analyzers {
+    default: Language,
+    analyzers: Vec<Analyzer>,
+}
+
+impl Analyzers {
+    /// Analyzers with the built-in stopwords, or with `stopwords` for every language.
+    pub fn new(default: Language, stopwords: Option<&str>) -> Self {
+        let analyzers = Language::ALL
+            .iter()
+            .map(|language| match stopwords {
+                Some(stopwords) => Analyzer::new(*language, stopwords.lines()),
+                None => Analyzer::for_language(*language),
+            })
+            .collect();
+        Analyzers { default, analyzers }
+    }
+
+    pub fn from_config(cfg: &SearchConfig, input_dir: &Path) -> Self {
+        let stopwords = cfg.stopwords.as_ref().and_then(|file| {
+            read_file_to_str(input_dir.join(file))
+                .map_err(|err| warn!("Could not read stopwords from {:?}: {}", file, err))
+                .ok()
+        });
+        Analyzers::new(cfg.language, stopwords.as_deref())
+    }
+
+    pub fn get(&self, language: Language) -> &Analyzer {
+        self.analyzers
+            .iter()
+            .find(|analyzer| analyzer.language == language)
+            .expect("There is an analyzer for every language.")
+    }
+
+    pub fn default_analyzer(&self) -> &Analyzer {
+        self.get(self.default)
+    }
+
+    /// The analyzer of the language in the `lang` key of the frontmatter of the note.
+    pub fn for_note(&self, note: &Note) -> &Analyzer {
+        let lang = note
+            .frontmatter
+            .as_ref()
+            .and_then(|frontmatter| frontmatter["lang"].as_str());
+        match lang.map(|code| (code, Language::from_code(code))) {
+            Some((_, Some(language))) => self.get(language),
+            Some((code, None)) => {
+                warn!(
+                    "Unknown language {:?} in {:?}, searching it as {:?}.",
+                    code, note.path, self.default
+                );
+                self.default_analyzer()
+            }
+            None => self.default_analyzer(),
+        }
+    }
+}
+
+/// The text of a note before its first heading, or under one of its headings.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Section {
+    /// The headings the section is nested in, ending with its own heading.
+    breadcrumb: Vec<String>,
+    /// The id of the heading in the html of the note.
+    id: Option<String>,
+    text: String,
+}
+
+/// Split markdown into sections at its headings. `ids` are the ids of the headings, as
+/// given by `Note::heading_ids`.
+fn sections(markdown: &str, ids: &[Option<String>]) -> Vec<Section> {
+    let mut sections = vec![Section::default()];
+    let mut parents: Vec<(HeadingLevel, String)> = vec![];
+    let mut heading: Option<String> = None;
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        // Math is shown with its delimiters.
+        let event = match event {
+            Event::InlineMath(math) => Event::Text(format!("${}$", math).into()),
+            Event::DisplayMath(math) => Event::Text(format!("$${}$$", math).into()),
+            event => event,
+        };
+        match event {
+            Event::Start(Tag::Heading { .. }) => heading = Some(String::new()),
+            Event::End(TagEnd::Heading(level)) => {
+                let text = heading.take().unwrap_or_default().trim().to_string();
+                parents.retain(|(parent, _)| *parent < level);
+                parents.push((level, text));
+                sections.push(Section {
+                    breadcrumb: parents.iter().map(|(_, text)| text.clone()).collect(),
+                    id: None,
+                    text: String::new(),
+                });
+            }
+            Event::Text(text) | Event::Code(text) => match heading.as_mut() {
+                Some(heading) => heading.push_str(&text),
+                None => sections.last_mut().unwrap().text.push_str(&text),
+            },
+            Event::SoftBreak | Event::HardBreak => match heading.as_mut() {
+                Some(heading) => heading.push(' '),
+                None => sections.last_mut().unwrap().text.push(' '),
+            },
+            Event::End(_) => sections.last_mut().unwrap().text.push('\n'),
+            _ => {}
+        }
+    }
+    // Math blocks can look like headings once they are put back, so only link to the
+    // headings if they are the same as in the html.
+    if ids.len() == sections.len() - 1 {
+        for (section, id) in sections.iter_mut().skip(1).zip(ids) {
+            section.id = id.clone();
+        }
+    }
+    sections
+}
+
+/// Url of the page of the note, relative to the root. A path that can't be slugified is
+/// used as it is, like for the page itself.
+pub fn page_url(note: &Note, input_dir: &Path) -> String {
+    let path = prepend_slash(relative_to(&note.path, input_dir));
+    slugify_path(&path, Some("html"))
+        .unwrap_or_else(|_| path.with_extension("html"))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The start of `text` with its whitespace collapsed, cut at `max_len` characters.
+pub(crate) fn excerpt(text: &str, max_len: usize) -> String {
+    let mut excerpt = String::new();
+    for word in text.split_whitespace() {
+        if excerpt.chars().count() + word.chars().count() + 1 > max_len {
+            if excerpt.is_empty() {
+                excerpt = word.chars().take(max_len).collect();
+            }
+            excerpt.push('…');
+            break;
+        }
+        if !excerpt.is_empty() {
+            excerpt.push(' ');
+        }
+        excerpt.push_str(word);
+    }
+    excerpt
+}
+
+/// The number of edits needed to turn `a` into `b`, or `None` if it is more than `max`.
+fn edit_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().min().copied().unwrap_or(0) > max {
+            return None;
+        }
+        previous = current;
+    }
+    Some(previous[b.len()]).filter(|distance| *distance <= max)
+}
+
+/// How well the indexed `term` matches the query token, between 0 (no match) and 1.
+///
+/// Besides exact matches, a token matches the terms it is a prefix of (so results show up
+/// while typing), the shorter forms of itself (`notes` finds `note`) and the terms that
+/// are one typo away, or two for long tokens. `search.js` uses the same rules.
+pub fn match_factor(token: &str, term: &str) -> f64 {
+    if token == term {
+        return 1.0;
+    }
+    let token_chars: Vec<char> = token.chars().collect();
+    let term_chars: Vec<char> = term.chars().collect();
+    if term_chars.len() >= 3 && token.starts_with(term) && token_chars.len() - term_chars.len() <= 3
+    {
+        return 0.8;
+    }
+    if token_chars.len() >= 2 && term.starts_with(token) {
+        return 0.7;
+    }
+    let typos = match token_chars.len() {
+        0..=3 => return 0.0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    match edit_distance(&token_chars, &term_chars, typos) {
+        Some(_) => 0.5,
+        None => 0.0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchDocument {
+    /// Url of the page, relative to the root, with the id of the heading of the section.
+    pub path: String,
+    /// Title of the note.
+    pub title: String,
+    /// The headings the section is nested in, ending with its own heading.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breadcrumb: Vec<String>,
+    /// The number of terms in each field.
+    pub lengths: [u32; 4],
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub excerpt: String,
+}
+
+/// A document containing a term, with the frequency of the term in each field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Posting(pub u32, pub [u32; 4]);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub doc: usize,
+    pub score: f64,
+}
+
+/// Inverted index of the notes, ranked with BM25F: the term frequencies of the title,
+/// headings, tags and body are weighted before they are saturated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchIndex {
+    pub version: u32,
+    pub k1: f64,
+    pub b: f64,
+    pub fields: Vec<String>,
+    pub weights: [f64; 4],
+    pub average_lengths: [f64; 4],
+    /// The stopwords of the languages of the notes, to drop from the queries.
+    pub stopwords: BTreeSet<String>,
+    pub docs: Vec<SearchDocument>,
+    /// The postings of the stemmed words.
+    pub terms: BTreeMap<String, Vec<Posting>>,
+    /// The words of the notes that differ from their stem, with their stem. Queries are
+    /// matched with these, so the client doesn't need to stem.
+    pub words: BTreeMap<String, String>,
+    /// The shards with the rest of the terms and words, if the index is sharded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<Shards>,
+    #[serde(skip)]
+    languages: BTreeSet<Language>,
+}
+
+/// The terms and words of the index that start with the same characters.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Shard {
+    pub terms: BTreeMap<String, Vec<Posting>>,
+    pub words: BTreeMap<String, String>,
+}
+
+fn shard_key(word: &str, prefix_len: usize) -> String {
+    word.chars().take(prefix_len).collect()
+}
+
+/// Where to find the shard of a term or word.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shards {
+    /// The number of characters that the terms and words in a shard share.
+    pub prefix_len: usize,
+    /// The number of the shard of each prefix. Shard `n` is saved in `SHARD_DIR/n.json`.
+    pub files: BTreeMap<String, usize>,
+}
+
+impl Shards {
+    /// The prefix of the shard of a term or word.
+    pub fn key(&self, word: &str) -> String {
+        shard_key(word, self.prefix_len)
+    }
+}
+
+impl SearchIndex {
+    pub fn new(weights: &FieldWeights) -> Self {
+        SearchIndex {
+            version: INDEX_VERSION,
+            k1: K1,
+            b: B,
+            fields: FIELDS.iter().map(|field| field.to_string()).collect(),
+            weights: [weights.title, weights.headings, weights.tags, weights.body],
+            average_lengths: [0.; 4],
+            stopwords: BTreeSet::new(),
+            docs: vec![],
+            terms: BTreeMap::new(),
+            words: BTreeMap::new(),
+            shards: None,
+            languages: BTreeSet::new(),
+        }
+    }
+
+    pub fn from_notes(
+        notes: &[Note],
+        analyzers: &Analyzers,
+        cfg: &SearchConfig,
+        input_dir: &Path,
+    ) -> Self {
+        Self::from_notes_with_text(notes, analyzers, cfg, input_dir).0
+    }
+
+    /// The index of the notes, and the full text of each of its documents.
+    pub fn from_notes_with_text(
+        notes: &[Note],
+        analyzers: &Analyzers,
+        cfg: &SearchConfig,
+        input_dir: &Path,
+    ) -> (Self, Vec<String>) {
+        let mut index = SearchIndex::new(&cfg.weights);
+        let mut texts = vec![];
+        for note in notes {
+            texts.extend(index.add_note(note, analyzers, cfg, input_dir));
+        }
+        (index, texts)
+    }
+
+    /// Add a document for every section of the note. Returns the full text of each of them.
+    pub fn add_note(
+        &mut self,
+        note: &Note,
+        analyzers: &Analyzers,
+        cfg: &SearchConfig,
+        input_dir: &Path,
+    ) -> Vec<String> {
+        let analyzer = analyzers.for_note(note);
+        let path = page_url(note, input_dir);
+        let sections = sections(&note.plain_markdown(), &note.heading_ids());
+        let mut texts = vec![];
+        for (idx, section) in sections.into_iter().enumerate() {
+            let url = match &section.id {
+                Some(id) => format!("{}#{}", path, id),
+                None => path.to_string(),
+            };
+            // The title and tags belong to the note, which the first section links to.
+            let fields = if idx == 0 {
+                let tags: Vec<&str> = note.tags.iter().map(|tag| tag.tag_path.as_str()).collect();
+                [
+                    analyzer.tokenize(&note.title),
+                    vec![],
+                    analyzer.tokenize(&tags.join(" ")),
+                    analyzer.tokenize(&section.text),
+                ]
+            } else {
+                [
+                    vec![],
+                    analyzer.tokenize(section.breadcrumb.last().map_or("", String::as_str)),
+                    vec![],
+                    analyzer.tokenize(&section.text),
+                ]
+            };
+            self.add_document(
+                SearchDocument {
+                    path: url,
+                    title: note.title.clone(),
+                    breadcrumb: section.breadcrumb,
+                    lengths: [0; 4],
+                    excerpt: excerpt(&section.text, cfg.max_len),
+                },
+                fields,
+                analyzer,
+            );
+            texts.push(section.text);
+        }
+        texts
+    }
+
+    /// Add a document, given the words of each of its fields, as tokenized by `analyzer`.
+    pub fn add_document(
+        &mut self,
+        mut document: SearchDocument,
+        fields: [Vec<String>; 4],
+        analyzer: &Analyzer,
+    ) {
+        let doc = self.docs.len() as u32;
+        let mut frequencies: BTreeMap<String, [u32; 4]> = BTreeMap::new();
+        for (field, words) in fields.iter().enumerate() {
+            for word in words {
+                let term = analyzer.stem(word);
+                if &term != word {
+                    self.words.insert(word.clone(), term.clone());
+                }
+                frequencies.entry(term).or_default()[field] += 1;
+            }
+        }
+        for (term, tf) in frequencies {
+            self.terms.entry(term).or_default().push(Posting(doc, tf));
+        }
+        if self.languages.insert(analyzer.language()) {
+            self.stopwords.extend(analyzer.stopwords().cloned());
+        }
+        document.lengths = fields.each_ref().map(|terms| terms.len() as u32);
+        let count = (doc + 1) as f64;
+        for (field, length) in document.lengths.iter().enumerate() {
+            self.average_lengths[field] += (*length as f64 - self.average_lengths[field]) / count;
+        }
+        self.docs.push(document);
+    }
+
+    /// Move the terms and words into shards by their first `prefix_len` characters. The
+    /// index keeps the documents, and the numbers of the shards in `shards`.
+    pub fn into_shards(mut self, prefix_len: usize) -> (SearchIndex, Vec<Shard>) {
+        let mut shards = Shards {
+            prefix_len,
+            files: BTreeMap::new(),
         };
+        let mut contents: Vec<Shard> = vec![];
+        let mut shard_of = |key: String, contents: &mut Vec<Shard>| -> usize {
+            let count = shards.files.len();
+            let n = *shards.files.entry(key).or_insert(count);
+            if n == contents.len() {
+                contents.push(Shard::default());
+            }
+            n
+        };
+        for (term, postings) in std::mem::take(&mut self.terms) {
+            let n = shard_of(shard_key(&term, prefix_len), &mut contents);
+            contents[n].terms.insert(term, postings);
+        }
+        for (word, term) in std::mem::take(&mut self.words) {
+            let n = shard_of(shard_key(&word, prefix_len), &mut contents);
+            contents[n].words.insert(word, term);
+        }
+        self.shards = Some(shards);
+        (self, contents)
+    }
+
+    /// The weighted term frequency of a posting, normalized by the length of the fields.
+    fn weighted_tf(&self, doc: &SearchDocument, tf: &[u32; 4], weights: &[f64; 4]) -> f64 {
+        (0..FIELDS.len())
+            .filter(|field| tf[*field] > 0)
+            .map(|field| {
+                let relative_length = if self.average_lengths[field] > 0. {
+                    doc.lengths[field] as f64 / self.average_lengths[field]
+                } else {
+                    1.
+                };
+                weights[field] * tf[field] as f64 / (1. - self.b + self.b * relative_length)
+            })
+            .sum()
+    }
 
-        for stopword in stopwords {
-            content = content.replace(stopword.as_ref(), " ");
+    fn idf(&self, postings: &[Posting]) -> f64 {
+        let n = self.docs.len() as f64;
+        let df = postings.len() as f64;
+        (1. + (n - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    /// The terms matching the query token, with how well they match. The token is matched
+    /// with the words of the notes as well as with their stems.
+    fn matching_terms(&self, token: &str, stem: &str) -> HashMap<&str, f64> {
+        let mut factors: HashMap<&str, f64> = HashMap::new();
+        let words = self.words.iter();
+        let terms = self.terms.keys().map(|term| (term, term));
+        for (word, term) in words.chain(terms) {
+            let factor = if term == stem {
+                1.0
+            } else {
+                match_factor(token, word)
+            };
+            if factor > 0. {
+                let entry = factors.entry(term.as_str()).or_default();
+                *entry = entry.max(factor);
+            }
         }
+        factors
+    }
+
+    /// The documents matching `query`, best first.
+    ///
+    /// Every token of the query adds the score of its best matching term in a document.
+    /// Documents that don't match all the tokens are ranked lower.
+    pub fn search(&self, query: &str, analyzer: &Analyzer, limit: usize) -> Vec<SearchHit> {
+        self.search_with_weights(query, analyzer, &self.weights, limit)
+    }
+
+    /// Like `search`, with other weights of the fields. Fields with weight `0` are not
+    /// searched.
+    pub fn search_with_weights(
+        &self,
+        query: &str,
+        analyzer: &Analyzer,
+        weights: &[f64; 4],
+        limit: usize,
+    ) -> Vec<SearchHit> {
+        let tokens = analyzer.tokenize(query);
+        let mut scores: HashMap<usize, (f64, usize)> = HashMap::new();
+        let mut matched_tokens = 0;
+        for token in &tokens {
+            let mut best: HashMap<usize, f64> = HashMap::new();
+            for (term, factor) in self.matching_terms(token, &analyzer.stem(token)) {
+                let postings = &self.terms[term];
+                let idf = self.idf(postings);
+                for Posting(doc, tf) in postings {
+                    let doc = *doc as usize;
+                    let tf = self.weighted_tf(&self.docs[doc], tf, weights);
+                    if tf == 0. {
+                        continue;
+                    }
+                    let score = factor * idf * tf * (self.k1 + 1.) / (self.k1 + tf);
+                    let entry = best.entry(doc).or_default();
+                    *entry = entry.max(score);
+                }
+            }
+            if !best.is_empty() {
+                matched_tokens += 1;
+            }
+            for (doc, score) in best {
+                let entry = scores.entry(doc).or_default();
+                entry.0 += score;
+                entry.1 += 1;
+            }
+        }
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .map(|(doc, (score, matches))| SearchHit {
+                doc,
+                score: score * matches as f64 / matched_tokens as f64,
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.doc.cmp(&b.doc)));
+        hits.truncate(limit);
+        hits
+    }
+}
 
-        SearchEntry {
-            path: slugify_path(
-                &prepend_slash(&relative_to(&note.path, input_dir)),
-                Some("html"),
-            )
-            .expect(&format!(
-                "Failed to sluggify note path for note {}.",
-                note.title
-            )),
-            title: &note.title,
-            content,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(analyzer: &Analyzer, text: &str) -> Vec<String> {
+        analyzer.tokenize(text)
+    }
+
+    fn index(analyzer: &Analyzer) -> SearchIndex {
+        let mut index = SearchIndex::new(&FieldWeights::default());
+        let docs = [
+            (
+                "Gardening",
+                "",
+                "",
+                "Tomatoes need plenty of sun and water.",
+            ),
+            (
+                "Recipes",
+                "Tomato soup",
+                "",
+                "Boil the tomatoes, then blend them.",
+            ),
+            (
+                "Journal",
+                "",
+                "daily",
+                "Watered the garden. Read about rust and borrowing.",
+            ),
+        ];
+        for (title, headings, tags, body) in docs {
+            let document = SearchDocument {
+                path: format!("{}.html", title.to_lowercase()),
+                title: title.to_string(),
+                breadcrumb: vec![],
+                lengths: [0; 4],
+                excerpt: String::new(),
+            };
+            index.add_document(
+                document,
+                [
+                    terms(analyzer, title),
+                    terms(analyzer, headings),
+                    terms(analyzer, tags),
+                    terms(analyzer, body),
+                ],
+                analyzer,
+            );
         }
+        index
+    }
+
+    fn titles(index: &SearchIndex, analyzer: &Analyzer, query: &str) -> Vec<String> {
+        index
+            .search(query, analyzer, 10)
+            .iter()
+            .map(|hit| index.docs[hit.doc].title.clone())
+            .collect()
+    }
+
+    fn english(stopwords: &[&str]) -> Analyzer {
+        Analyzer::new(Language::English, stopwords)
+    }
+
+    #[test]
+    fn tokenize_drops_stopwords() {
+        let analyzer = english(&["the ", "and", "a", "don't"]);
+        assert_eq!(
+            analyzer.tokenize("The Theory and a Practice, 2nd ed. Don’t panic"),
+            vec!["theory", "practice", "2nd", "ed", "panic"]
+        );
+    }
+
+    #[test]
+    fn stemming() {
+        let english = Analyzer::for_language(Language::English);
+        assert_eq!(english.stem("gardening"), english.stem("gardens"));
+        let dutch = Analyzer::for_language(Language::Dutch);
+        assert_eq!(dutch.stem("boeken"), dutch.stem("boek"));
+        assert_eq!(
+            dutch.tokenize("de fietsen van mijn buren"),
+            vec!["fietsen", "buren"]
+        );
+    }
+
+    #[test]
+    fn language_codes() {
+        assert_eq!(Language::from_code("nl-BE"), Some(Language::Dutch));
+        assert_eq!(Language::from_code("English"), Some(Language::English));
+        assert_eq!(Language::from_code("fr"), None);
+    }
+
+    #[test]
+    fn fields_are_weighted() {
+        let analyzer = english(&["the", "of", "and", "them", "then", "about"]);
+        let index = index(&analyzer);
+        // The heading of the recipe counts more than the body of the note on gardening.
+        assert_eq!(
+            titles(&index, &analyzer, "tomato soup"),
+            vec!["Recipes", "Gardening"]
+        );
+        assert_eq!(
+            titles(&index, &analyzer, "gardening"),
+            vec!["Gardening", "Journal"]
+        );
+    }
+
+    #[test]
+    fn prefix_and_typos() {
+        let analyzer = english(&["the"]);
+        let index = index(&analyzer);
+        assert_eq!(titles(&index, &analyzer, "borr"), vec!["Journal"]);
+        // Other forms of the words match through their stem.
+        assert_eq!(titles(&index, &analyzer, "borrowed"), vec!["Journal"]);
+        assert_eq!(titles(&index, &analyzer, "jurnal"), vec!["Journal"]);
+        assert_eq!(titles(&index, &analyzer, "daly"), vec!["Journal"]);
+        assert!(titles(&index, &analyzer, "xyz").is_empty());
+    }
+
+    #[test]
+    fn match_factors() {
+        assert_eq!(match_factor("note", "note"), 1.0);
+        assert_eq!(match_factor("notes", "note"), 0.8);
+        assert_eq!(match_factor("no", "note"), 0.7);
+        assert_eq!(match_factor("nite", "note"), 0.5);
+        assert_eq!(match_factor("nit", "not"), 0.0);
+        assert_eq!(match_factor("excercise", "exercises"), 0.5);
+    }
+
+    #[test]
+    fn sections_with_breadcrumbs() {
+        let markdown = "Intro\n# Plants\nSome text.\n## `Tomato` care\nWater.\n# Other\n";
+        let ids = [
+            Some("plants".into()),
+            Some("care".into()),
+            Some("other".into()),
+        ];
+        let sections = sections(markdown, &ids);
+        let breadcrumbs: Vec<Vec<String>> = sections
+            .iter()
+            .map(|section| section.breadcrumb.clone())
+            .collect();
+        assert_eq!(
+            breadcrumbs,
+            vec![
+                vec![],
+                vec!["Plants".to_string()],
+                vec!["Plants".to_string(), "Tomato care".to_string()],
+                vec!["Other".to_string()],
+            ]
+        );
+        assert_eq!(sections[2].id.as_deref(), Some("care"));
+        assert_eq!(sections[2].text.trim(), "Water.");
+        assert_eq!(sections[0].text.trim(), "Intro");
+        // Without the right ids, the sections link to the note itself.
+        assert!(super::sections(markdown, &ids[1..])
+            .iter()
+            .all(|s| s.id.is_none()));
+    }
+
+    #[test]
+    fn sections_without_callout_types_and_labels() {
+        let content = "> [!tip]- Folded title\n> A [[tip|hint]]. ^blk\n\n> [!warning]\n> Careful\n";
+        let note = Note::from_content("note.md".into(), content.into(), Path::new(""), false, &vec![]);
+        let markdown = note.plain_markdown();
+        assert_eq!(markdown, ">  Folded title\n> A hint. \n\n> \n> Careful\n");
+        let text = &sections(&markdown, &[])[0].text;
+        assert!(text.contains("Folded title") && text.contains("A hint."));
+        assert!(!text.contains("[!") && !text.contains("^blk"));
+    }
+
+    #[test]
+    fn shards_by_prefix() {
+        let analyzer = english(&["the"]);
+        let full = index(&analyzer);
+        let (index, shards) = full.clone().into_shards(2);
+        assert!(index.terms.is_empty() && index.words.is_empty());
+        let files = &index.shards.as_ref().unwrap().files;
+        assert_eq!(files.len(), shards.len());
+        // Every term ends up in the shard of its prefix, and nothing gets lost.
+        let tomato = &shards[files["to"]];
+        assert_eq!(tomato.terms["tomato"], full.terms["tomato"]);
+        assert_eq!(tomato.words["tomatoes"], "tomato");
+        let terms: usize = shards.iter().map(|shard| shard.terms.len()).sum();
+        assert_eq!(terms, full.terms.len());
+    }
+
+    #[test]
+    fn excerpt_cuts_at_words() {
+        assert_eq!(excerpt("one  two\nthree", 9), "one two…");
+        assert_eq!(excerpt("one two", 20), "one two");
     }
 }
@@ -0,0 +1,206 @@
+// Force-directed view of the links between the notes.
+//
+// Every element with a `data-graph-src` attribute is filled with the graph at that url, and
+// every `.local-graph` element with the graph in its json script tag.
+// Clicking a node opens the corresponding page.
+
+function cssColor(element, name, fallback) {
+    let value = getComputedStyle(element).getPropertyValue(name).trim();
+    return value.length > 0 ? value : fallback;
+}
+
+function renderGraph(container, graph) {
+    const canvas = document.createElement("canvas");
+    container.appendChild(canvas);
+    const ctx = canvas.getContext("2d");
+
+    const nodes = graph.nodes.map((node, idx) => {
+        // Start on a spiral, so the layout is the same on every visit.
+        let angle = idx * 2.4;
+        let radius = 10 * Math.sqrt(idx + 1);
+        return Object.assign({}, node, {
+            x: radius * Math.cos(angle), y: radius * Math.sin(angle),
+            vx: 0, vy: 0, degree: 0,
+        });
+    });
+    const byId = new Map(nodes.map(node => [node.id, node]));
+    const edges = graph.edges
+        .map(edge => ({ source: byId.get(edge.source), target: byId.get(edge.target) }))
+        .filter(edge => edge.source && edge.target);
+    edges.forEach(edge => { edge.source.degree++; edge.target.degree++; });
+
+    let view = { x: 0, y: 0, scale: 1 };
+    let hovered = null;
+    let alpha = 1;
+
+    function resize() {
+        const ratio = window.devicePixelRatio || 1;
+        canvas.width = container.clientWidth * ratio;
+        canvas.height = container.clientHeight * ratio;
+        canvas.style.width = container.clientWidth + "px";
+        canvas.style.height = container.clientHeight + "px";
+        ctx.setTransform(ratio, 0, 0, ratio, 0, 0);
+    }
+
+    function radius(node) {
+        return 3 + Math.sqrt(node.degree) * 1.5;
+    }
+
+    function step() {
+        // Repulsion between all nodes.
+        for (let i = 0; i < nodes.length; i++) {
+            for (let j = i + 1; j < nodes.length; j++) {
+                const a = nodes[i], b = nodes[j];
+                let dx = b.x - a.x, dy = b.y - a.y;
+                let dist2 = Math.max(dx * dx + dy * dy, 1);
+                if (dist2 > 90000) continue;
+                const force = 200 * alpha / dist2;
+                a.vx -= dx * force; a.vy -= dy * force;
+                b.vx += dx * force; b.vy += dy * force;
+            }
+        }
+        // Springs along the edges.
+        for (const edge of edges) {
+            const a = edge.source, b = edge.target;
+            const dx = b.x - a.x, dy = b.y - a.y;
+            const dist = Math.max(Math.sqrt(dx * dx + dy * dy), 1);
+            const force = 0.05 * alpha * (dist - 50) / dist;
+            a.vx += dx * force; a.vy += dy * force;
+            b.vx -= dx * force; b.vy -= dy * force;
+        }
+        // Keep everything near the center.
+        for (const node of nodes) {
+            node.vx -= node.x * 0.01 * alpha;
+            node.vy -= node.y * 0.01 * alpha;
+            if (node !== dragged) {
+                node.x += node.vx;
+                node.y += node.vy;
+            }
+            node.vx *= 0.6;
+            node.vy *= 0.6;
+        }
+        alpha *= 0.99;
+    }
+
+    function isNeighbour(node) {
+        return hovered !== null && edges.some(edge =>
+            (edge.source === hovered && edge.target === node)
+            || (edge.target === hovered && edge.source === node));
+    }
+
+    function draw() {
+        const width = container.clientWidth, height = container.clientHeight;
+        const text = cssColor(container, "--text-color", "#333");
+        const faint = cssColor(container, "--underline-faded", "#999");
+        const accent = cssColor(container, "--accent-color", "#64b4ff");
+        ctx.clearRect(0, 0, width, height);
+        ctx.save();
+        ctx.translate(width / 2 + view.x, height / 2 + view.y);
+        ctx.scale(view.scale, view.scale);
+
+        ctx.lineWidth = 1 / view.scale;
+        for (const edge of edges) {
+            const active = hovered !== null && (edge.source === hovered || edge.target === hovered);
+            ctx.strokeStyle = active ? accent : faint;
+            ctx.beginPath();
+            ctx.moveTo(edge.source.x, edge.source.y);
+            ctx.lineTo(edge.target.x, edge.target.y);
+            ctx.stroke();
+        }
+
+        ctx.font = (11 / view.scale) + "px sans-serif";
+        ctx.textAlign = "center";
+        for (const node of nodes) {
+            const highlight = node === hovered || isNeighbour(node) || node.current;
+            ctx.fillStyle = highlight ? accent : (node.kind === "tag" ? faint : text);
+            ctx.beginPath();
+            ctx.arc(node.x, node.y, radius(node), 0, 2 * Math.PI);
+            ctx.fill();
+            if (highlight || view.scale > 1.5 || nodes.length < 30) {
+                ctx.fillStyle = text;
+                ctx.fillText(node.title, node.x, node.y - radius(node) - 4 / view.scale);
+            }
+        }
+        ctx.restore();
+    }
+
+    function toGraph(event) {
+        const rect = canvas.getBoundingClientRect();
+        return {
+            x: (event.clientX - rect.left - container.clientWidth / 2 - view.x) / view.scale,
+            y: (event.clientY - rect.top - container.clientHeight / 2 - view.y) / view.scale,
+        };
+    }
+
+    function nodeAt(event) {
+        const point = toGraph(event);
+        return nodes.find(node => {
+            const dx = node.x - point.x, dy = node.y - point.y;
+            return dx * dx + dy * dy <= Math.pow(radius(node) + 3 / view.scale, 2);
+        }) || null;
+    }
+
+    let dragged = null, panning = null, moved = false;
+    canvas.addEventListener("mousedown", event => {
+        moved = false;
+        dragged = nodeAt(event);
+        if (dragged === null) {
+            panning = { x: event.clientX - view.x, y: event.clientY - view.y };
+        }
+    });
+    canvas.addEventListener("mousemove", event => {
+        if (dragged !== null) {
+            const point = toGraph(event);
+            dragged.x = point.x;
+            dragged.y = point.y;
+            alpha = Math.max(alpha, 0.3);
+            moved = true;
+        } else if (panning !== null) {
+            view.x = event.clientX - panning.x;
+            view.y = event.clientY - panning.y;
+            moved = true;
+        } else {
+            hovered = nodeAt(event);
+            canvas.style.cursor = hovered ? "pointer" : "default";
+        }
+    });
+    window.addEventListener("mouseup", () => { dragged = null; panning = null; });
+    canvas.addEventListener("click", event => {
+        const node = nodeAt(event);
+        if (!moved && node !== null && node.url) {
+            window.location.href = node.url;
+        }
+    });
+    canvas.addEventListener("wheel", event => {
+        event.preventDefault();
+        view.scale = Math.min(Math.max(view.scale * Math.exp(-event.deltaY * 0.001), 0.1), 8);
+    }, { passive: false });
+    window.addEventListener("resize", resize);
+
+    resize();
+    function frame() {
+        if (alpha > 0.005) {
+            step();
+        }
+        draw();
+        window.requestAnimationFrame(frame);
+    }
+    frame();
+}
+
+async function loadGraphs() {
+    for (const container of document.querySelectorAll("[data-graph-src], .local-graph")) {
+        // The script may be included more than once.
+        if (container.dataset.rendered) continue;
+        container.dataset.rendered = "true";
+        if (container.dataset.graphSrc) {
+            let response = await fetch(container.dataset.graphSrc);
+            renderGraph(container, await response.json());
+        } else {
+            let data = container.querySelector("script[type='application/json']");
+            renderGraph(container, JSON.parse(data.textContent));
+        }
+    }
+}
+
+loadGraphs();
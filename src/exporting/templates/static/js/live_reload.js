@@ -0,0 +1,7 @@
+// Injected by `oxidian serve`: reload the page whenever the notes are recompiled.
+const reloadSource = new EventSource("{{RELOAD_ENDPOINT}}");
+reloadSource.onmessage = function (event) {
+    if (event.data == "reload") {
+        location.reload();
+    }
+};
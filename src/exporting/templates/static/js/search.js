@@ -1,33 +1,210 @@
+// Ranked search over the inverted index in `static/js/search_index.json`.
+//
+// The index is built by `exporting::search`, with an entry for every heading section of
+// the notes. The tokenization, the matching of query tokens with the words of the index
+// (exact, prefix, shorter forms and typos) and the BM25F scoring are the same as there.
+// The words are stemmed in the index, which maps them to their stems. A sharded index only
+// has the documents, and the shards with the words and terms are fetched when needed.
+
 const selectElement = document.getElementById('filter_input');
+const MAX_RESULTS = 20;
+
+const segmenter = typeof Intl !== "undefined" && Intl.Segmenter
+    ? new Intl.Segmenter(undefined, { granularity: "word" })
+    : null;
+
+// The words of the text, like `unicode_words` of the index.
+function tokenize(text, stopwords) {
+    text = text.toLowerCase().replace(/’/g, "'");
+    let words = segmenter
+        ? Array.from(segmenter.segment(text)).filter(s => s.isWordLike).map(s => s.segment)
+        : text.split(/[^\p{Alphabetic}\p{N}']+/u);
+    return words.filter(word => word.length > 0 && !stopwords.has(word));
+}
+
+function editDistance(a, b, max) {
+    if (Math.abs(a.length - b.length) > max) return null;
+    let previous = Array.from({ length: b.length + 1 }, (_, j) => j);
+    for (let i = 0; i < a.length; i++) {
+        let current = [i + 1];
+        for (let j = 0; j < b.length; j++) {
+            current.push(Math.min(
+                previous[j] + (a[i] === b[j] ? 0 : 1),
+                previous[j + 1] + 1,
+                current[j] + 1));
+        }
+        if (Math.min(...current) > max) return null;
+        previous = current;
+    }
+    return previous[b.length] <= max ? previous[b.length] : null;
+}
+
+function matchFactor(token, term) {
+    if (token === term) return 1.0;
+    const tokenChars = Array.from(token), termChars = Array.from(term);
+    if (termChars.length >= 3 && token.startsWith(term)
+        && tokenChars.length - termChars.length <= 3) {
+        return 0.8;
+    }
+    if (tokenChars.length >= 2 && term.startsWith(token)) return 0.7;
+    if (tokenChars.length < 4) return 0.0;
+    const typos = tokenChars.length < 8 ? 1 : 2;
+    return editDistance(tokenChars, termChars, typos) !== null ? 0.5 : 0.0;
+}
+
+const INDEX_URL = "static/js/search_index.json";
+const SHARD_URL = "static/js/search/";
+
+class SearchIndex {
+    constructor(index) {
+        this.index = index;
+        this.stopwords = new Set(index.stopwords);
+        // The terms are stems, so the queries are matched with the words they came from.
+        this.words = [];
+        this.terms = {};
+        this.loaded = new Map();
+        this.add(index);
+    }
+
+    add(shard) {
+        Object.assign(this.terms, shard.terms);
+        for (const entry of Object.entries(shard.words)) this.words.push(entry);
+        for (const term of Object.keys(shard.terms)) this.words.push([term, term]);
+    }
+
+    // Fetch a shard of a sharded index, once.
+    loadShard(n) {
+        if (!this.loaded.has(n)) {
+            this.loaded.set(n, fetch(`${SHARD_URL}${n}.json`)
+                .then(response => response.json())
+                .then(shard => this.add(shard)));
+        }
+        return this.loaded.get(n);
+    }
+
+    // Fetch the shards that may contain words matching the tokens: the shards of the words
+    // starting with the token, and of the shorter forms of the token.
+    async loadShards(tokens) {
+        const shards = this.index.shards;
+        if (!shards) return;
+        const key = word => Array.from(word).slice(0, shards.prefix_len).join("");
+        const needed = tokens.flatMap(token => Object.entries(shards.files)
+            .filter(([prefix, _]) => prefix.startsWith(key(token)) || key(token).startsWith(prefix))
+            .map(([_, n]) => n));
+        await Promise.all(needed.map(n => this.loadShard(n)));
+        // Words can have a stem with another prefix.
+        const stems = this.words.map(([_, term]) => term).filter(term => !(term in this.terms));
+        await Promise.all(stems
+            .map(term => shards.files[key(term)])
+            .filter(n => n !== undefined)
+            .map(n => this.loadShard(n)));
+    }
+
+    matchingTerms(token) {
+        const factors = new Map();
+        for (const [word, term] of this.words) {
+            const factor = matchFactor(token, word);
+            if (factor > 0) factors.set(term, Math.max(factors.get(term) || 0, factor));
+        }
+        return factors;
+    }
+
+    weightedTf(doc, tf) {
+        let total = 0;
+        for (let field = 0; field < tf.length; field++) {
+            if (tf[field] === 0) continue;
+            const average = this.index.average_lengths[field];
+            const relative = average > 0 ? doc.lengths[field] / average : 1;
+            total += this.index.weights[field] * tf[field]
+                / (1 - this.index.b + this.index.b * relative);
+        }
+        return total;
+    }
 
-function build_link_of_result(res) {
-    return `<a href=${res.obj.path}>${fuzzysort.highlight(res)}</a>`
+    idf(postings) {
+        const n = this.index.docs.length, df = postings.length;
+        return Math.log(1 + (n - df + 0.5) / (df + 0.5));
+    }
+
+    async search(query, limit) {
+        const k1 = this.index.k1;
+        const scores = new Map();
+        let matchedTokens = 0;
+        const tokens = tokenize(query, this.stopwords);
+        await this.loadShards(tokens);
+        for (const token of tokens) {
+            const best = new Map();
+            for (const [term, factor] of this.matchingTerms(token)) {
+                const postings = this.terms[term];
+                if (!postings) continue;
+                const idf = this.idf(postings);
+                for (const [doc, frequencies] of postings) {
+                    const tf = this.weightedTf(this.index.docs[doc], frequencies);
+                    const score = factor * idf * tf * (k1 + 1) / (k1 + tf);
+                    best.set(doc, Math.max(best.get(doc) || 0, score));
+                }
+            }
+            if (best.size > 0) matchedTokens++;
+            for (const [doc, score] of best) {
+                const entry = scores.get(doc) || { score: 0, matches: 0 };
+                entry.score += score;
+                entry.matches++;
+                scores.set(doc, entry);
+            }
+        }
+        return Array.from(scores, ([doc, entry]) => ({
+            doc: this.index.docs[doc],
+            id: doc,
+            score: entry.score * entry.matches / matchedTokens,
+        }))
+            .sort((a, b) => b.score - a.score || a.id - b.id)
+            .slice(0, limit);
+    }
+}
+
+function escapeHtml(text) {
+    return text.replace(/[&<>"']/g, c => ({
+        '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;'
+    })[c]);
 }
 
 function present_search_results(results) {
-    ////console.log(results[0].obj)
-    math_html = results.map(match => `<li> ${build_link_of_result(match)}</li>`);
-    document.getElementById("filter").innerHTML = math_html.join("\n");
+    const output = document.getElementById("filter");
+    if (results.length === 0) {
+        output.innerHTML = '<p class="noresults">No results</p>';
+        return;
+    }
+    const items = results.map(result => {
+        let excerpt = result.doc.excerpt
+            ? `<p class="search-excerpt">${escapeHtml(result.doc.excerpt)}</p>`
+            : "";
+        // Results in a section of a note show the headings leading to it.
+        let name = [result.doc.title].concat(result.doc.breadcrumb || [])
+            .map(escapeHtml)
+            .join(' <span class="search-breadcrumb">›</span> ');
+        return `<li><a href="${escapeHtml(result.doc.path)}">${name}</a>${excerpt}</li>`;
+    });
+    output.innerHTML = `<ul>${items.join("\n")}</ul>`;
 }
 
-async function getSearchIdx() { 
-    let url = "static/js/search_index.json";
-    let response = await fetch(url); 
-    return await response.json();
+async function getSearchIdx() {
+    let response = await fetch(INDEX_URL);
+    return new SearchIndex(await response.json());
 }
 
-async function register_handler() { 
-    let search_idx_raw = await getSearchIdx();
+async function register_handler() {
+    let search_idx = await getSearchIdx();
+    // Shards may arrive after the results of a later query.
+    let latest = 0;
     selectElement.addEventListener(
-        'input', event => {
+        'input', async event => {
             let search_query = event.target.value;
-            if (search_query.length > 0) {
-                let search_result = fuzzysort.go(
-                    search_query, search_idx_raw, {
-                        key: ['title'], limit: 50, threshold: -10000, all:false
-                    }
-                )
-                present_search_results(search_result);
+            let current = ++latest;
+            if (search_query.trim().length > 0) {
+                let results = await search_idx.search(search_query, MAX_RESULTS);
+                if (current === latest) present_search_results(results);
+            } else {
+                document.getElementById("filter").innerHTML = "";
             }
         }
     );
@@ -40,6 +217,7 @@ document.addEventListener('keydown', handleKeypress);
 function handleKeypress(e) {
     if (e.ctrlKey) {
         if ((e.key == "k") || (e.key == "p")) {
+            e.preventDefault();
             activateSearch();
         }
     }
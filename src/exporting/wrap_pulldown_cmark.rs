@@ -1,12 +1,26 @@
 use slugify::slugify;
+use std::collections::VecDeque;
+use std::fmt::Write;
 use std::marker::PhantomData;
-use std::write;
-use pulldown_cmark::{Event, Tag};
-use pulldown_cmark::escape::StrWrite;
+use std::ops::Range;
+use pulldown_cmark::{CowStr, Event, HeadingLevel, OffsetIter, Parser, Tag, TagEnd};
 use pulldown_cmark::html::push_html;
+use crate::components::link::Link;
+use crate::exporting::math::MathRenderer;
+use crate::obsidian::admonitions::Callout;
+use crate::obsidian::headers::HeaderParser;
+use crate::obsidian::labels::{trailing_label, BlockLabel};
+use crate::obsidian::raw_html::{find_raw_html_blocks, RawHtmlBlock};
+use crate::obsidian::{highlights, links, tags};
+use crate::utils::delimiters::{find_pair_ids, DelimPair};
+use crate::utils::utils::markdown_options;
 
+const RAW_HTML_MARKER: &str = "%%RAW_HTML";
+
+/// Converts markdown events to html events, giving the headings an id.
 pub struct MarkdownParser<'a, P> {
     parser: P,
+    math: Option<&'a MathRenderer>,
     _type_hint: PhantomData<&'a P>,
 }
 
@@ -18,39 +32,44 @@ where
     pub fn new(parser: P) -> Self {
         Self {
             parser,
+            math: None,
             _type_hint: PhantomData,
         }
     }
 
-    fn convert_heading(&mut self, level: pulldown_cmark::HeadingLevel, classes: Vec<&str>) -> Event<'a> {
-        // Read events until the end of heading 
+    /// Render the math with `math`, instead of leaving it to the browser.
+    pub fn with_math(mut self, math: Option<&'a MathRenderer>) -> Self {
+        self.math = math;
+        self
+    }
+
+    fn convert_heading(&mut self, level: HeadingLevel, classes: Vec<CowStr<'a>>) -> Event<'a> {
+        // Read events until the end of heading
         let mut buffer = Vec::new();
         let mut content_buffer = String::new();
 
-        while let Some(event) = self.parser.next() {
+        for event in self.parser.by_ref() {
             match &event {
-                Event::End(Tag::Heading(n, _, _)) if n == &level => break,
-                Event::Text(text) => {
+                Event::End(TagEnd::Heading(n)) if n == &level => break,
+                Event::Text(text) | Event::InlineMath(text) | Event::DisplayMath(text) => {
                     write!(content_buffer, "{}", &text)
                     .expect("Could not write text to a string")
                 },
                 _ => {},
             }
-            buffer.push(event.clone()); // Necessary clone, because the inner parser throws them
-                                        // out.
+            buffer.push(math_to_html(event, self.math));
         }
 
         // Convert the events into an HTML Tag
         let mut html = String::with_capacity(content_buffer.capacity());
-        write!(&mut html, "<{}", level).unwrap(); 
-        let header_id = slugify!(&content_buffer);
-        if header_id.len() > 0 {
+        write!(&mut html, "<{}", level).unwrap();
+        if let Some(header_id) = heading_id(&content_buffer) {
             write!(&mut html, " id=\"{}\"", header_id).unwrap();
         }
-        for class in classes { 
+        for class in classes {
             write!(&mut html, " class=\"{}\"", class).unwrap();
         }
-        html.push_str(">");
+        html.push('>');
         push_html(&mut html, buffer.into_iter());
         writeln!(&mut html, "</{}>", level).unwrap();
 
@@ -58,6 +77,80 @@ where
     }
 }
 
+/// Math is given with its delimiters, and is typeset in the browser unless it is rendered
+/// with `renderer`.
+fn math_to_html<'a>(event: Event<'a>, renderer: Option<&MathRenderer>) -> Event<'a> {
+    match (event, renderer) {
+        (Event::InlineMath(math) | Event::DisplayMath(math), Some(renderer)) => {
+            Event::InlineHtml(renderer.render(&math).into())
+        }
+        (Event::InlineMath(math) | Event::DisplayMath(math), None) => Event::InlineHtml(math),
+        (event, _) => event,
+    }
+}
+
+/// The id that `MarkdownParser` gives to a heading with the given text, if any.
+fn heading_id(text: &str) -> Option<String> {
+    Some(slugify!(text)).filter(|id| !id.is_empty())
+}
+
+/// The ids of the headings in the html of `events`, in order. Headings without text have
+/// no id.
+pub fn heading_ids<'a, I: IntoIterator<Item = Event<'a>>>(events: I) -> Vec<Option<String>> {
+    let mut ids = vec![];
+    let mut text: Option<String> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading { id: Some(id), .. }) => ids.push(Some(id.to_string())),
+            Event::Start(Tag::Heading { id: None, .. }) => text = Some(String::new()),
+            Event::Text(content) | Event::InlineMath(content) | Event::DisplayMath(content) => {
+                if let Some(text) = text.as_mut() {
+                    text.push_str(&content);
+                }
+            }
+            Event::End(TagEnd::Heading(..)) => {
+                if let Some(text) = text.take() {
+                    ids.push(heading_id(&text));
+                }
+            }
+            _ => {}
+        }
+    }
+    ids
+}
+
+/// `text` as html, with the special characters escaped.
+pub fn text_to_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    push_html(&mut html, std::iter::once(Event::Text(text.into())));
+    html
+}
+
+/// `text` as html, like `text_to_html`, with the math between `$`s or `$$`s in it rendered
+/// with `renderer`. Without a renderer, the math is left to the browser.
+pub fn text_with_math_to_html(text: &str, renderer: Option<&MathRenderer>) -> String {
+    let Some(renderer) = renderer else {
+        return text_to_html(text);
+    };
+    let mut math = find_pair_ids(text, &DelimPair::new("$$", "$$"));
+    let inline: Vec<(usize, usize)> = find_pair_ids(text, &DelimPair::new("$", "$"))
+        .into_iter()
+        .filter(|(start, end)| !math.iter().any(|(s, e)| start < e && s < end))
+        .collect();
+    math.extend(inline);
+    math.sort();
+
+    let mut html = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, end) in math {
+        html.push_str(&text_to_html(&text[last..start]));
+        html.push_str(&renderer.render(&text[start..end]));
+        last = end;
+    }
+    html.push_str(&text_to_html(&text[last..]));
+    html
+}
+
 impl<'a, P> Iterator for MarkdownParser<'a, P>
 where
     P: Iterator<Item=Event<'a>>,
@@ -67,28 +160,614 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         match self.parser.next() {
             // If we get a header without an id, generate one.
-            Some(Event::Start(Tag::Heading(level, None, classes))) => Some(self.convert_heading(level, classes)),
-            Some(event) => Some(event),
+            Some(Event::Start(Tag::Heading { level, id: None, classes, .. })) => {
+                Some(self.convert_heading(level, classes))
+            }
+            Some(event) => Some(math_to_html(event, self.math)),
             None => None,
         }
     }
 }
 
+/// A piece of a note: a markdown event, or Obsidian syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element<'a> {
+    Markdown(Event<'a>),
+    /// A wikilink, embed, markdown link or url, with the range of its source. `text` is the
+    /// html of the text of markdown links.
+    Link {
+        link: Link,
+        source: Range<usize>,
+        text: Option<String>,
+    },
+    Tag(tags::Tag),
+    HighlightStart,
+    HighlightEnd,
+    /// A `^label` at the end of a block, for blockrefs, with the range of its source.
+    Label { label: BlockLabel, source: Range<usize> },
+    /// Inline or display math, with its delimiters.
+    Math(CowStr<'a>),
+    /// The start of a callout, with the range of its `[!type]` and folding sign.
+    CalloutStart { callout: Callout, source: Range<usize> },
+    CalloutEnd(Callout),
+    /// The html between `%%RAW_HTML` lines.
+    RawHtml(String),
+}
+
+/// The markdown of a note, prepared to be parsed with the Obsidian extensions.
+pub struct ObsidianMarkdown {
+    /// The markdown, with empty lines where Obsidian does not need them: before headings and
+    /// around raw html.
+    text: String,
+    /// `text` with `\(`, `\)`, `\[` and `\]` outside of code replaced by `$$`, so they are
+    /// parsed as math. The ranges of both are the same.
+    parsed: String,
+    raw_html: Vec<RawHtmlBlock>,
+}
+
+impl ObsidianMarkdown {
+    pub fn new(content: &str) -> Self {
+        let text = add_empty_lines(content);
+        let parsed = replace_math_delimiters(&text);
+        let raw_html = find_raw_html_blocks(&text);
+        ObsidianMarkdown {
+            text,
+            parsed,
+            raw_html,
+        }
+    }
+
+    /// The markdown that the ranges of the elements refer to.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn parser(&self) -> ObsidianParser<'_> {
+        ObsidianParser {
+            text: &self.text,
+            events: Parser::new_ext(&self.parsed, markdown_options()).into_offset_iter(),
+            raw_html: &self.raw_html,
+            raw_html_done: 0,
+            inline: vec![],
+            queue: VecDeque::new(),
+            quotes: vec![],
+            callout_title: None,
+            title_paragraph: None,
+            math: None,
+        }
+    }
+}
+
+/// The length of the code fence that `line` opens or closes, with its character.
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let line = line.trim_start();
+    let fence = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == fence).count();
+    (len >= 3).then_some((fence, len))
+}
+
+/// Add the empty lines that markdown needs before headings, which Obsidian does not
+/// need, and around raw html.
+fn add_empty_lines(content: &str) -> String {
+    let mut headers = HeaderParser::new();
+    let mut fence: Option<(char, usize)> = None;
+    let mut raw_html = false;
+    let mut previous_empty = true;
+
+    // Add a bit of buffer capacity for the newlines that we'll add.
+    let mut updated = String::with_capacity(content.len() + 10);
+    for line in content.lines() {
+        let is_marker = line.trim() == RAW_HTML_MARKER;
+        if let Some((open, open_len)) = fence {
+            if code_fence(line).is_some_and(|(c, len)| c == open && len >= open_len) {
+                fence = None;
+            }
+            updated.push_str(line);
+        } else if raw_html || is_marker {
+            if is_marker && !raw_html && !previous_empty {
+                updated.push('\n');
+            }
+            raw_html ^= is_marker;
+            updated.push_str(line);
+            if is_marker && !raw_html {
+                updated.push('\n');
+            }
+        } else {
+            fence = code_fence(line);
+            match headers.process_line(line) {
+                Some(updated_line) => updated.push_str(&updated_line),
+                None => updated.push_str(line),
+            }
+        }
+        previous_empty = line.trim().is_empty();
+        updated.push('\n');
+    }
+    updated
+}
+
+/// The ranges of the code in `markdown`.
+fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(markdown, markdown_options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+fn replace_math_delimiters(text: &str) -> String {
+    if !text.contains("\\(") && !text.contains("\\[") {
+        return text.to_string();
+    }
+    let code = code_ranges(text);
+    let mut parsed = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, delim) in text.match_indices(['(', ')', '[', ']']) {
+        let is_delimiter = start > 0 && text.as_bytes()[start - 1] == b'\\';
+        if !is_delimiter || code.iter().any(|range| range.contains(&start)) {
+            continue;
+        }
+        parsed.push_str(&text[last..start - 1]);
+        parsed.push_str("$$");
+        last = start + delim.len();
+    }
+    parsed.push_str(&text[last..]);
+    parsed
+}
+
+fn is_inline(event: &Event) -> bool {
+    match event {
+        Event::Start(tag) => matches!(
+            tag,
+            Tag::Emphasis
+                | Tag::Strong
+                | Tag::Strikethrough
+                | Tag::Superscript
+                | Tag::Subscript
+                | Tag::Link { .. }
+                | Tag::Image { .. }
+        ),
+        Event::End(tag) => matches!(
+            tag,
+            TagEnd::Emphasis
+                | TagEnd::Strong
+                | TagEnd::Strikethrough
+                | TagEnd::Superscript
+                | TagEnd::Subscript
+                | TagEnd::Link
+                | TagEnd::Image
+        ),
+        Event::Html(_) | Event::Rule => false,
+        _ => true,
+    }
+}
+
+/// A piece of an inline group: text that may contain Obsidian syntax, or an element.
+enum Piece<'a> {
+    Text(Range<usize>),
+    Element(Element<'a>),
+    Highlight,
+}
+
+/// Iterates over the elements of an `ObsidianMarkdown`.
+///
+/// Markdown events are grouped into inlines, which are scanned for Obsidian syntax, and
+/// blocks. Blockquotes that start with `[!type]` are callouts.
+pub struct ObsidianParser<'a> {
+    text: &'a str,
+    events: OffsetIter<'a>,
+    raw_html: &'a [RawHtmlBlock],
+    /// The number of raw html blocks that were emitted.
+    raw_html_done: usize,
+    /// The inline events of the current block.
+    inline: Vec<(Event<'a>, Range<usize>)>,
+    queue: VecDeque<Element<'a>>,
+    /// The open blockquotes, with the callouts among them.
+    quotes: Vec<Option<Callout>>,
+    /// The end of the first line of the current callout, which has its type and title.
+    callout_title: Option<usize>,
+    /// The paragraph that starts with the title of a callout, if it isn't emitted yet.
+    title_paragraph: Option<Event<'a>>,
+    /// Renders the math in the text of markdown links, unless it is typeset in the browser.
+    math: Option<&'a MathRenderer>,
+}
+
+impl<'a> ObsidianParser<'a> {
+    /// Render the math in the text of links with `math`, instead of leaving it to the browser.
+    pub fn with_math(mut self, math: Option<&'a MathRenderer>) -> Self {
+        self.math = math;
+        self
+    }
+
+    /// Whether the event at `range` is part of raw html. The html is emitted at the first one.
+    fn skip_raw_html(&mut self, range: &Range<usize>) -> bool {
+        let Some(index) = self
+            .raw_html
+            .iter()
+            .position(|block| block.range.contains(&range.start))
+        else {
+            return false;
+        };
+        if index >= self.raw_html_done {
+            self.flush_inline(false);
+            self.raw_html_done = index + 1;
+            let html = self.raw_html[index].html.clone();
+            self.queue.push_back(Element::RawHtml(html));
+        }
+        true
+    }
+
+    /// Whether the inline event at `range` is on the first line of a callout. Emits the
+    /// paragraph that the line started, once the content of the callout starts.
+    fn skip_callout_title(&mut self, range: &Range<usize>) -> bool {
+        match self.callout_title {
+            Some(title_end) if range.start <= title_end => true,
+            Some(_) => {
+                self.callout_title = None;
+                if let Some(paragraph) = self.title_paragraph.take() {
+                    self.queue.push_back(Element::Markdown(paragraph));
+                }
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn block(&mut self, event: Event<'a>, range: Range<usize>) {
+        if self.title_paragraph.is_some() {
+            self.callout_title = None;
+            match event {
+                // The callout only has a title.
+                Event::End(TagEnd::Paragraph) => {
+                    self.title_paragraph = None;
+                    return;
+                }
+                _ => self.queue.extend(self.title_paragraph.take().map(Element::Markdown)),
+            }
+        }
+        match event {
+            Event::Start(Tag::BlockQuote(_)) => {
+                let first_line = self.text[range.clone()].lines().next().unwrap_or_default();
+                let callout = first_line
+                    .trim_start()
+                    .strip_prefix('>')
+                    .and_then(Callout::parse);
+                match &callout {
+                    Some(callout) => {
+                        let title_end = range.start + first_line.len();
+                        let start = range.start + first_line.find("[!").unwrap_or_default();
+                        self.callout_title = Some(title_end);
+                        self.queue.push_back(Element::CalloutStart {
+                            callout: callout.clone(),
+                            source: start..title_end - callout.title.len(),
+                        });
+                    }
+                    None => self.queue.push_back(Element::Markdown(event)),
+                }
+                self.quotes.push(callout);
+            }
+            Event::End(TagEnd::BlockQuote(_)) => match self.quotes.pop().flatten() {
+                Some(callout) => self.queue.push_back(Element::CalloutEnd(callout)),
+                None => self.queue.push_back(Element::Markdown(event)),
+            },
+            Event::Start(Tag::Paragraph)
+                if self.callout_title.is_some_and(|end| range.start < end) =>
+            {
+                self.title_paragraph = Some(event);
+            }
+            Event::Html(html) => {
+                let elements = self.html_elements(html, range, false);
+                self.queue.extend(elements);
+            }
+            event => {
+                self.callout_title = None;
+                self.queue.push_back(Element::Markdown(event));
+            }
+        }
+    }
+
+    /// The elements of html: links and tags are recognised in it, except in math.
+    fn html_elements(&self, html: CowStr<'a>, range: Range<usize>, inline: bool) -> Vec<Element<'a>> {
+        let as_event = |html: CowStr<'a>| match inline {
+            true => Event::InlineHtml(html),
+            false => Event::Html(html),
+        };
+        let source = &self.text[range.clone()];
+        if source != html.as_ref() {
+            return vec![Element::Markdown(as_event(html))];
+        }
+        let mut math = find_pair_ids(source, &DelimPair::new("$$", "$$"));
+        math.extend(find_pair_ids(source, &DelimPair::new("$", "$")));
+        let in_math = |found: &Range<usize>| math.iter().any(|(start, end)| found.start < *end && *start < found.end);
+
+        let mut found: Vec<(Range<usize>, Element<'a>)> = vec![];
+        let obsidian_links = links::locate_obsidian_links(source).into_iter();
+        let markdown_links = links::locate_markdown_links(source).into_iter();
+        for (link_range, link) in obsidian_links.chain(markdown_links) {
+            if !in_math(&link_range) {
+                add_found(&mut found, link_range.clone(), Element::Link {
+                    link,
+                    source: link_range.start + range.start..link_range.end + range.start,
+                    text: None,
+                });
+            }
+        }
+        for (tag_range, tag) in tags::locate_tags(source, true) {
+            if !in_math(&tag_range) {
+                add_found(&mut found, tag_range, Element::Tag(tag));
+            }
+        }
+        found.sort_by_key(|(found_range, _)| found_range.start);
+
+        let mut elements = vec![];
+        let mut last = 0;
+        for (found_range, element) in found {
+            if found_range.start > last {
+                elements.push(Element::Markdown(as_event(source[last..found_range.start].into())));
+            }
+            elements.push(element);
+            last = found_range.end;
+        }
+        if last < source.len() {
+            elements.push(Element::Markdown(as_event(source[last..].into())));
+        }
+        elements
+    }
+
+    /// Keep the blanks at the end of the line of the last inline event, before `line_end`,
+    /// which the markdown parser drops.
+    fn keep_trailing_blanks(&mut self, line_end: usize) {
+        let Some(end) = self.inline.last().map(|(_, range)| range.end) else {
+            return;
+        };
+        let rest = &self.text[end..line_end.max(end)];
+        let blanks = &rest[..rest.find('\n').unwrap_or(rest.len())];
+        if !blanks.is_empty() && blanks.trim_matches([' ', '\t']).is_empty() {
+            self.inline.push((Event::Text(blanks.into()), end..end + blanks.len()));
+        }
+    }
+
+    /// The markdown link that starts at `self.inline[start]`, and the index of its end.
+    fn markdown_link(&self, start: usize) -> Option<(Element<'a>, usize)> {
+        let range = &self.inline[start].1;
+        let link = links::parse_markdown_link(&self.text[range.clone()])?;
+        let mut depth = 0;
+        let mut content = vec![];
+        for (index, (event, event_range)) in self.inline.iter().enumerate().skip(start) {
+            match event {
+                Event::Start(Tag::Link { .. } | Tag::Image { .. }) => depth += 1,
+                Event::End(TagEnd::Link | TagEnd::Image) => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                let mut text = String::new();
+                let events = content.into_iter().skip(1);
+                push_html(&mut text, events.map(|event| math_to_html(event, self.math)));
+                let element = Element::Link {
+                    text: (!link.is_attachment).then_some(text),
+                    link,
+                    source: range.clone(),
+                };
+                return Some((element, index));
+            }
+            match event {
+                // Math is given with its delimiters, like outside links.
+                Event::InlineMath(_) | Event::DisplayMath(_) => {
+                    content.push(Event::InlineMath(self.text[event_range.clone()].into()))
+                }
+                _ => content.push(event.clone()),
+            }
+        }
+        None
+    }
+
+    /// Scan the inline events of the current block for Obsidian syntax.
+    fn flush_inline(&mut self, at_block_end: bool) {
+        if self.inline.is_empty() {
+            return;
+        }
+        let mut pieces: Vec<Piece<'a>> = vec![];
+        let mut index = 0;
+        while index < self.inline.len() {
+            if let Event::Start(Tag::Link { .. } | Tag::Image { .. }) = self.inline[index].0 {
+                if let Some((element, end)) = self.markdown_link(index) {
+                    pieces.push(Piece::Element(element));
+                    index = end + 1;
+                    continue;
+                }
+            }
+            let (event, range) = self.inline[index].clone();
+            index += 1;
+            match event {
+                // Only text that is written as it is can contain Obsidian syntax, not escaped
+                // characters.
+                Event::Text(text) if *text == self.text[range.clone()] => match pieces.last_mut() {
+                    Some(Piece::Text(run)) if run.end == range.start => run.end = range.end,
+                    _ => pieces.push(Piece::Text(range)),
+                },
+                Event::InlineMath(_) | Event::DisplayMath(_) => {
+                    let math = CowStr::Borrowed(&self.text[range]);
+                    pieces.push(Piece::Element(Element::Math(math)));
+                }
+                Event::InlineHtml(html) => {
+                    for element in self.html_elements(html, range, true) {
+                        pieces.push(Piece::Element(element));
+                    }
+                }
+                event => pieces.push(Piece::Element(Element::Markdown(event))),
+            }
+        }
+        self.inline.clear();
+
+        let mut pieces: Vec<Piece<'a>> = pieces
+            .into_iter()
+            .flat_map(|piece| match piece {
+                Piece::Text(run) => self.scan_text(run),
+                piece => vec![piece],
+            })
+            .collect();
+
+        // The `^label` of the block.
+        if at_block_end {
+            if let Some(Piece::Text(run)) = pieces.last() {
+                let run = run.clone();
+                if let Some((label_range, label)) = trailing_label(&self.text[run.clone()]) {
+                    pieces.pop();
+                    let source = run.start + label_range.start..run.start + label_range.end;
+                    pieces.push(Piece::Text(run.start..source.start));
+                    pieces.push(Piece::Element(Element::Label { label, source }));
+                }
+            }
+        }
+
+        // Highlights are between pairs of `==`.
+        let highlights = pieces.iter().filter(|piece| matches!(piece, Piece::Highlight)).count();
+        let mut opened = 0;
+        for piece in pieces {
+            let element = match piece {
+                Piece::Text(run) if run.is_empty() => continue,
+                Piece::Text(run) => Element::Markdown(Event::Text(self.text[run].into())),
+                Piece::Highlight if opened >= highlights - highlights % 2 => {
+                    Element::Markdown(Event::Text("==".into()))
+                }
+                Piece::Highlight => {
+                    opened += 1;
+                    match opened % 2 {
+                        1 => Element::HighlightStart,
+                        _ => Element::HighlightEnd,
+                    }
+                }
+                Piece::Element(element) => element,
+            };
+            self.queue.push_back(element);
+        }
+    }
+
+    /// Split the text at `run` into text and Obsidian syntax: wikilinks, embeds, markdown
+    /// links that the markdown parser does not accept, urls, tags and highlights.
+    fn scan_text(&self, run: Range<usize>) -> Vec<Piece<'a>> {
+        let text = &self.text[run.clone()];
+        let offset = |found: Range<usize>| found.start + run.start..found.end + run.start;
+        let at_boundary = self.text[..run.start]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+
+        let mut found: Vec<(Range<usize>, Piece<'a>)> = vec![];
+        let obsidian_links = links::locate_obsidian_links(text).into_iter();
+        let markdown_links = links::locate_markdown_links(text).into_iter();
+        let raw_links = links::locate_raw_links(text).into_iter();
+        for (range, link) in obsidian_links.chain(markdown_links).chain(raw_links) {
+            let element = Element::Link {
+                link,
+                source: offset(range.clone()),
+                text: None,
+            };
+            add_found(&mut found, range, Piece::Element(element));
+        }
+        for (range, tag) in tags::locate_tags(text, at_boundary) {
+            add_found(&mut found, range, Piece::Element(Element::Tag(tag)));
+        }
+        for range in highlights::find_highlight_delimiters(text) {
+            add_found(&mut found, range, Piece::Highlight);
+        }
+        found.sort_by_key(|(range, _)| range.start);
+
+        let mut pieces = vec![];
+        let mut last = 0;
+        for (range, piece) in found {
+            pieces.push(Piece::Text(offset(last..range.start)));
+            pieces.push(piece);
+            last = range.end;
+        }
+        pieces.push(Piece::Text(offset(last..text.len())));
+        pieces
+    }
+}
+
+/// Add `element` at `range`, unless it overlaps with an element that was found before.
+fn add_found<E>(found: &mut Vec<(Range<usize>, E)>, range: Range<usize>, element: E) {
+    if !found.iter().any(|(other, _)| range.start < other.end && other.start < range.end) {
+        found.push((range, element));
+    }
+}
+
+impl<'a> Iterator for ObsidianParser<'a> {
+    type Item = Element<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(element) = self.queue.pop_front() {
+                return Some(element);
+            }
+            let Some((event, range)) = self.events.next() else {
+                if self.inline.is_empty() {
+                    return None;
+                }
+                self.flush_inline(false);
+                continue;
+            };
+            if self.skip_raw_html(&range) {
+                continue;
+            }
+            if is_inline(&event) {
+                if !self.skip_callout_title(&range) {
+                    if event == Event::SoftBreak {
+                        self.keep_trailing_blanks(range.start);
+                    }
+                    self.inline.push((event, range));
+                }
+                continue;
+            }
+            if let Event::End(TagEnd::Paragraph | TagEnd::Item) = event {
+                self.keep_trailing_blanks(range.end);
+            }
+            self.flush_inline(matches!(event, Event::End(_)));
+            self.block(event, range);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::exporting::config::MathPrerender;
 
     fn convert(s: &str) -> String {
         let mut buf = String::new();
         let mut options = pulldown_cmark::Options::empty();
         options.insert(pulldown_cmark::Options::ENABLE_HEADING_ATTRIBUTES);
-        
+
         let basic_parser = pulldown_cmark::Parser::new_ext(s, options);
         let parser = MarkdownParser::new(basic_parser);
         pulldown_cmark::html::push_html(&mut buf, parser);
         buf
     }
 
+    fn elements(s: &str) -> Vec<Element<'static>> {
+        let markdown = ObsidianMarkdown::new(s);
+        markdown
+            .parser()
+            .map(|element| match element {
+                Element::Markdown(event) => Element::Markdown(event.into_static()),
+                Element::Math(math) => Element::Math(math.into_static()),
+                Element::Link { link, source, text } => Element::Link { link, source, text },
+                Element::Tag(tag) => Element::Tag(tag),
+                Element::HighlightStart => Element::HighlightStart,
+                Element::HighlightEnd => Element::HighlightEnd,
+                Element::Label { label, source } => Element::Label { label, source },
+                Element::CalloutStart { callout, source } => Element::CalloutStart { callout, source },
+                Element::CalloutEnd(callout) => Element::CalloutEnd(callout),
+                Element::RawHtml(html) => Element::RawHtml(html),
+            })
+            .collect()
+    }
+
+    fn text(s: &str) -> Element<'static> {
+        Element::Markdown(Event::Text(s.to_string().into()))
+    }
+
     #[test]
     fn heading_id() {
         let s = "## Heading {#heading-id}";
@@ -126,7 +805,7 @@ mod test {
     fn empty() {
         assert_eq!(convert("##").trim_end(), "<h2></h2>");
     }
-    
+
     #[test]
     fn with_link() {
         let s = "### [Link](https://example.com/) {#example}";
@@ -134,10 +813,165 @@ mod test {
             r#"<h3 id="example"><a href="https://example.com/">Link</a></h3>"#);
     }
 
+    #[test]
+    fn ids() {
+        let s = "# First\n\ntext\n\n## Second {#custom}\n\n### `code` only\n\n##\n\n## With $x$";
+        assert_eq!(heading_ids(Parser::new_ext(s, markdown_options())), vec![
+            Some("first".to_string()), Some("custom".to_string()), Some("only".to_string()), None,
+            Some("with-x".to_string()),
+        ]);
+    }
+
     #[test]
     fn to_be_escaped() {
         let s = "## ><";
         assert_eq!(convert(s).trim_end(), "<h2>&gt;&lt;</h2>");
     }
-}
 
+    #[test]
+    fn math_in_heading() {
+        let heading = Tag::Heading {
+            level: HeadingLevel::H2,
+            id: None,
+            classes: vec![],
+            attrs: vec![],
+        };
+        let events = vec![
+            Event::Start(heading),
+            Event::Text("Case ".into()),
+            Event::InlineMath("$N > 1$".into()),
+            Event::End(TagEnd::Heading(HeadingLevel::H2)),
+        ];
+        let mut html = String::new();
+        push_html(&mut html, MarkdownParser::new(events.into_iter()));
+        assert_eq!(html.trim_end(), r#"<h2 id="case-n-1">Case $N > 1$</h2>"#);
+    }
+
+    #[test]
+    fn empty_lines_before_headings() {
+        assert_eq!(add_empty_lines("text\n# Heading\n#tag"), "text\n\n# Heading\n#tag\n");
+        assert_eq!(add_empty_lines("```\ncode\n# comment\n```"), "```\ncode\n# comment\n```\n");
+    }
+
+    #[test]
+    fn latex_delimiters() {
+        assert_eq!(replace_math_delimiters(r"\(x\) and `\(y\)`"), r"$$x$$ and `\(y\)`");
+    }
+
+    #[test]
+    fn wikilinks_and_tags() {
+        let found = elements("See [[note|alias]] #tag, not `[[code]]` or a#b.");
+        let links: Vec<&Element> = found
+            .iter()
+            .filter(|element| matches!(element, Element::Link { .. }))
+            .collect();
+        assert_eq!(links.len(), 1);
+        if let Element::Link { link, source, .. } = links[0] {
+            assert_eq!(link.source_string, "[[note|alias]]");
+            assert_eq!(*source, 4..18);
+        }
+        let tags: Vec<&Element> = found
+            .iter()
+            .filter(|element| matches!(element, Element::Tag(_)))
+            .collect();
+        assert_eq!(tags.len(), 1);
+    }
+
+    #[test]
+    fn markdown_link_text() {
+        let found = elements("A [**bold** link](other.md).");
+        assert!(found.iter().any(|element| matches!(element,
+            Element::Link { text: Some(text), .. } if text == "<strong>bold</strong> link")));
+    }
+
+    #[test]
+    fn math_in_link_text() {
+        let renderer = MathRenderer::new(MathPrerender::Mathml).unwrap();
+        let markdown = ObsidianMarkdown::new("A [$x$ link](other.md).");
+        let rendered: Vec<Element> = markdown.parser().with_math(Some(&renderer)).collect();
+        assert!(rendered.iter().any(|element| matches!(element,
+            Element::Link { text: Some(text), .. } if text.contains("<mi>x</mi>") && text.ends_with(" link"))));
+        assert!(elements("A [$x$ link](other.md).").iter().any(|element| matches!(element,
+            Element::Link { text: Some(text), .. } if text == "$x$ link")));
+
+        let alias = text_with_math_to_html("<$x$ and $$y$$>", Some(&renderer));
+        assert!(alias.starts_with("&lt;<span class=\"katex\">"));
+        assert!(alias.contains("<mi>x</mi>"));
+        assert!(alias.contains("display=\"block\""));
+        assert!(alias.ends_with("&gt;"));
+        assert_eq!(text_with_math_to_html("<$x$>", None), "&lt;$x$&gt;");
+    }
+
+    #[test]
+    fn highlights() {
+        let found = elements("Some ==marked== text ==");
+        assert_eq!(found[1..6], [
+            text("Some "),
+            Element::HighlightStart,
+            text("marked"),
+            Element::HighlightEnd,
+            text(" text "),
+        ]);
+        assert_eq!(found[6], text("=="));
+    }
+
+    #[test]
+    fn labels() {
+        let found = elements("A lemma. ^lemma\n\nNot ^here.");
+        assert!(found.iter().any(|element| matches!(element,
+            Element::Label { label, source } if label.label == "lemma" && *source == (9..15))));
+        assert_eq!(found.iter().filter(|element| matches!(element, Element::Label { .. })).count(), 1);
+    }
+
+    #[test]
+    fn math() {
+        let found = elements(r"Let $x_1 *y*$ and \(z\).");
+        assert!(found.contains(&Element::Math(r"$x_1 *y*$".into())));
+        assert!(found.contains(&Element::Math(r"\(z\)".into())));
+    }
+
+    #[test]
+    fn callouts() {
+        let found = elements("> [!tip] Title\n> Content\n\n> Quote");
+        assert!(matches!(&found[0], Element::CalloutStart { callout, source }
+            if callout.ad_type == "tip" && *source == (2..8)));
+        assert_eq!(found[1], Element::Markdown(Event::Start(Tag::Paragraph)));
+        assert_eq!(found[2], text("Content"));
+        assert!(matches!(found[4], Element::CalloutEnd(_)));
+        assert!(found.contains(&Element::Markdown(Event::Start(Tag::BlockQuote(None)))));
+    }
+
+    #[test]
+    fn callout_with_title_only() {
+        let found = elements("> [!note] Title");
+        assert_eq!(found.len(), 2);
+    }
+
+    #[test]
+    fn nested_foldable_callouts() {
+        let found = elements("> [!note]- Outer\n> > [!my-type]+ Inner\n> > Inside\n>\n> Outside");
+        let callouts: Vec<(bool, &str)> = found
+            .iter()
+            .filter_map(|element| match element {
+                Element::CalloutStart { callout, .. } => Some((true, callout.ad_type.as_str())),
+                Element::CalloutEnd(callout) => Some((false, callout.ad_type.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            callouts,
+            [(true, "note"), (true, "my-type"), (false, "my-type"), (false, "note")]
+        );
+        assert!(found.contains(&text("Inside")));
+        assert!(found.contains(&text("Outside")));
+        assert!(!found.iter().any(|element| matches!(element,
+            Element::Markdown(Event::Text(text)) if text.contains("Outer") || text.contains("Inner"))));
+    }
+
+    #[test]
+    fn raw_html() {
+        let found = elements("Text\n%%RAW_HTML\n<b>[[x]]</b>\n%%RAW_HTML\nMore");
+        assert!(found.contains(&Element::RawHtml("<b>[[x]]</b>\n".to_string())));
+        assert!(!found.iter().any(|element| matches!(element, Element::Link { .. })));
+    }
+}
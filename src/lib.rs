@@ -5,6 +5,7 @@ pub mod core;
 pub mod utils;
 
 pub mod exporting;
+pub mod server;
 
 
 
@@ -1,46 +1,105 @@
 use crate::core::html::wrap_html_raw;
-use crate::core::sanitization::Sanitization;
+use crate::exporting::config::CalloutType;
+use log::warn;
 use regex::Regex;
 
 lazy_static! {
     static ref OBS_ADMONITION_TITLE_RE: Regex =
-        Regex::new(r"\s*(?:\[!(?P<type>[aA-zZ)]+)\])(?P<title>[^\n*]*)").unwrap();
-    static ref OBS_HEADER_RE: Regex = Regex::new(r"^[[:blank:]]?#+\s").unwrap();
-}
-
-pub struct AdmonitionParser {
-    state: AdmonitionState,
+        Regex::new(r"^\s*\[!(?P<type>[\w-]+)\](?P<fold>[+-])?(?P<title>[^\n]*)").unwrap();
 }
 
+///The types of callouts that Obsidian knows, with their aliases.
+const CALLOUT_ALIASES: [(&str, &[&str]); 13] = [
+    ("note", &[]),
+    ("abstract", &["summary", "tldr"]),
+    ("info", &[]),
+    ("todo", &[]),
+    ("tip", &["hint", "important"]),
+    ("success", &["check", "done"]),
+    ("question", &["help", "faq"]),
+    ("warning", &["caution", "attention"]),
+    ("failure", &["fail", "missing"]),
+    ("danger", &["error"]),
+    ("bug", &[]),
+    ("example", &[]),
+    ("quote", &["cite"]),
+];
 
-pub enum ParseOutput {
-    Placeholder { 
-        replacement: String, 
-        placeholder: Option<Sanitization>
-    },
-    None,
+///Whether a callout can be folded, and if it starts folded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Folding {
+    ///`[!type]`: always shown.
+    Fixed,
+    ///`[!type]+`: shown, but can be folded.
+    Expanded,
+    ///`[!type]-`: folded, but can be shown.
+    Collapsed,
 }
 
-enum AdmonitionState {
-    Idle,
-    Body,
-    SingleBreak,
+///The first line of a callout: `> [!type] title`, or `> [!type]- title` for foldable ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Callout {
+    ///The type as written, in lowercase.
+    pub ad_type: String,
+    pub title: String,
+    pub folding: Folding,
 }
 
-impl AdmonitionParser {
-    pub fn new() -> Self {
-        AdmonitionParser {
-            state: AdmonitionState::Idle,
+impl Callout {
+    ///The callout that `line` starts, if any. `line` is the first line of a quote, without
+    ///the `>`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let captures = OBS_ADMONITION_TITLE_RE.captures(line)?;
+        let folding = match captures.name("fold").map(|fold| fold.as_str()) {
+            Some("+") => Folding::Expanded,
+            Some(_) => Folding::Collapsed,
+            None => Folding::Fixed,
+        };
+        Some(Callout {
+            ad_type: captures["type"].to_lowercase(),
+            title: captures["title"].to_string(),
+            folding,
+        })
+    }
+
+    ///The type that the type of the callout is an alias of, like `tip` for `hint`. Unknown
+    ///types are their own.
+    pub fn base_type(&self) -> &str {
+        CALLOUT_ALIASES
+            .iter()
+            .find(|(_, aliases)| aliases.contains(&self.ad_type.as_str()))
+            .map_or(&self.ad_type, |(base, _)| base)
+    }
+
+    ///The title, or the capitalised type if the callout has none, like Obsidian does.
+    fn title_or_type(&self) -> String {
+        if !self.title.trim().is_empty() {
+            return self.title.clone();
         }
+        let mut chars = self.ad_type.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
     }
 
-    fn start_admonition(ad_type: &str, title: &str) -> String {
-        let mut title_html = format!("<div class=\"admonition admonition-note {}\">\n", ad_type);
+    ///The html that opens the callout, up to its content. Foldable callouts are `<details>`.
+    pub fn start_html(&self) -> String {
+        let base_type = self.base_type();
+        let (tag, title_tag, open) = match self.folding {
+            Folding::Fixed => ("div", "div", ""),
+            Folding::Expanded => ("details", "summary", " open"),
+            Folding::Collapsed => ("details", "summary", ""),
+        };
+        let mut title_html = format!(
+            "<{} class=\"admonition admonition-note {}\" data-callout=\"{}\"{}>\n",
+            tag, base_type, self.ad_type, open
+        );
         title_html.push_str(
             wrap_html_raw(
-                title,
-                "div",
-                format!("class=\"admonition-title {}\"", ad_type),
+                self.title_or_type(),
+                title_tag,
+                format!("class=\"admonition-title {}\"", base_type),
             )
             .as_ref(),
         );
@@ -48,91 +107,121 @@ impl AdmonitionParser {
         title_html
     }
 
+    ///The html that closes the callout.
+    pub fn end_html(&self) -> &'static str {
+        match self.folding {
+            Folding::Fixed => "</div></div>\n",
+            Folding::Expanded | Folding::Collapsed => "</div></details>\n",
+        }
+    }
+}
 
-    pub fn process_line(&mut self, line: &str) -> ParseOutput {
-        match self.state {
-            AdmonitionState::Idle => {
-                if let Some(captures) = OBS_ADMONITION_TITLE_RE.captures(line) {
-                    let ad_type = captures
-                        .name("type")
-                        .map(|v| v.as_str())
-                        .expect("Wrong regex!")
-                        .to_lowercase();
-                    let title = captures
-                        .name("title")
-                        .map(|v| v.as_str())
-                        .expect("Wrong regex!");
-                    self.state = AdmonitionState::Body;
-                    let replacement = Self::start_admonition(&ad_type, title);
-                    let sanitization = Sanitization::new(line, replacement, false); 
-                    ParseOutput::Placeholder { 
-                        replacement: sanitization.get_placeholder(), 
-                        placeholder: Some(sanitization)
-                    }
-                } else {
-                    ParseOutput::None
-                }
-            },
-            AdmonitionState::Body | AdmonitionState::SingleBreak => {
-                let trimmed_line = line.trim_start();
-                if trimmed_line.starts_with(">") {
-                    ParseOutput::Placeholder { 
-                        replacement: trimmed_line[1..].to_string(),
-                        placeholder: None 
-                    }
-                } else {
-                    match self.state {
-                        AdmonitionState::Body => {
-                            if OBS_HEADER_RE.is_match(line) {
-                                // Detected a header. This is a special case,
-                                // in which the admonition is ended and the header is
-                                // immediately placed.
-                                //
-                                self.state = AdmonitionState::Idle;
-                                // Add a closing div tag, but sanitize it away, so it doesn't 
-                                // confuse the markdown renderer. 
-                                // That is, `</div>` will be replaced with a hash.
-                                // Since the flag `false` is passed, 
-                                // it will be substituted back AFTER markdown compilation.
-                                // The original line simply gets added back to the content
-                                // unchanged.
-                                let addition = "</div></div>";
-                                let sanitization = Sanitization::new(
-                                        addition.to_string(), 
-                                        addition.to_string(), false);
-                                let replacement = sanitization.get_placeholder() + "\n" + line;
-                                let placeholder = Some(sanitization);
-
-                                return ParseOutput::Placeholder{
-                                        replacement, placeholder 
-                                    };
-                            }
-                            self.state = AdmonitionState::SingleBreak;
-                            ParseOutput::None
-                        }
-                        AdmonitionState::SingleBreak => {
-                            // There already was a break before this
-                            // line, so a new line that does not
-                            // start with `>` automatically breaks
-                            // the admonition.
-                            self.state = AdmonitionState::Idle; 
-
-                            let addition = "</div></div>";
-                            let sanitization = Sanitization::new(addition, addition, false);
-                            //let replacement = line.to_string() + "\n" + &sanitization.get_placeholder();
-                            let replacement = sanitization.get_placeholder() + "\n" + line;
-                            let placeholder = Some(sanitization);
-
-                            ParseOutput::Placeholder{
-                                    replacement, placeholder 
-                            }
-                        }
-                        _ => {
-                            panic!("Impossible.");
-                        }
-                    }
-                }
+///The colour of a custom callout type as `r, g, b`, from `#rrggbb` or `r, g, b`.
+fn rgb(color: &str) -> Option<String> {
+    let color = color.trim();
+    let channels: Vec<u8> = match color.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => (0..6)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<_>>()?,
+        Some(_) => return None,
+        None => color
+            .split(',')
+            .map(|channel| channel.trim().parse().ok())
+            .collect::<Option<_>>()?,
+    };
+    match channels[..] {
+        [r, g, b] => Some(format!("{}, {}, {}", r, g, b)),
+        _ => None,
+    }
+}
+
+///The css of the callout types declared in the configuration, in a `<style>` element. Empty
+///if there are none.
+pub fn custom_callout_style(types: &[CalloutType]) -> String {
+    let mut css = String::new();
+    for callout_type in types {
+        let name = callout_type.name.to_lowercase();
+        if !OBS_ADMONITION_TITLE_RE.is_match(&format!("[!{}]", name)) {
+            warn!("Callout type `{}` can't be used in notes, so it is left out.", name);
+            continue;
+        }
+        let selector = format!(".admonition[data-callout=\"{}\"]", name);
+        if let Some(color) = &callout_type.color {
+            match rgb(color) {
+                Some(rgb) => css.push_str(&format!("{} {{ --admonition-rgb: {}; }}\n", selector, rgb)),
+                None => warn!("Invalid colour `{}` of callout type `{}`.", color, name),
             }
         }
+        if let Some(icon) = &callout_type.icon {
+            let icon: String = icon.chars().filter(|c| !matches!(c, '"' | '\\' | '<')).collect();
+            css.push_str(&format!(
+                "{} > .admonition-title::before {{ content: \"{}\"; }}\n",
+                selector, icon
+            ));
+        }
+    }
+    match css.is_empty() {
+        true => css,
+        false => format!("<style>\n{}</style>", css),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{custom_callout_style, Callout, Folding};
+    use crate::exporting::config::CalloutType;
+
+    #[test]
+    fn callout_line() {
+        let callout = Callout::parse(" [!NOTE] Some title").unwrap();
+        assert_eq!(callout.ad_type, "note");
+        assert_eq!(callout.title, " Some title");
+        assert_eq!(callout.folding, Folding::Fixed);
+        assert_eq!(Callout::parse("Not a [!note]"), None);
+
+        let callout = Callout::parse(" [!my-type]- Folded").unwrap();
+        assert_eq!(callout.ad_type, "my-type");
+        assert_eq!(callout.folding, Folding::Collapsed);
+        assert_eq!(callout.base_type(), "my-type");
+    }
+
+    #[test]
+    fn aliases_and_folding() {
+        let callout = Callout::parse(" [!Hint]+").unwrap();
+        assert_eq!(callout.base_type(), "tip");
+        assert_eq!(
+            callout.start_html(),
+            "<details class=\"admonition admonition-note tip\" data-callout=\"hint\" open>\n\
+             <summary class=\"admonition-title tip\">Hint</summary>\
+             <div class=\"admonition-content\">"
+        );
+        assert_eq!(callout.end_html(), "</div></details>\n");
+        assert!(!Callout::parse(" [!faq]- Why?").unwrap().start_html().contains("open"));
+    }
+
+    #[test]
+    fn custom_types() {
+        let types = [
+            CalloutType {
+                name: "Recipe".to_string(),
+                color: Some("#ff8000".to_string()),
+                icon: Some("restaurant".to_string()),
+            },
+            CalloutType {
+                name: "proof".to_string(),
+                color: Some("12, 34".to_string()),
+                icon: None,
+            },
+        ];
+        assert_eq!(
+            custom_callout_style(&types),
+            "<style>\n\
+             .admonition[data-callout=\"recipe\"] { --admonition-rgb: 255, 128, 0; }\n\
+             .admonition[data-callout=\"recipe\"] > .admonition-title::before \
+             { content: \"restaurant\"; }\n\
+             </style>"
+        );
+        assert_eq!(custom_callout_style(&[]), "");
     }
 }
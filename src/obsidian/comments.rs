@@ -0,0 +1,100 @@
+use pulldown_cmark::{Event, Parser, Tag};
+use regex::Regex;
+use std::borrow::Cow;
+use std::ops::Range;
+
+use super::raw_html::find_raw_html_blocks;
+use crate::utils::constants::OBS_COMMENTS;
+use crate::utils::utils::markdown_options;
+
+lazy_static! {
+    static ref LATEX_MATH_RE: Regex = Regex::new(r"(?s)\\\(.*?\\\)|\\\[.*?\\\]").unwrap();
+}
+
+///The ranges of `content` in which `%%` does not start or end a comment: code, math and raw
+///html.
+fn protected_ranges(content: &str) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Parser::new_ext(content, markdown_options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_)
+            | Event::InlineMath(_)
+            | Event::DisplayMath(_)
+            | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect();
+    ranges.extend(LATEX_MATH_RE.find_iter(content).map(|found| found.range()));
+    ranges.extend(find_raw_html_blocks(content).into_iter().map(|block| block.range));
+    ranges
+}
+
+///The ranges of the Obsidian comments in `content`, with their `%%` delimiters. A comment
+///that is not closed runs until the end of the content, like in Obsidian.
+pub fn find_comments(content: &str) -> Vec<Range<usize>> {
+    if !content.contains(OBS_COMMENTS) {
+        return vec![];
+    }
+    let protected = protected_ranges(content);
+    let mut comments = vec![];
+    let mut start: Option<usize> = None;
+    let mut from = 0;
+    while let Some(found) = content[from..].find(OBS_COMMENTS) {
+        let delimiter = from + found;
+        from = delimiter + OBS_COMMENTS.len();
+        if protected.iter().any(|range| range.contains(&delimiter)) {
+            continue;
+        }
+        match start.take() {
+            None => start = Some(delimiter),
+            Some(start) => comments.push(start..from),
+        }
+    }
+    comments.extend(start.map(|start| start..content.len()));
+    comments
+}
+
+///`content` without its Obsidian comments. The line breaks in comments are kept, so the
+///lines of the rest of the content don't move.
+pub fn strip_comments(content: &str) -> Cow<'_, str> {
+    let comments = find_comments(content);
+    if comments.is_empty() {
+        return Cow::Borrowed(content);
+    }
+    let mut stripped = String::with_capacity(content.len());
+    let mut last = 0;
+    for comment in comments {
+        stripped.push_str(&content[last..comment.start]);
+        stripped.extend(content[comment.clone()].chars().filter(|c| *c == '\n'));
+        last = comment.end;
+    }
+    stripped.push_str(&content[last..]);
+    Cow::Owned(stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strip_comments;
+
+    #[test]
+    fn inline_and_block_comments() {
+        assert_eq!(strip_comments("Some %%secret%% text"), "Some  text");
+        assert_eq!(
+            strip_comments("Before\n\n%%\nHidden [[link]] #tag\n%%\nAfter"),
+            "Before\n\n\n\n\nAfter"
+        );
+        assert_eq!(strip_comments("Text %% never closed\nmore"), "Text \n");
+        assert_eq!(strip_comments("No comments"), "No comments");
+    }
+
+    #[test]
+    fn code_math_and_raw_html_are_kept() {
+        let content = "`%%code%%` and $a %% b$ and \\(c %% d\\) %%x%%\n\
+                       ```\n%% in a block %%\n```\n\
+                       %%RAW_HTML\n<b>x</b>\n%%RAW_HTML\n";
+        assert_eq!(
+            strip_comments(content),
+            content.replace(" %%x%%", " ")
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use log::debug;
+use regex::Regex;
+use slugify::slugify;
+
+lazy_static! {
+    static ref HEADING_RE: Regex =
+        Regex::new(r"^[[:blank:]]?(?P<level>#+)[[:blank:]]+(?P<title>.*)$").unwrap();
+    static ref EMPTYLINE: Regex = Regex::new(r"^[[:blank:]]*$").unwrap();
+}
+
+/// Get the level and the title of a markdown heading, if the given line is one.
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    HEADING_RE.captures(line).map(|captures| {
+        let level = captures.name("level").map_or(0, |l| l.as_str().len());
+        let title = captures.name("title").map_or("", |t| t.as_str().trim());
+        (level, title)
+    })
+}
+
+fn heading_matches(title: &str, query: &str) -> bool {
+    title.eq_ignore_ascii_case(query.trim()) || slugify!(title) == slugify!(query)
+}
+
+/// Extract the section of `content` that starts at the heading named `heading`.
+///
+/// The section includes the heading itself and runs until the next heading of the same
+/// or a higher level. Returns `None` if no such heading exists.
+pub fn extract_section(content: &str, heading: &str) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let (start, level) = lines.iter().enumerate().find_map(|(idx, line)| {
+        parse_heading(line)
+            .filter(|(_, title)| heading_matches(title, heading))
+            .map(|(level, _)| (idx, level))
+    })?;
+
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| parse_heading(line).is_some_and(|(lvl, _)| lvl <= level))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+
+    debug!("Found section {} on lines {}-{}", heading, start, end);
+    Some(lines[start..end].join("\n"))
+}
+
+/// Extract the block of `content` that is labelled with `^label`.
+///
+/// A label is either placed at the end of the last line of a block, or on its own line
+/// directly below it. The label itself is not part of the returned block.
+/// Returns `None` if the label does not occur in `content`.
+pub fn extract_block(content: &str, label: &str) -> Option<String> {
+    let marker = format!("^{}", label.trim_start_matches('^'));
+    let lines: Vec<&str> = content.lines().collect();
+
+    let label_idx = lines.iter().position(|line| {
+        let trimmed = line.trim_end();
+        trimmed.ends_with(&marker)
+            && trimmed[..trimmed.len() - marker.len()]
+                .chars()
+                .last()
+                .is_none_or(char::is_whitespace)
+    })?;
+
+    let mut block: Vec<String> = vec![];
+    let label_line = lines[label_idx].trim_end();
+    let remainder = label_line[..label_line.len() - marker.len()].trim_end();
+    if !remainder.is_empty() {
+        block.push(remainder.to_string());
+    }
+
+    // Walk back to the start of the block.
+    for line in lines[..label_idx].iter().rev() {
+        if EMPTYLINE.is_match(line) {
+            if block.is_empty() {
+                // Label on its own line, separated from its block by an empty line.
+                continue;
+            }
+            break;
+        }
+        if parse_heading(line).is_some() {
+            // Headings are blocks of their own.
+            break;
+        }
+        block.push(line.to_string());
+    }
+    block.reverse();
+    Some(block.join("\n"))
+}
+
+/// Extract the part of `content` referred to by the subtarget of an embed.
+///
+/// The subtarget is first interpreted as a heading, and then as a block label.
+pub fn extract_subtarget(content: &str, subtarget: &str) -> Option<String> {
+    extract_section(content, subtarget).or_else(|| extract_block(content, subtarget))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_block, extract_section, extract_subtarget};
+
+    const NOTE: &str = "# Title
+Intro text.
+
+## Definition
+A *set* is a collection.
+
+### Remark
+Sets can be empty.
+
+## Examples
+Some examples. ^examples
+
+A paragraph
+over two lines.
+^two-lines
+";
+
+    #[test]
+    fn section_with_subsections() {
+        assert_eq!(
+            extract_section(NOTE, "Definition").unwrap(),
+            "## Definition\nA *set* is a collection.\n\n### Remark\nSets can be empty.\n"
+        );
+    }
+
+    #[test]
+    fn section_case_insensitive() {
+        assert_eq!(
+            extract_section(NOTE, "remark").unwrap(),
+            "### Remark\nSets can be empty.\n"
+        );
+    }
+
+    #[test]
+    fn missing_section() {
+        assert!(extract_section(NOTE, "Nonexistent").is_none());
+    }
+
+    #[test]
+    fn block_inline_label() {
+        assert_eq!(extract_block(NOTE, "examples").unwrap(), "Some examples.");
+    }
+
+    #[test]
+    fn block_label_on_own_line() {
+        assert_eq!(
+            extract_block(NOTE, "two-lines").unwrap(),
+            "A paragraph\nover two lines."
+        );
+    }
+
+    #[test]
+    fn label_is_not_a_prefix_match() {
+        assert!(extract_block("Some text ^examples-2", "examples").is_none());
+        assert!(extract_block("Some text^examples", "examples").is_none());
+    }
+
+    #[test]
+    fn subtarget_prefers_section() {
+        assert_eq!(
+            extract_subtarget(NOTE, "examples").unwrap(),
+            "## Examples\nSome examples. ^examples\n\nA paragraph\nover two lines.\n^two-lines"
+        );
+    }
+
+    #[test]
+    fn subtarget_falls_back_to_block() {
+        assert_eq!(
+            extract_subtarget(NOTE, "two-lines").unwrap(),
+            "A paragraph\nover two lines."
+        );
+    }
+}
@@ -16,7 +16,7 @@ enum HeaderState {
 }
 
 lazy_static! {
-    static ref OBS_HEADER_RE: Regex = Regex::new(r"^[[:blank:]]?#+[[:blank:]]*").unwrap();
+    static ref OBS_HEADER_RE: Regex = Regex::new(r"^[[:blank:]]?#+(?:[[:blank:]]|$)").unwrap();
     static ref EMPTYLINE: Regex = Regex::new(r"^[[:blank:]]*$").unwrap();
 }
 
@@ -1,22 +1,42 @@
-use crate::utils::placeholders::{find_pair_ids, DelimPair};
 use crate::core::html::HtmlTag;
-use log::debug;
+use std::ops::Range;
 
-///Replace highlights, given by ==<content>== by spans with the `hl` class.
-pub fn replace_obs_highlights(content: &str) -> String {
-    let delimiters = DelimPair::new("==", "==");
-    let originals: Vec<&str> = find_pair_ids(&content, &delimiters).into_iter()
-                                .map(|(start, end)| &content[start..end])
-                                .collect();
+///The `==` delimiters of highlights in `content`. Every other delimiter opens a highlight,
+///which is rendered as a span with the `highlight` class.
+pub fn find_highlight_delimiters(content: &str) -> Vec<Range<usize>> {
+    content
+        .match_indices("==")
+        .map(|(start, delim)| start..start + delim.len())
+        .collect()
+}
+
+///The html that starts a highlight.
+pub fn open_highlight() -> String {
+    let span = HtmlTag::span().with_class("highlight").wrap("");
+    span.trim_end_matches("</span>").to_string()
+}
+
+///The html that ends a highlight.
+pub fn close_highlight() -> &'static str {
+    "</span>"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{close_highlight, find_highlight_delimiters, open_highlight};
+    use crate::core::html::HtmlTag;
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(find_highlight_delimiters("a ==b== c"), vec![2..4, 5..7]);
+        assert_eq!(find_highlight_delimiters("a ==="), vec![2..4]);
+    }
 
-    let mut content = content.to_owned(); 
-    for original in originals {
-        debug!("Handling match {}", &original);
-        let internal = original.strip_prefix("==").expect("Found highlighted substring that does not start with `==`") 
-                               .strip_suffix("==").expect("Found highlighted substring that does not end with `==`");
-        debug!("Replacing      {}", &internal);
-        debug!("with           {}", &HtmlTag::span().with_class("highlight").wrap(&internal));
-        content = content.replace(&original, &HtmlTag::span().with_class("highlight").wrap(&internal));
+    #[test]
+    fn highlight_span() {
+        assert_eq!(
+            format!("{}x{}", open_highlight(), close_highlight()),
+            HtmlTag::span().with_class("highlight").wrap("x")
+        );
     }
-    content 
 }
@@ -1,10 +1,11 @@
 use regex::Regex;
 use log::debug;
+use std::ops::Range;
 
 lazy_static! {
     static ref OBSIDIAN_LABEL_RE: Regex =
-        Regex::new(r"^[\s]*(?P<start>\^)(?P<label>[aA-zZ\d-]*)\n").unwrap();
-            //beginning of line, or space
+        Regex::new(r"(?:^|\s)(?P<start>\^)(?P<label>[a-zA-Z\d-]+)\s*$").unwrap();
+            //end of a block, after a space
 }
 
 
@@ -14,14 +15,33 @@ pub struct BlockLabel {
     pub source: String
 }
 
-///Find labels for blockrefs: `^...`
-pub fn find_labels(content: &str) -> Vec<BlockLabel> {
-    OBSIDIAN_LABEL_RE.captures_iter(content)
-        .map(|capture| BlockLabel { 
-            label: String::from(&capture["label"]),
-            source: format!("{}{}", &capture["start"], &capture["label"])
-        })
-        .map(|l| {debug!("Found label {}", l.label); l})
-        .collect::<Vec<BlockLabel>>()
+///Find the label for blockrefs at the end of a block: `^...`, with the range of its source.
+pub fn trailing_label(content: &str) -> Option<(Range<usize>, BlockLabel)> {
+    let capture = OBSIDIAN_LABEL_RE.captures(content)?;
+    let source = capture.name("start")?.start()..capture.name("label")?.end();
+    let label = BlockLabel {
+        label: String::from(&capture["label"]),
+        source: content[source.clone()].to_string(),
+    };
+    debug!("Found label {}", label.label);
+    Some((source, label))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::trailing_label;
+
+    #[test]
+    fn label_at_end() {
+        let (range, label) = trailing_label("A lemma. ^lemma-2\n").unwrap();
+        assert_eq!(range, 9..17);
+        assert_eq!(label.label, "lemma-2");
+        assert_eq!(trailing_label("^only").unwrap().1.source, "^only");
+    }
+
+    #[test]
+    fn label_in_text() {
+        assert_eq!(trailing_label("A ^label in the middle"), None);
+        assert_eq!(trailing_label("x^2"), None);
+    }
+}
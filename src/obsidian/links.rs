@@ -9,35 +9,63 @@ lazy_static! {
     static ref STANDARD_URL_RE: Regex = Regex::new(r"(https:\/\/|http:\/\/|www\.)(\S*)").unwrap();
 }
 use crate::components::link::Link;
+use std::ops::Range;
 
-pub fn find_markdown_links(content: &str) -> Vec<Link> {
-    MD_LINK_RE
+///The wikilinks and embeds in `content`, with the ranges of their source.
+pub fn locate_obsidian_links(content: &str) -> Vec<(Range<usize>, Link)> {
+    OBSIDIAN_LINK_RE
         .captures_iter(content)
-        .map(|capture| {
-            Link::from_md_link(
-                capture.get(0).map_or("", |s| s.as_str()),
-                &capture["target"],
-                Some(&capture["alias"]),
-                !capture["is_attachment"].is_empty(),
-            )
+        .filter_map(|capture| {
+            let link =
+                Link::from_obsidian_link(&capture["link"], !capture["is_attachment"].is_empty())
+                    .ok()?;
+            Some((capture.get(0)?.range(), link))
         })
         .collect()
 }
 
 pub fn find_obsidian_links(content: &str) -> Vec<Link> {
-    OBSIDIAN_LINK_RE
+    locate_obsidian_links(content)
+        .into_iter()
+        .map(|(_, link)| link)
+        .collect()
+}
+
+fn markdown_link(capture: &regex::Captures) -> Link {
+    Link::from_md_link(
+        capture.get(0).map_or("", |s| s.as_str()),
+        &capture["target"],
+        Some(&capture["alias"]),
+        !capture["is_attachment"].is_empty(),
+    )
+}
+
+///The markdown links in `content`, with the ranges of their source.
+pub fn locate_markdown_links(content: &str) -> Vec<(Range<usize>, Link)> {
+    MD_LINK_RE
         .captures_iter(content)
-        .map(|capture| {
-            Link::from_obsidian_link(&capture["link"], !capture["is_attachment"].is_empty())
-                .unwrap()
-        })
+        .filter_map(|capture| Some((capture.get(0)?.range(), markdown_link(&capture))))
         .collect()
 }
 
-pub fn find_raw_links(content: &str) -> Vec<Link> {
+///The link written as `source`, if all of it is a markdown link: `[alias](target)`.
+pub fn parse_markdown_link(source: &str) -> Option<Link> {
+    MD_LINK_RE
+        .captures(source)
+        .filter(|capture| capture.get(0).is_some_and(|m| m.range() == (0..source.len())))
+        .map(|capture| markdown_link(&capture))
+}
+
+///The urls in `content` that are not part of a link, with their ranges.
+pub fn locate_raw_links(content: &str) -> Vec<(Range<usize>, Link)> {
     let links = STANDARD_URL_RE
         .captures_iter(content)
-        .map(|capture| Link::from_raw(&capture[2], &capture[0]))
+        .filter_map(|capture| {
+            Some((
+                capture.get(0)?.range(),
+                Link::from_raw(&capture[2], &capture[0]),
+            ))
+        })
         .collect();
     debug!("Found links {:?}", links);
     links
@@ -47,7 +75,7 @@ pub fn find_raw_links(content: &str) -> Vec<Link> {
 mod tests {
     use crate::components::link::{FileType, Link, LinkType};
 
-    use super::find_obsidian_links;
+    use super::{find_obsidian_links, parse_markdown_link};
 
     fn test_multiple_links(content: &str, links: Vec<&str>) {
         let found_links = find_obsidian_links(content);
@@ -107,5 +135,13 @@ mod tests {
             LinkType::Attachment(FileType::Image)
         );
     }
+
+    #[test]
+    fn test_whole_markdown_link() {
+        let link = parse_markdown_link("[alias](note.md#Heading)").unwrap();
+        assert_eq!(link.alias.as_deref(), Some("alias"));
+        assert_eq!(link.subtarget.as_deref(), Some("Heading"));
+        assert!(parse_markdown_link("[a](x(y))").is_none());
+    }
     // More tests...
 }
@@ -1,5 +1,7 @@
 pub mod tags;
 pub mod admonitions;
+pub mod comments;
+pub mod embeds;
 pub mod headers;
 pub mod highlights;
 pub mod labels;
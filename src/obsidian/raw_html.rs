@@ -1,64 +1,65 @@
-use crate::core::html::wrap_html_raw;
-use crate::core::sanitization::Sanitization;
+use std::ops::Range;
 
-pub struct RawHTMLParser {
-    state: RawHTMLParseState,
-}
-
-pub enum ParseOutput {
-    Placeholder {
-        replacement: String,
-        placeholder: Option<Sanitization>,
-    },
-    None,
-}
+const RAW_HTML_MARKER: &str = "%%RAW_HTML";
 
-enum RawHTMLParseState {
-    Idle,
-    Body,
+///Html between two `%%RAW_HTML` lines, which is copied to the output as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawHtmlBlock {
+    ///The range of the block in the content, including the marker lines.
+    pub range: Range<usize>,
+    ///The lines between the markers.
+    pub html: String,
 }
 
-impl RawHTMLParser {
-    pub fn new() -> Self {
-        RawHTMLParser {
-            state: RawHTMLParseState::Idle,
-        }
-    }
-
-    pub fn process_line(&mut self, line: &str) -> ParseOutput {
-        let trimmed_line = line.trim_start();
-        match self.state {
-            RawHTMLParseState::Idle => {
-                if trimmed_line == "%%RAW_HTML" {
-                    self.state = RawHTMLParseState::Body;
-                    let replacement = "".to_string();
-                    let sanitization = Sanitization::new(line, replacement, false);
-                    ParseOutput::Placeholder {
-                        replacement: sanitization.get_placeholder(),
-                        placeholder: Some(sanitization),
-                    }
-                } else {
-                    ParseOutput::None
-                }
+///Find the raw html blocks in `content`. A block that is not closed runs until the end of
+///the content.
+pub fn find_raw_html_blocks(content: &str) -> Vec<RawHtmlBlock> {
+    let mut blocks = vec![];
+    let mut current: Option<RawHtmlBlock> = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let is_marker = line.trim() == RAW_HTML_MARKER;
+        match current.as_mut() {
+            None if is_marker => {
+                current = Some(RawHtmlBlock {
+                    range: offset..offset + line.len(),
+                    html: String::new(),
+                })
             }
-            RawHTMLParseState::Body => {
-                if trimmed_line == "%%RAW_HTML" {
-                    self.state = RawHTMLParseState::Idle;
-                    let replacement = "".to_string();
-                    let sanitization = Sanitization::new(line, replacement, false);
-                    return ParseOutput::Placeholder {
-                        replacement: sanitization.get_placeholder(),
-                        placeholder: Some(sanitization),
-                    };
+            None => {}
+            Some(block) => {
+                block.range.end = offset + line.len();
+                if is_marker {
+                    blocks.extend(current.take());
+                } else {
+                    block.html.push_str(line.trim_end_matches(['\r', '\n']));
+                    block.html.push('\n');
                 }
-                let sanitization = Sanitization::new(line, line, false);
-                let replacement = sanitization.get_placeholder();
-                let placeholder = Some(sanitization);
-                return ParseOutput::Placeholder {
-                    replacement,
-                    placeholder,
-                };
             }
         }
+        offset += line.len();
+    }
+    blocks.extend(current);
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_raw_html_blocks;
+
+    #[test]
+    fn raw_block() {
+        let content = "text\n%%RAW_HTML\n<b>x</b>\n  %%RAW_HTML\nmore";
+        let blocks = find_raw_html_blocks(content);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].html, "<b>x</b>\n");
+        assert_eq!(&content[blocks[0].range.clone()], "%%RAW_HTML\n<b>x</b>\n  %%RAW_HTML\n");
+    }
+
+    #[test]
+    fn unclosed_block() {
+        let blocks = find_raw_html_blocks("%%RAW_HTML\n<i>");
+        assert_eq!(blocks[0].html, "<i>\n");
+        assert_eq!(blocks[0].range, 0..14);
     }
 }
@@ -1,4 +1,5 @@
 use regex::Regex;
+use std::ops::Range;
 
 lazy_static! {
     static ref OBSIDIAN_TAG_RE: Regex =
@@ -12,37 +13,47 @@ pub struct Tag {
     pub source: String,
 }
 
-pub fn find_tags(content: &str) -> Vec<Tag> {
-    let mut tags = OBSIDIAN_TAG_RE
+///The tags in `content`, with the ranges of their source. A `#` at the very start of `content`
+///only starts a tag if `at_boundary`, i.e. if `content` follows whitespace or starts a block.
+pub fn locate_tags(content: &str, at_boundary: bool) -> Vec<(Range<usize>, Tag)> {
+    OBSIDIAN_TAG_RE
         .captures_iter(content)
-        .filter(|capture| {
-            capture.get(0).map_or(false, |c| {
-                content[c.start()..c.end()]
-                    .chars()
-                    .any(|c| c.is_alphabetic())
-            })
+        .filter_map(|capture| {
+            let name = capture.name("tagname")?;
+            let source = (name.start() - 1)..name.end();
+            if source.start == 0 && !at_boundary {
+                return None;
+            }
+            if !name.as_str().chars().any(|c| c.is_alphabetic()) {
+                return None;
+            }
+            let tag = Tag {
+                tag_path: name.as_str().to_lowercase(),
+                source: content[source.clone()].to_string(),
+            };
+            Some((source, tag))
         })
-        .map(|capture| Tag {
-            tag_path: String::from(&capture["tagname"]).to_lowercase(),
-            source: capture
-                .get(0)
-                .map_or("", |m| m.as_str().trim_start())
-                .to_string(),
-        })
-        .collect::<Vec<Tag>>();
-    // Sort by length (longest first) to fix issues pertaining to tags beginning with the same word.
-    sort_by_length(&mut tags);
-    tags
+        .collect()
 }
 
-fn sort_by_length(elements: &mut Vec<Tag>) {
-    elements.sort_by(|b, a| a.tag_path.len().cmp(&b.tag_path.len()));
+///Sort tags by length, longest first.
+pub fn sort_by_length(elements: &mut [Tag]) {
+    elements.sort_by_key(|tag| std::cmp::Reverse(tag.tag_path.len()));
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::{find_tags, Tag};
+    use super::{locate_tags, sort_by_length, Tag};
+
+    fn find_tags(content: &str) -> Vec<Tag> {
+        let mut tags = locate_tags(content, true)
+            .into_iter()
+            .map(|(_, tag)| tag)
+            .collect::<Vec<Tag>>();
+        sort_by_length(&mut tags);
+        tags
+    }
 
     fn test_multiple_tags(content: &str, tags: Vec<Tag>) {
         let found_tags = find_tags(content);
@@ -133,4 +144,11 @@ mod tests {
             }],
         );
     }
+
+    #[test]
+    fn tag_at_start_needs_boundary() {
+        assert_eq!(locate_tags("#tag", false), vec![]);
+        assert_eq!(locate_tags("x #tag", false)[0].0, 2..6);
+        assert_eq!(locate_tags("#tag", true)[0].0, 0..4);
+    }
 }
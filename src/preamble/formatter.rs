@@ -1,13 +1,42 @@
 use super::parser::{parse_preamble, Declarator};
-use log::warn;
 
+/// Macros with their expansion, like `\RR` and `\mathbb{R}`.
+pub type Macros = Vec<(String, String)>;
+
+/// The commands of the preamble as macros, by name, like KaTeX takes them. Optional arguments
+/// are left out, since KaTeX macros don't have them. Also returns the errors of the commands
+/// that could not be parsed, with the line on which they start.
+pub fn preamble_macros(preamble: &str) -> (Macros, Vec<(usize, String)>) {
+    let mut macros = vec![];
+    let mut errors = vec![];
+    for (line, command) in parse_preamble(preamble) {
+        match command {
+            Ok(cmd) => {
+                let expansion = match cmd.declarator {
+                    Declarator::NewCommand | Declarator::RenewCommand => cmd.definition,
+                    Declarator::DeclareMathOperator(star) => format!(
+                        "\\operatorname{}{{{}}}",
+                        if star { "*" } else { "" },
+                        cmd.definition
+                    ),
+                };
+                macros.push((cmd.cmd, expansion));
+            }
+            Err(e) => errors.push((line, e.to_string())),
+        }
+    }
+    (macros, errors)
+}
 
 pub trait FormatPreamble {
-    fn preamble_to_html(&self, preamble: &str) -> String {
+    /// Convert the commands of the preamble. Also returns the errors of the commands that
+    /// could not be parsed, with the line on which they start.
+    fn preamble_to_html(&self, preamble: &str) -> (String, Vec<(usize, String)>) {
         let mut output: Vec<String> = Vec::with_capacity(preamble.len());
-        for command in parse_preamble(preamble) {
-            if let Ok(cmd) = command {
-                match cmd.declarator {
+        let mut errors = vec![];
+        for (line, command) in parse_preamble(preamble) {
+            match command {
+                Ok(cmd) => match cmd.declarator {
                     Declarator::NewCommand | Declarator::RenewCommand => {
                         output.push(self.fmt_newcommand(
                             &cmd.cmd,
@@ -23,12 +52,11 @@ pub trait FormatPreamble {
                             starred,
                         ));
                     }
-                } 
-            } else {
-                warn!("could not parse command: {}.", command.unwrap_err());
+                },
+                Err(e) => errors.push((line, e.to_string())),
             }
         }
-        output.join(",\n").replace("\\", "\\\\")
+        (output.join(",\n").replace("\\", "\\\\"), errors)
     }
 
     fn fmt_declaremathoperator(&self, name: &str, operator: &str, star: bool) -> String;
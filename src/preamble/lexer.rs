@@ -6,6 +6,10 @@ pub struct Lexer<T: Iterator<Item = char>> {
     buffer: String,
     text: T,
     state: LexerState,
+    /// The line of the next char, counting from 1.
+    line: usize,
+    /// The line on which the last token ended.
+    token_line: usize,
 }
 
 #[derive(Debug)]
@@ -54,9 +58,16 @@ impl<T: Iterator<Item = char>> Lexer<T> {
             buffer: String::new(),
             text,
             state: LexerState::Normal,
+            line: 1,
+            token_line: 1,
         }
     }
 
+    /// The line on which the last token ended, counting from 1.
+    pub fn token_line(&self) -> usize {
+        self.token_line
+    }
+
     fn flush<'a>(&'a mut self) -> Option<Token> {
         let result = match self.buffer.as_str().trim() {
             r"\newcommand" => Some(Token::Newcommand),
@@ -142,10 +153,16 @@ impl<T: Iterator<Item = char>> Iterator for Lexer<T> {
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(next_char) = self.text.next() {
             let parse_result = self.parse_char(next_char);
+            // A token ends at the char before `next_char`, which is on the same line.
+            self.token_line = self.line;
+            if next_char == '\n' {
+                self.line += 1;
+            }
             if parse_result.is_some() {
                 return parse_result;
             }
         }
+        self.token_line = self.line;
         return self.flush();
     }
 }
@@ -3,13 +3,15 @@ use super::lexer::{SyntaxErr, Token, Lexer};
 
 type ParseResult = Result<TexCommand, SyntaxErr>;
 
-struct PreambleParser<T: Iterator<Item = Token>> {
-    lexer: T,
+struct PreambleParser<C: Iterator<Item = char>> {
+    lexer: Lexer<C>,
+    /// The line on which the last command started.
+    line: usize,
 }
 
-impl<T: Iterator<Item = Token>> PreambleParser<T> {
-    pub fn new(lexer: T) -> Self {
-        Self { lexer }
+impl<C: Iterator<Item = char>> PreambleParser<C> {
+    pub fn new(lexer: Lexer<C>) -> Self {
+        Self { lexer, line: 1 }
     }
 }
 
@@ -283,7 +285,7 @@ impl Transition for CommandParseState {
     }
 }
 
-impl<T: Iterator<Item = Token>> PreambleParser<T> {
+impl<C: Iterator<Item = char>> PreambleParser<C> {
     fn parse_mathoperator(&mut self, token: &Token) -> ParseResult {
         let mut parser_state = CommandParseState::Start;
         let mut command_name = "".to_string();
@@ -358,11 +360,12 @@ impl<T: Iterator<Item = Token>> PreambleParser<T> {
     }
 }
 
-impl<'a, T: Iterator<Item = Token>> Iterator for PreambleParser<T> {
+impl<C: Iterator<Item = char>> Iterator for PreambleParser<C> {
     type Item = ParseResult;
 
     fn next(&mut self) -> Option<ParseResult> {
         while let Some(token) = self.lexer.next() {
+            self.line = self.lexer.token_line();
             match token {
                 Token::DeclareMathOperator(_) => {
                     return Some(self.parse_mathoperator(&token));
@@ -377,9 +380,16 @@ impl<'a, T: Iterator<Item = Token>> Iterator for PreambleParser<T> {
     }
 }
 
-pub fn parse_preamble<'a>(preamble: &'a str) -> impl Iterator<Item=ParseResult> + 'a {
-    let lexer = Lexer::new(preamble.chars());
-    PreambleParser::new(lexer)
+/// Parse the commands of the preamble, with the line on which each of them starts, counting
+/// from 1.
+pub fn parse_preamble<'a>(
+    preamble: &'a str,
+) -> impl Iterator<Item = (usize, ParseResult)> + 'a {
+    let mut parser = PreambleParser::new(Lexer::new(preamble.chars()));
+    std::iter::from_fn(move || {
+        let result = parser.next()?;
+        Some((parser.line, result))
+    })
 }
 
 #[cfg(test)]
@@ -387,7 +397,7 @@ mod tests {
     use super::*;
 
     fn test_valid(input_string: &str, expected: &[TexCommand]) {
-        for (result, correct) in parse_preamble(input_string).zip(expected) {
+        for ((_, result), correct) in parse_preamble(input_string).zip(expected) {
             if let Ok(res) = result { 
                 assert_eq!(&res, correct);
             } else {
@@ -397,7 +407,7 @@ mod tests {
     }
 
     fn test_invalid(input_string: &str) {
-        for result in parse_preamble(input_string) {
+        for (_, result) in parse_preamble(input_string) {
             assert!(result.is_err())
         }
     }
@@ -516,4 +526,17 @@ r"\newcommand{\d}{\mathrm{d}}							  % differential
         ])
     }
 
+    #[test]
+    fn lines_of_commands() {
+        let input = r"% A comment
+\newcommand{\d}{\mathrm{d}}
+
+\newcommand{\broken}[x]{#1}
+\DeclareMathOperator{\argmin}{arg\,min}
+";
+        let lines: Vec<(usize, bool)> = parse_preamble(input)
+            .map(|(line, result)| (line, result.is_ok()))
+            .collect();
+        assert_eq!(lines, vec![(2, true), (4, false), (5, true)]);
+    }
 }
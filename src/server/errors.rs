@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ServeError {
+    #[error("Could not start the server on `{address}`: {reason}")]
+    Bind { address: String, reason: String },
+}
@@ -0,0 +1,273 @@
+use super::errors::ServeError;
+use super::reload::{stream_events, Reloader, RELOAD_ENDPOINT};
+use crate::exporting::load_static::LIVE_RELOAD_SCRIPT;
+use crate::utils::filesys::{compressed_path, Codec};
+use crate::utils::utils::read_file_to_str;
+use log::{debug, info, warn};
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+/// Local HTTP server for previewing the output directory while editing notes.
+///
+/// Html pages get a small script injected that reloads the page whenever the [Reloader]
+/// is notified.
+pub struct DevServer {
+    output_dir: PathBuf,
+    root_path: String,
+    reloader: Reloader,
+}
+
+///Decode the percent-encoded characters in a url path.
+fn decode_url(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            if let Some(byte) = url
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                idx += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        "ttf" => "font/ttf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+///Find a pre-compressed copy of the file, like the ones of the search index, in an encoding
+///the client accepts.
+fn precompressed(path: &Path, accept_encoding: &str) -> Option<(PathBuf, &'static str)> {
+    let accepted: Vec<&str> = accept_encoding
+        .split(',')
+        .map(|encoding| encoding.split(';').next().unwrap_or("").trim())
+        .collect();
+    [Codec::Brotli, Codec::Gzip]
+        .into_iter()
+        .filter(|codec| accepted.contains(&codec.encoding()))
+        .map(|codec| (compressed_path(path, codec), codec.encoding()))
+        .find(|(compressed, _)| compressed.is_file())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("Invalid header.")
+}
+
+///Make sure the root path starts and ends with a slash.
+fn normalize_root(root_path: Option<&str>) -> String {
+    let trimmed = root_path.unwrap_or("/").trim_matches('/');
+    if trimmed.is_empty() {
+        "/".to_string()
+    } else {
+        format!("/{}/", trimmed)
+    }
+}
+
+impl DevServer {
+    pub fn new<P: Into<PathBuf>>(
+        output_dir: P,
+        root_path: Option<&str>,
+        reloader: Reloader,
+    ) -> Self {
+        DevServer {
+            output_dir: output_dir.into(),
+            root_path: normalize_root(root_path),
+            reloader,
+        }
+    }
+
+    /// Address at which the website is available, given the port of the server.
+    pub fn url(&self, port: u16) -> String {
+        format!("http://localhost:{}{}", port, self.root_path)
+    }
+
+    /// Start serving the output directory on the given port in a background thread.
+    pub fn start(self, port: u16) -> Result<thread::JoinHandle<()>, ServeError> {
+        let address = format!("127.0.0.1:{}", port);
+        let server = Server::http(&address).map_err(|e| ServeError::Bind {
+            address: address.clone(),
+            reason: e.to_string(),
+        })?;
+        info!("Serving {:?} at {}", self.output_dir, self.url(port));
+        Ok(thread::spawn(move || {
+            for request in server.incoming_requests() {
+                self.handle(request);
+            }
+        }))
+    }
+
+    ///Find the file in the output directory that corresponds to the requested url.
+    fn resolve(&self, url: &str) -> Option<PathBuf> {
+        let path = decode_url(url.split(['?', '#']).next().unwrap_or(""));
+        let relative = Path::new(path.strip_prefix(&self.root_path)?);
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            // Don't serve anything outside of the output directory.
+            return None;
+        }
+
+        let mut candidate = self.output_dir.join(relative);
+        if candidate.is_dir() {
+            candidate.push("index.html");
+        } else if !candidate.exists() && candidate.extension().is_none() {
+            candidate.set_extension("html");
+        }
+        candidate.is_file().then_some(candidate)
+    }
+
+    fn respond_with_page(&self, request: Request, path: &Path) -> std::io::Result<()> {
+        let mut page = read_file_to_str(path)?;
+        let script = format!(
+            "<script>{}</script>",
+            LIVE_RELOAD_SCRIPT.replace("{{RELOAD_ENDPOINT}}", RELOAD_ENDPOINT)
+        );
+        match page.rfind("</body>") {
+            Some(idx) => page.insert_str(idx, &script),
+            None => page.push_str(&script),
+        }
+        request.respond(
+            Response::from_string(page).with_header(header("Content-Type", content_type(path))),
+        )
+    }
+
+    fn respond_with_file(&self, request: Request, path: &Path) -> std::io::Result<()> {
+        let accepted = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Accept-Encoding"))
+            .map(|h| h.value.to_string())
+            .unwrap_or_default();
+        let response = match precompressed(path, &accepted) {
+            Some((compressed, encoding)) => Response::from_file(File::open(compressed)?)
+                .with_header(header("Content-Encoding", encoding)),
+            None => Response::from_file(File::open(path)?),
+        };
+        request.respond(response.with_header(header("Content-Type", content_type(path))))
+    }
+
+    fn handle(&self, request: Request) {
+        let url = request.url().to_string();
+        debug!("{} {}", request.method(), url);
+
+        if url == RELOAD_ENDPOINT {
+            let events = self.reloader.subscribe();
+            let writer = request.into_writer();
+            thread::spawn(move || stream_events(writer, events));
+            return;
+        }
+
+        if url == "/" && self.root_path != "/" {
+            let redirect =
+                Response::empty(StatusCode(302)).with_header(header("Location", &self.root_path));
+            if let Err(e) = request.respond(redirect) {
+                warn!("Could not respond to request {}. Got error {}", url, e);
+            }
+            return;
+        }
+
+        let result = match self.resolve(&url) {
+            Some(path) if content_type(&path).starts_with("text/html") => {
+                self.respond_with_page(request, &path)
+            }
+            Some(path) => self.respond_with_file(request, &path),
+            None => {
+                debug!("No file found for {}", url);
+                request.respond(Response::from_string("404 Not Found").with_status_code(404))
+            }
+        };
+        if let Err(e) = result {
+            warn!("Could not respond to request {}. Got error {}", url, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_url, normalize_root, precompressed, DevServer};
+    use crate::server::reload::Reloader;
+    use std::fs::{create_dir, File};
+    use tempfile::tempdir;
+
+    #[test]
+    fn decode_percent_encoding() {
+        assert_eq!(decode_url("/my%20note.html"), "/my note.html");
+        assert_eq!(decode_url("/100%"), "/100%");
+    }
+
+    #[test]
+    fn root_is_wrapped_in_slashes() {
+        assert_eq!(normalize_root(None), "/");
+        assert_eq!(normalize_root(Some("/")), "/");
+        assert_eq!(normalize_root(Some("notes")), "/notes/");
+    }
+
+    #[test]
+    fn resolve_respects_root_path() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("sub")).unwrap();
+        File::create(dir.path().join("index.html")).unwrap();
+        File::create(dir.path().join("sub").join("note.html")).unwrap();
+
+        let server = DevServer::new(dir.path(), Some("/notes"), Reloader::new());
+        assert_eq!(
+            server.resolve("/notes/"),
+            Some(dir.path().join("index.html"))
+        );
+        assert_eq!(
+            server.resolve("/notes/sub/note?x=1"),
+            Some(dir.path().join("sub").join("note.html"))
+        );
+        assert_eq!(server.resolve("/sub/note.html"), None);
+        assert_eq!(server.resolve("/notes/../index.html"), None);
+    }
+
+    #[test]
+    fn serves_precompressed_copies() {
+        let dir = tempdir().unwrap();
+        let index = dir.path().join("index.json");
+        File::create(&index).unwrap();
+        File::create(dir.path().join("index.json.gz")).unwrap();
+
+        assert_eq!(
+            precompressed(&index, "gzip, deflate, br"),
+            Some((dir.path().join("index.json.gz"), "gzip"))
+        );
+        assert_eq!(precompressed(&index, "br"), None);
+        assert_eq!(precompressed(&index, ""), None);
+    }
+}
@@ -0,0 +1,5 @@
+mod errors;
+pub mod http;
+pub mod reload;
+
+pub use errors::ServeError;
@@ -0,0 +1,86 @@
+use log::{debug, info};
+use std::io::Write;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Endpoint that browsers connect to, to get notified of recompiled notes.
+pub const RELOAD_ENDPOINT: &str = "/__oxidian/reload";
+
+/// Keeps track of the browser tabs that are listening for reload events.
+#[derive(Clone, Default)]
+pub struct Reloader {
+    clients: Arc<Mutex<Vec<Sender<()>>>>,
+}
+
+impl Reloader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new listener. The returned receiver gets a message for every reload.
+    pub fn subscribe(&self) -> Receiver<()> {
+        let (tx, rx) = channel();
+        self.clients.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Tell all the connected browser tabs to reload the page.
+    pub fn notify(&self) {
+        let mut clients = self.clients.lock().unwrap();
+        // Drop the clients that have disconnected in the meantime.
+        clients.retain(|client| client.send(()).is_ok());
+        info!("Sent reload signal to {} page(s).", clients.len());
+    }
+}
+
+/// Keep an open server-sent event stream on `writer`, and push a reload event for every
+/// message on `events`. Returns once the client has disconnected.
+pub fn stream_events<W: Write>(mut writer: W, events: Receiver<()>) {
+    let header = "HTTP/1.1 200 OK\r\n\
+                  Content-Type: text/event-stream\r\n\
+                  Cache-Control: no-cache\r\n\
+                  Connection: keep-alive\r\n\r\n\
+                  retry: 1000\n\n";
+    if writer
+        .write_all(header.as_bytes())
+        .and_then(|_| writer.flush())
+        .is_err()
+    {
+        return;
+    }
+    for _ in events {
+        if writer
+            .write_all(b"data: reload\n\n")
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            debug!("Reload listener disconnected.");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reloader;
+
+    #[test]
+    fn notify_reaches_all_subscribers() {
+        let reloader = Reloader::new();
+        let first = reloader.subscribe();
+        let second = reloader.subscribe();
+        reloader.notify();
+        assert!(first.try_recv().is_ok());
+        assert!(second.try_recv().is_ok());
+    }
+
+    #[test]
+    fn disconnected_subscribers_are_dropped() {
+        let reloader = Reloader::new();
+        drop(reloader.subscribe());
+        let alive = reloader.subscribe();
+        reloader.notify();
+        assert!(alive.try_recv().is_ok());
+        assert_eq!(reloader.clients.lock().unwrap().len(), 1);
+    }
+}
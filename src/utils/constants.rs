@@ -1,9 +1,16 @@
 pub const INDEX_FILE: &str = "index.md";
 
-pub const OBS_COMMENTS: &str = "%%%%";
+/// Delimiter of Obsidian comments, `%% like this %%`.
+pub const OBS_COMMENTS: &str = "%%";
 
 pub const TAG_DIR: &str = "tags";
 
+/// File in the output directory that records the inputs of the previous build.
+pub const MANIFEST_FILE: &str = ".oxidian_manifest.json";
+
+/// Maximum number of nested embeds (`![[note]]`) that are rendered.
+pub const MAX_EMBED_DEPTH: usize = 8;
+
 pub const NOTE_EXT: [&str;2] = [
     "md",
     "html",
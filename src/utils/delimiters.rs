@@ -1,7 +1,6 @@
 //use super::errors::MathFindError;
 
 use std::cmp;
-use crate::core::sanitization::Sanitization;
 use log::debug;
 
 
@@ -13,7 +12,6 @@ enum MathState {
 pub struct DelimPair {
     pub open: String,
     pub close: String,
-    pub before_md: bool,
 }
 
 impl DelimPair {
@@ -21,15 +19,6 @@ impl DelimPair {
         DelimPair {
             open: String::from(open),
             close: String::from(close),
-            before_md: true
-        }
-    }
-
-    pub fn new_after_md(open: &str, close: &str) -> Self {
-        DelimPair {
-            open: String::from(open),
-            close: String::from(close),
-            before_md: false
         }
     }
 }
@@ -41,35 +30,15 @@ fn get_search_pattern<'a>(state: &MathState, pair: &'a DelimPair) -> &'a str {
     }
 }
 
-
-fn get_placeholders_after<T: Iterator<Item=(usize, usize)>>(content: &str, ranges: T) -> Vec<Sanitization> {
-    ranges.map(
-        |(start, end)| Sanitization::after_md(&content[start..end]) 
-    ).collect()
-}
-
-fn get_placeholders_before<T: Iterator<Item=(usize, usize)>>(content: &str, ranges: T) -> Vec<Sanitization> {
-    ranges.map(
-        |(start, end)| Sanitization::before_md(&content[start..end]) 
-    ).collect()
-}
-
-fn generate_placeholders(content: &str, delim: &DelimPair) -> Vec<Sanitization> {
-    if delim.before_md {
-        get_placeholders_before(&content, find_pair_ids(&content, delim).into_iter())
-    } else {
-    get_placeholders_after(&content, find_pair_ids(&content, delim).into_iter())
-    }
-}
-
-
+///The ranges of the pairs of delimiters in `content`, like math between `$`s, including the
+///delimiters.
 pub fn find_pair_ids(content: &str, delim: &DelimPair) -> Vec<(usize, usize)> {
     let mut ranges = vec![];
     let mut state = MathState::ExpectOpen;
     let mut curr_start = 0;
     let mut prev_open = None;
 
-    while let Some(index) = content[curr_start..].find(get_search_pattern(&state, &delim)) { 
+    while let Some(index) = content[curr_start..].find(get_search_pattern(&state, delim)) { 
         let pattern = get_search_pattern(&state, delim);
         match state {
             MathState::ExpectOpen => {
@@ -89,9 +58,7 @@ pub fn find_pair_ids(content: &str, delim: &DelimPair) -> Vec<(usize, usize)> {
                     );
                     ranges.push((start_idx, end_idx));
                     debug!("Found slice {}", &content[start_idx..end_idx]);
-                    
-                    //let to_replace = &content[start_idx..end_idx];
-                    //result.push(Sanitization::from(to_replace));
+
                     state = MathState::ExpectOpen;
                     prev_open = None;
                 }
@@ -103,49 +70,20 @@ pub fn find_pair_ids(content: &str, delim: &DelimPair) -> Vec<(usize, usize)> {
     ranges
 }
 
-///Find code that is within pairs of given delimiters 
-///(math and code) and replace them, by placeholders. 
-///Otherwise, tags or other special symbols may be found in these places.
-pub fn disambiguate_protected(content: &str) -> (String, Vec<Sanitization>) {
-    let mut result = vec!();
-
-    let pairs: [DelimPair; 6] = [
-        DelimPair::new_after_md("$$", "$$"),
-        DelimPair::new_after_md(r"\[", r"\]"),
-        DelimPair::new_after_md("$", "$"),
-        DelimPair::new_after_md(r"\(", r"\)"),
-        DelimPair::new(r"```", r"```"),
-        DelimPair::new(r"`", r"`")
-    ];
-    let mut new_string = String::from(content);
-    for pair in pairs { 
-        let sanitize = generate_placeholders(&new_string, &pair);
-        for delimited_element in &sanitize { 
-            new_string = new_string.replace(
-                &delimited_element.original, &delimited_element.get_placeholder()
-                );
-        }
-        result.extend(sanitize);
-    }
-    //debug!("sanitized string:\n{}", new_string);
-    (new_string, result)
-}
-
 
 #[cfg(test)]
 mod tests { 
 
     use std::assert_eq;
-    use super::{DelimPair, generate_placeholders, Sanitization};
+    use super::{DelimPair, find_pair_ids};
 
     fn run_basic_test(query: &str, solution: Vec<&str>, open_delim: &str, close_delim: &str) {
-        let sanitization: Vec<Sanitization> = solution
-            .iter()
-            .map(|s| Sanitization::from(s.to_string()))
-            .collect();
         let delimiters = DelimPair::new(open_delim, close_delim);
-        let pairs = generate_placeholders(query, &delimiters);
-        assert_eq!(sanitization, pairs);
+        let pairs: Vec<&str> = find_pair_ids(query, &delimiters)
+            .into_iter()
+            .map(|(start, end)| &query[start..end])
+            .collect();
+        assert_eq!(solution, pairs);
     }
 
 
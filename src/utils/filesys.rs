@@ -1,6 +1,8 @@
 use super::errors::{FileWriteError, GetAgeError, NotePathError};
 use super::utils::prepend_slash;
 use crate::utils::constants::NOTE_EXT;
+use brotli::enc::{BrotliCompress, BrotliEncoderParams};
+use flate2::write::GzEncoder;
 use log::{debug, error, info, warn};
 use slugify::slugify;
 use std::fs::File;
@@ -68,6 +70,21 @@ pub fn resolve_path<'a>(
     ResolvedPath::Broken
 }
 
+/// Map from the (lowercase) aliases of notes to the paths of the notes, relative to the notes
+/// directory.
+pub type Aliases = std::collections::HashMap<String, PathBuf>;
+
+/// Paths of the notes that are not published, relative to the notes directory and starting
+/// with a `/`, like the targets of links.
+pub type Unpublished = std::collections::HashSet<PathBuf>;
+
+/// Find the note that has the target of a link as an alias.
+pub fn resolve_alias(path: &Path, aliases: &Aliases) -> Option<PathBuf> {
+    let name = path.to_string_lossy();
+    let name = name.strip_suffix(".md").unwrap_or(&name);
+    aliases.get(&name.to_lowercase()).cloned()
+}
+
 fn just_filename(path: &Path) -> bool {
     path.parent() == Some(Path::new(""))
 }
@@ -184,6 +201,14 @@ pub fn walk_ignoring<'a>(
     dir: &Path,
     ignoring: &'a Vec<PathBuf>,
 ) -> impl Iterator<Item = DirEntry> + 'a {
+    walk_ignoring_with_errors(dir, ignoring).filter_map(Result::ok)
+}
+
+///Like `walk_ignoring`, but the entries that can't be read are returned as errors.
+fn walk_ignoring_with_errors<'a>(
+    dir: &Path,
+    ignoring: &'a Vec<PathBuf>,
+) -> impl Iterator<Item = walkdir::Result<DirEntry>> + 'a {
     // WalkDir::new(dir)
     //     .into_iter()
     //     .filter_entry(move |entry| {
@@ -209,7 +234,6 @@ pub fn walk_ignoring<'a>(
             //
             should_include
         })
-        .filter_map(Result::ok)
 }
 
 pub fn move_file<F: AsRef<Path>, T: AsRef<Path>>(from: F, to: T) -> Result<(), io::Error> {
@@ -243,14 +267,19 @@ pub fn is_note<'a>(path: &Path, ignored: &[PathBuf]) -> bool {
     NOTE_EXT.iter().any(|ext| **ext == extension)
 }
 
+///The notes in `path`, outside the directories in `ignore`. Entries that can't be read, like
+///directories without permission, are returned as errors.
 pub fn get_all_notes_exclude<'a>(
     path: &Path,
     ignore: &'a Vec<PathBuf>,
-) -> impl Iterator<Item = io::Result<PathBuf>> + 'a {
-    let entries = walk_ignoring(path, ignore);
+) -> impl Iterator<Item = walkdir::Result<PathBuf>> + 'a {
+    let entries = walk_ignoring_with_errors(path, ignore);
 
     entries.filter_map(|entry| {
-        let path = entry.into_path();
+        let path = match entry {
+            Ok(entry) => entry.into_path(),
+            Err(e) => return Some(Err(e)),
+        };
         let extension = path.extension()?.to_str()?.to_lowercase();
 
         let contains = NOTE_EXT.iter().any(|ext| **ext == extension);
@@ -330,7 +359,7 @@ pub fn write_bin_to_file(path: &Path, content: &[u8]) -> Result<usize, FileWrite
         create_dir_if_not_exists(parent_dir)?;
         //.expect("Failed to create parent dir for search index.");
     }
-    let file = File::create(&path).expect("Could not create file!");
+    let file = File::create(path)?;
     let mut writer = std::io::BufWriter::new(file);
     let size = content.len();
     writer.write_all(content)?;
@@ -338,6 +367,78 @@ pub fn write_bin_to_file(path: &Path, content: &[u8]) -> Result<usize, FileWrite
     Ok(size)
 }
 
+///The encodings of pre-compressed copies of files, which web servers can send as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Brotli,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 2] = [Codec::Gzip, Codec::Brotli];
+
+    ///The extension added to the name of the compressed copy, e.g. `gz` for `index.json.gz`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            Codec::Brotli => "br",
+        }
+    }
+
+    ///The name of the encoding in the `Accept-Encoding` and `Content-Encoding` headers.
+    pub fn encoding(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Brotli => "br",
+        }
+    }
+
+    fn compress(self, content: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Codec::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(content)?;
+                encoder.finish()
+            }
+            Codec::Brotli => {
+                let mut compressed = Vec::new();
+                let params = BrotliEncoderParams::default();
+                BrotliCompress(&mut &content[..], &mut compressed, &params)?;
+                Ok(compressed)
+            }
+        }
+    }
+}
+
+///The path of the copy of `path` compressed with `codec`, e.g. `index.json.gz`.
+pub fn compressed_path(path: &Path, codec: Codec) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(codec.extension());
+    PathBuf::from(name)
+}
+
+///Write a copy of `content` compressed with `codec` next to `path`. Returns the compressed size.
+pub fn write_compressed(
+    path: &Path,
+    content: &[u8],
+    codec: Codec,
+) -> Result<usize, FileWriteError> {
+    write_bin_to_file(&compressed_path(path, codec), &codec.compress(content)?)
+}
+
+///Remove the compressed copies of `path`, except the one compressed with `keep`, so a web server
+///doesn't send a copy left by an earlier build.
+pub fn remove_compressed_copies(path: &Path, keep: Option<Codec>) -> Result<(), FileWriteError> {
+    for codec in Codec::ALL.into_iter().filter(|&codec| Some(codec) != keep) {
+        let copy = compressed_path(path, codec);
+        if copy.is_file() {
+            remove_file(&copy)?;
+        }
+    }
+    Ok(())
+}
+
 ///Write the given string to a file at the given path.
 pub fn write_to_file(path: &Path, content: &str) -> Result<usize, FileWriteError> {
     write_bin_to_file(path, content.as_bytes())
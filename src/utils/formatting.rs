@@ -47,10 +47,10 @@ fn render_link(link: &Link, to_html: bool) -> String {
     debug!("Link {} has type {:?}", link_text, link.link_type());
 
     match link.link_type() {
-        LinkType::Note => {
+        LinkType::Note | LinkType::Embed => {
             // Link to note should point to html page.
             let mut target_abs = slugify_path(&link.target, Some("html"))
-                .unwrap()
+                .unwrap_or_else(|_| link.target.with_extension("html"))
                 .to_string_lossy()
                 .to_string();
             //info!("Sluggified path {:?} to {:?}", link.target, target_abs);
@@ -65,7 +65,9 @@ fn render_link(link: &Link, to_html: bool) -> String {
                     target_abs.push_str(&slugify!(subtarget));
                 }
             }
-            if link.broken {
+            if link.unpublished {
+                link_text
+            } else if link.broken {
                 render_link_aux(&target_abs, &link_text, true, Some(&vec!["broken"]))
             } else {
                 render_link_aux(&target_abs, &link_text, to_html, None)
@@ -81,7 +83,8 @@ fn render_link(link: &Link, to_html: bool) -> String {
         }
         LinkType::External => render_link_aux(&link_target_str, &link_text, to_html, None),
         LinkType::Attachment(filetype) => {
-            let target_rel = slugify_path(&link.target, None).unwrap();
+            let target_rel =
+                slugify_path(&link.target, None).unwrap_or_else(|_| link.target.clone());
             let target_file = prepend_slash(&target_rel).to_string_lossy().to_string();
 
             let mut tag = match filetype {
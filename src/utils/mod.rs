@@ -3,4 +3,4 @@ pub mod constants;
 pub mod filesys; 
 pub mod formatting; 
 pub mod utils; 
-pub mod placeholders;
+pub mod delimiters;
@@ -10,9 +10,7 @@ use std::{
 use crate::utils::constants::TAG_DIR;
 use crate::components::link::Dimensions;
 use crate::core::html::HtmlTag;
-use crate::exporting::wrap_pulldown_cmark::MarkdownParser;
 use crate::obsidian::tags::Tag;
-use pulldown_cmark::html;
 
 use crate::exporting::config::ExportConfig;
 use figment::Error;
@@ -164,22 +162,19 @@ pub fn move_to(
     Ok(new_ref.join(relative_path))
 }
 
-/// Convert a given string containing Markdown content to a html representation.
-pub fn markdown_to_html(markdown: &str) -> String {
-    // Set up options and parser. Strikethroughs are not part of the CommonMark standard
-    // and we therefore must enable it explicitly.
+/// The markdown extensions used to convert the notes to html.
+pub fn markdown_options() -> pulldown_cmark::Options {
+    // Strikethroughs are not part of the CommonMark standard and we therefore must enable it
+    // explicitly.
     let mut options = pulldown_cmark::Options::empty();
     options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
     options.insert(pulldown_cmark::Options::ENABLE_TABLES);
     options.insert(pulldown_cmark::Options::ENABLE_FOOTNOTES);
     options.insert(pulldown_cmark::Options::ENABLE_TASKLISTS);
     options.insert(pulldown_cmark::Options::ENABLE_HEADING_ATTRIBUTES);
-
-    let basic_parser = pulldown_cmark::Parser::new_ext(&markdown, options);
-    let wrapper = MarkdownParser::new(basic_parser);
-    let mut html_output = String::new();
-    html::push_html(&mut html_output, wrapper);
-    html_output
+    // Math is typeset in the browser, so its content is not markdown.
+    options.insert(pulldown_cmark::Options::ENABLE_MATH);
+    options
 }
 
 /// Prepend a slash in front of a path, making it absolute.